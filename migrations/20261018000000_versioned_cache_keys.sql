@@ -0,0 +1,11 @@
+-- 翻訳先の言語とバックエンドをキャッシュのキーに含める
+--   既存の行は元のタイトルだけをキーにした形式 (schema_version = 1) として残し、
+--   ja-JP の翻訳を取得したときに新しい形式のキーで保存し直す
+ALTER TABLE rss_cache
+    ADD COLUMN target_language VARCHAR(35) NOT NULL DEFAULT '',
+    ADD COLUMN backend VARCHAR(255) NOT NULL DEFAULT '',
+    ADD COLUMN schema_version INT NOT NULL DEFAULT 1;
+
+ALTER TABLE rss_cache
+    DROP INDEX unique_raw_title,
+    ADD UNIQUE KEY unique_cache_key (raw_title, target_language, backend, schema_version);
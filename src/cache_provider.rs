@@ -1,4 +1,11 @@
+pub mod entry;
+pub mod file;
+pub mod key;
+pub mod lru;
+#[cfg(test)]
+pub mod memory;
 pub mod provider;
+pub mod redis;
 pub mod webdav;
 pub mod s3;
 pub mod sql;
@@ -0,0 +1,60 @@
+use serde::{Deserialize, Serialize};
+
+use crate::translate::html_segment::decode_entities;
+
+// キャッシュに保存する翻訳結果
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CacheEntry {
+    pub translated: String,
+    // ローカルで判定した翻訳元の言語 (判定できなかった場合はNone)
+    pub source_language: Option<String>,
+    // 翻訳したバックエンド (フォールバックした場合にどれが使われたかを残す)
+    #[serde(default)]
+    pub backend: Option<String>,
+    // 翻訳メモリの似ているタイトルから作った場合の類似度 (0.0 - 1.0)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub match_score: Option<f64>,
+}
+
+impl CacheEntry {
+    pub fn encode(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+
+    // 以前のバージョンでは翻訳後のタイトルをそのまま保存していたので、JSONとして読めない場合はそのまま使う
+    //   Google翻訳の結果は &#39; などがエスケープされたまま保存されているので戻す
+    pub fn decode(value: String) -> CacheEntry {
+        match serde_json::from_str::<CacheEntry>(&value) {
+            Ok(entry) => entry,
+            Err(_) => CacheEntry {
+                translated: decode_entities(&value),
+                source_language: None,
+                backend: None,
+                match_score: None,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn legacy_value_is_unescaped() {
+        let entry = CacheEntry::decode("Rust&#39;s &quot;async&quot; &amp; more".to_string());
+        assert_eq!(entry.translated, "Rust's \"async\" & more");
+        assert_eq!(entry.backend, None);
+    }
+
+    #[test]
+    fn json_value_is_kept_as_is() {
+        let entry = CacheEntry {
+            translated: "a &amp; b".to_string(),
+            source_language: Some("en".to_string()),
+            backend: Some("google".to_string()),
+            match_score: None,
+        };
+        assert_eq!(CacheEntry::decode(entry.encode()), entry);
+    }
+}
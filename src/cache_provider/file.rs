@@ -0,0 +1,423 @@
+use sha2::{Digest, Sha256};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use super::provider::CacheProvider;
+
+use std::{error::Error, future::Future, pin::Pin};
+
+// 書き込み途中のファイルの拡張子
+const TEMP_EXTENSION: &str = "tmp";
+
+pub struct FileCacheProviderOptions {
+    // 保存先のディレクトリ
+    pub directory: PathBuf,
+    // まとめて取得・保存する場合に同時に読み書きするファイルの数
+    pub batch_concurrency: usize,
+    // この時間より前に保存されたものはコンパクションで消す (Noneの場合は消さない)
+    pub max_age: Option<Duration>,
+}
+
+// ローカルのディレクトリに保存する
+//   ファイル名は S3, WebDav と同じくキーの sha256 で、先頭の2文字ずつでディレクトリを分ける
+//   例: <directory>/ab/cd/abcd...
+#[derive(Clone)]
+pub struct FileCacheProvider {
+    directory: PathBuf,
+    batch_concurrency: usize,
+    max_age: Option<Duration>,
+    // 一時ファイルの名前が重ならないようにするための通し番号
+    temp_count: Arc<AtomicU64>,
+}
+
+// コンパクションの結果
+#[derive(Debug, Default)]
+pub struct CompactionReport {
+    pub removed_entries: u64,
+    pub removed_temp_files: u64,
+    pub removed_directories: u64,
+}
+
+impl CacheProvider for FileCacheProvider {
+    fn get(
+        &self,
+        key: String,
+    ) -> Pin<Box<dyn Future<Output = Result<Option<String>, Box<dyn Error + '_>>> + Send + 'static>>
+    {
+        let path = self.path_for(&key);
+        Box::pin(async move {
+            match tokio::fs::read_to_string(&path).await {
+                Ok(value) => Ok(Some(value)),
+                Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+                Err(e) => Err(e.into()),
+            }
+        })
+    }
+
+    fn set(
+        &self,
+        key: String,
+        value: String,
+    ) -> Pin<Box<dyn Future<Output = Result<(), Box<dyn Error + '_>>> + Send + 'static>> {
+        let path = self.path_for(&key);
+        let temp_path = path.with_extension(format!(
+            "{}.{}.{}",
+            std::process::id(),
+            self.temp_count.fetch_add(1, Ordering::SeqCst),
+            TEMP_EXTENSION
+        ));
+        Box::pin(async move {
+            // コンパクションで空のディレクトリが消されるのと重なった場合は、作り直して1回だけやり直す
+            let mut retried = false;
+            loop {
+                match write_atomically(&path, &temp_path, &value).await {
+                    Err(e) if e.kind() == ErrorKind::NotFound && !retried => retried = true,
+                    result => return Ok(result?),
+                }
+            }
+        })
+    }
+
+    // get_many, set_many では同時に並べて読み書きする
+    fn batch_concurrency(&self) -> usize {
+        self.batch_concurrency
+    }
+
+    fn clone_box(&self) -> Box<dyn CacheProvider> {
+        Box::new(self.clone())
+    }
+}
+
+impl FileCacheProvider {
+    pub fn new(options: FileCacheProviderOptions) -> Self {
+        FileCacheProvider {
+            directory: options.directory,
+            batch_concurrency: options.batch_concurrency,
+            max_age: options.max_age,
+            temp_count: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    fn path_for(&self, key: &str) -> PathBuf {
+        // keyをsha256でハッシュ化
+        let mut hasher = Sha256::new();
+        hasher.update(key.as_bytes());
+        let hashed_key = hasher.finalize();
+        let file_name = format!("{:x}", hashed_key);
+
+        self.directory
+            .join(&file_name[0..2])
+            .join(&file_name[2..4])
+            .join(file_name)
+    }
+
+    // 一定の間隔でコンパクションを行う
+    pub fn start_compaction(&self, interval: Duration) {
+        let provider = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            // 起動直後の1回は飛ばす
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                let directory = provider.directory.clone();
+                let max_age = provider.max_age;
+                let compacted =
+                    tokio::task::spawn_blocking(move || compact(&directory, max_age)).await;
+                match compacted {
+                    Ok(Ok(report)) => println!("file cache compaction: {:?}", report),
+                    Ok(Err(e)) => println!("Error (failed to compact file cache): {}", e),
+                    Err(e) => println!("Error (failed to compact file cache): {}", e),
+                }
+            }
+        });
+    }
+}
+
+// 読み込み中に途中までのファイルが見えないように、一時ファイルに書いてから置き換える
+async fn write_atomically(path: &Path, temp_path: &Path, value: &str) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    if let Err(e) = tokio::fs::write(temp_path, value).await {
+        let _ = tokio::fs::remove_file(temp_path).await;
+        return Err(e);
+    }
+    if let Err(e) = tokio::fs::rename(temp_path, path).await {
+        let _ = tokio::fs::remove_file(temp_path).await;
+        return Err(e);
+    }
+    Ok(())
+}
+
+// 古いエントリーと、書き込みが中断されて残った一時ファイル、空になったディレクトリを消す
+//   別のプロセスと同時に読み書きしていることがあるので、消そうとしたものがなくなっていても成功として扱い、
+//   それ以外のエラーもそのエントリーだけを飛ばして続ける
+pub fn compact(directory: &Path, max_age: Option<Duration>) -> std::io::Result<CompactionReport> {
+    let mut report = CompactionReport::default();
+    let now = SystemTime::now();
+    // 書き込み中の一時ファイルは消さないように、少し時間が経ったものだけを消す
+    let temp_max_age = Duration::from_secs(60 * 10);
+
+    let is_older_than = |path: &Path, age: Duration| -> bool {
+        std::fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| now.duration_since(modified).ok())
+            .is_some_and(|elapsed| elapsed > age)
+    };
+
+    for shard in read_dirs(directory)? {
+        let sub_shards = match read_dirs(&shard) {
+            Ok(sub_shards) => sub_shards,
+            Err(e) => {
+                println!("Error (failed to read {}): {}", shard.display(), e);
+                continue;
+            }
+        };
+        for sub_shard in sub_shards {
+            let entries = match read_entries(&sub_shard) {
+                Ok(entries) => entries,
+                Err(e) => {
+                    println!("Error (failed to read {}): {}", sub_shard.display(), e);
+                    continue;
+                }
+            };
+            for path in entries {
+                let is_temp = path.extension().is_some_and(|ext| ext == TEMP_EXTENSION);
+                if is_temp && is_older_than(&path, temp_max_age) {
+                    if remove_entry(&path) {
+                        report.removed_temp_files += 1;
+                    }
+                } else if !is_temp
+                    && max_age.is_some_and(|age| is_older_than(&path, age))
+                    && remove_entry(&path)
+                {
+                    report.removed_entries += 1;
+                }
+            }
+            if remove_empty_dir(&sub_shard) {
+                report.removed_directories += 1;
+            }
+        }
+        if remove_empty_dir(&shard) {
+            report.removed_directories += 1;
+        }
+    }
+
+    Ok(report)
+}
+
+// 消した場合は true を返す (すでに消えていた場合と失敗した場合は false)
+fn remove_entry(path: &Path) -> bool {
+    match std::fs::remove_file(path) {
+        Ok(()) => true,
+        Err(e) if e.kind() == ErrorKind::NotFound => false,
+        Err(e) => {
+            println!("Error (failed to remove {}): {}", path.display(), e);
+            false
+        }
+    }
+}
+
+// 空のディレクトリを消した場合は true を返す
+//   set がディレクトリを作った直後にファイルを置くことがあるので、空でなくなっていた場合は残す
+fn remove_empty_dir(directory: &Path) -> bool {
+    let is_empty = |directory: &Path| {
+        std::fs::read_dir(directory).is_ok_and(|mut entries| entries.next().is_none())
+    };
+    if !is_empty(directory) {
+        return false;
+    }
+    match std::fs::remove_dir(directory) {
+        Ok(()) => true,
+        Err(e) if e.kind() == ErrorKind::NotFound => false,
+        Err(e) => {
+            if is_empty(directory) {
+                println!("Error (failed to remove {}): {}", directory.display(), e);
+            }
+            false
+        }
+    }
+}
+
+// ディレクトリの中のファイルなどの一覧 (ディレクトリがない場合は空)
+fn read_entries(directory: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let entries = match std::fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    // 読んでいる間に消されたものは飛ばす
+    Ok(entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .collect())
+}
+
+// 分けたディレクトリの一覧 (ディレクトリがない場合は空)
+fn read_dirs(directory: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let entries = match std::fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut dirs = Vec::new();
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        if entry.file_type().is_ok_and(|file_type| file_type.is_dir()) {
+            dirs.push(entry.path());
+        }
+    }
+    Ok(dirs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+
+    fn provider(directory: &Path) -> FileCacheProvider {
+        FileCacheProvider::new(FileCacheProviderOptions {
+            directory: directory.to_path_buf(),
+            batch_concurrency: 2,
+            max_age: None,
+        })
+    }
+
+    // 更新日時を age だけ前にずらす
+    fn age(path: &Path, age: Duration) {
+        File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(SystemTime::now() - age)
+            .unwrap();
+    }
+
+    fn files(directory: &Path) -> Vec<PathBuf> {
+        let mut files = Vec::new();
+        for shard in read_dirs(directory).unwrap() {
+            for sub_shard in read_dirs(&shard).unwrap() {
+                files.extend(read_entries(&sub_shard).unwrap());
+            }
+        }
+        files
+    }
+
+    #[tokio::test]
+    async fn entries_are_sharded_by_the_key_hash() {
+        let directory = tempfile::tempdir().unwrap();
+        let provider = provider(directory.path());
+        provider
+            .set("Hello".to_string(), "こんにちは".to_string())
+            .await
+            .unwrap();
+
+        // "Hello" の sha256 は 185f8db3...
+        let path = directory
+            .path()
+            .join("18")
+            .join("5f")
+            .join("185f8db32271fe25f561a6fc938b2e264306ec304eda518007d1764826381969");
+        assert_eq!(std::fs::read_to_string(path).unwrap(), "こんにちは");
+        assert_eq!(
+            provider.get("Hello".to_string()).await.unwrap(),
+            Some("こんにちは".to_string())
+        );
+        assert_eq!(provider.get("missing".to_string()).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn writes_replace_the_entry_without_leaving_temp_files() {
+        let directory = tempfile::tempdir().unwrap();
+        let provider = provider(directory.path());
+        for value in ["first", "second"] {
+            provider
+                .set("key".to_string(), value.to_string())
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(
+            provider.get("key".to_string()).await.unwrap(),
+            Some("second".to_string())
+        );
+        assert_eq!(files(directory.path()).len(), 1);
+    }
+
+    #[tokio::test]
+    async fn compaction_removes_old_entries_temp_files_and_empty_directories() {
+        let directory = tempfile::tempdir().unwrap();
+        let provider = provider(directory.path());
+        provider
+            .set("old".to_string(), "1".to_string())
+            .await
+            .unwrap();
+        provider
+            .set("new".to_string(), "2".to_string())
+            .await
+            .unwrap();
+        age(&provider.path_for("old"), Duration::from_secs(60 * 60 * 48));
+
+        let new_path = provider.path_for("new");
+        let stale_temp = new_path.with_extension("1.0.tmp");
+        let fresh_temp = new_path.with_extension("1.1.tmp");
+        std::fs::write(&stale_temp, "").unwrap();
+        std::fs::write(&fresh_temp, "").unwrap();
+        age(&stale_temp, Duration::from_secs(60 * 60));
+
+        let report = compact(directory.path(), Some(Duration::from_secs(60 * 60 * 24))).unwrap();
+        assert_eq!(report.removed_entries, 1);
+        assert_eq!(report.removed_temp_files, 1);
+        // "old" だけが入っていた2階層のディレクトリ
+        assert_eq!(report.removed_directories, 2);
+        assert!(!provider.path_for("old").parent().unwrap().exists());
+        assert!(new_path.exists() && fresh_temp.exists() && !stale_temp.exists());
+
+        // 消したディレクトリにも保存し直せる
+        provider
+            .set("old".to_string(), "3".to_string())
+            .await
+            .unwrap();
+        assert_eq!(
+            provider.get("old".to_string()).await.unwrap(),
+            Some("3".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn compaction_skips_entries_it_cannot_remove() {
+        let directory = tempfile::tempdir().unwrap();
+        let provider = provider(directory.path());
+        provider
+            .set("old".to_string(), "1".to_string())
+            .await
+            .unwrap();
+        let old_path = provider.path_for("old");
+        age(&old_path, Duration::from_secs(60 * 60 * 48));
+
+        // ファイルとして消せないものがあっても、残りのエントリーは消す
+        let blocking = old_path.with_file_name("0000");
+        std::fs::create_dir(&blocking).unwrap();
+        File::open(&blocking)
+            .unwrap()
+            .set_modified(SystemTime::now() - Duration::from_secs(60 * 60 * 48))
+            .unwrap();
+
+        let report = compact(directory.path(), Some(Duration::from_secs(60))).unwrap();
+        assert_eq!(report.removed_entries, 1);
+        assert!(!old_path.exists() && blocking.exists());
+        assert_eq!(report.removed_directories, 0);
+    }
+
+    #[test]
+    fn compaction_of_a_missing_directory_succeeds() {
+        let directory = tempfile::tempdir().unwrap();
+        let report = compact(&directory.path().join("missing"), Some(Duration::ZERO)).unwrap();
+        assert_eq!(report.removed_entries, 0);
+        assert_eq!(report.removed_directories, 0);
+    }
+}
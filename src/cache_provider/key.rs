@@ -0,0 +1,197 @@
+use std::error::Error;
+
+use super::entry::CacheEntry;
+use super::provider::CacheProvider;
+
+// キャッシュのキーの形式のバージョン
+//   1: 元のタイトルだけ (翻訳先はすべて ja-JP)
+//   2: 翻訳先の言語とバックエンドを含める
+//   形式を変えた場合は上げて、以前のキーの翻訳を使わないようにする
+pub const CACHE_SCHEMA_VERSION: u32 = 2;
+
+// 以前の形式のキーで保存されている翻訳の翻訳先とバックエンド (当時は Google翻訳の v2 で ja-JP に固定されていた)
+const LEGACY_TARGET_LANGUAGE: &str = "ja-JP";
+const LEGACY_BACKEND: &str = "google:v2";
+const LEGACY_BACKEND_NAME: &str = "google";
+
+// キーの各部分の区切り (タイトルには含まれない制御文字を使う)
+const SEPARATOR: char = '\u{1f}';
+
+// 翻訳のキャッシュのキー
+//   S3 や WebDAV は encode した文字列のハッシュをファイル名にし、SQL は各部分をカラムに分けて保存する
+#[derive(Clone, Debug, PartialEq)]
+pub struct CacheKey {
+    pub schema_version: u32,
+    pub to: String,
+    // バックエンドの名前とバージョン (モデルを指定した場合はモデルも含める)
+    pub backend: String,
+    pub raw_title: String,
+}
+
+impl CacheKey {
+    pub fn new(to: &str, backend: &str, raw_title: &str) -> Self {
+        CacheKey {
+            schema_version: CACHE_SCHEMA_VERSION,
+            to: to.to_string(),
+            backend: backend.to_string(),
+            raw_title: raw_title.to_string(),
+        }
+    }
+
+    // 例: "v2\x1fja-JP\x1fgoogle:v3\x1fHello world"
+    pub fn encode(&self) -> String {
+        format!(
+            "v{}{}{}{}{}{}{}",
+            self.schema_version,
+            SEPARATOR,
+            self.to,
+            SEPARATOR,
+            self.backend,
+            SEPARATOR,
+            self.raw_title
+        )
+    }
+
+    // encode した文字列でなければ (集計などのキーや以前の形式のキー) None を返す
+    pub fn decode(key: &str) -> Option<CacheKey> {
+        let mut parts = key.splitn(4, SEPARATOR);
+        let schema_version = parts.next()?.strip_prefix('v')?.parse().ok()?;
+        let to = parts.next()?.to_string();
+        let backend = parts.next()?.to_string();
+        let raw_title = parts.next()?.to_string();
+        Some(CacheKey {
+            schema_version,
+            to,
+            backend,
+            raw_title,
+        })
+    }
+
+    // 以前の形式のキー (元のタイトルだけ)
+    //   以前は Google翻訳の v2 で ja-JP にしか翻訳していなかったので、それ以外の言語やバックエンドでは使わない
+    pub fn legacy_key(&self) -> Option<String> {
+        match self.to == LEGACY_TARGET_LANGUAGE && self.backend == LEGACY_BACKEND {
+            true => Some(self.raw_title.clone()),
+            false => None,
+        }
+    }
+}
+
+// キャッシュから翻訳をまとめて取得する (結果はキーと同じ順番で返す)
+//   見つからないものは以前の形式のキーでも探し、見つかった場合は新しいキーで保存し直す
+pub async fn get_many_migrating<'a>(
+    cache_provider: &'a dyn CacheProvider,
+    keys: &[CacheKey],
+) -> Result<Vec<Option<String>>, Box<dyn Error + 'a>> {
+    if keys.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut values = cache_provider
+        .get_many(keys.iter().map(|key| key.encode()).collect())
+        .await?;
+
+    let legacy: Vec<(usize, String)> = values
+        .iter()
+        .enumerate()
+        .filter(|(_, value)| value.is_none())
+        .filter_map(|(index, _)| {
+            keys[index]
+                .legacy_key()
+                .map(|legacy_key| (index, legacy_key))
+        })
+        .collect();
+    if legacy.is_empty() {
+        return Ok(values);
+    }
+    let legacy_values = cache_provider
+        .get_many(
+            legacy
+                .iter()
+                .map(|(_, legacy_key)| legacy_key.clone())
+                .collect(),
+        )
+        .await?;
+
+    let mut migrated: Vec<(String, String)> = Vec::new();
+    for ((index, _), value) in legacy.into_iter().zip(legacy_values) {
+        if let Some(value) = value {
+            // 翻訳したバックエンドを残して新しい形式で保存する
+            let entry = CacheEntry {
+                backend: Some(LEGACY_BACKEND_NAME.to_string()),
+                ..CacheEntry::decode(value)
+            };
+            let value = entry.encode();
+            migrated.push((keys[index].encode(), value.clone()));
+            values[index] = Some(value);
+        }
+    }
+    if !migrated.is_empty() {
+        println!(
+            "migrating {} cache entries to the new key format",
+            migrated.len()
+        );
+        // 終了を待たないで非同期で行う
+        let cache_provider = cache_provider.clone_box();
+        tokio::spawn(async move {
+            let set_result = cache_provider.set_many(migrated).await;
+            if let Err(e) = set_result {
+                println!("Error (failed to migrate cache entries): {}", e);
+            }
+        });
+    }
+
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache_provider::memory::MemoryCacheProvider;
+
+    #[test]
+    fn legacy_key_is_only_for_google_v2_into_japanese() {
+        assert_eq!(
+            CacheKey::new("ja-JP", "google:v2", "Hello").legacy_key(),
+            Some("Hello".to_string())
+        );
+        assert_eq!(CacheKey::new("en", "google:v2", "Hello").legacy_key(), None);
+        assert_eq!(
+            CacheKey::new("ja-JP", "deepl:v2", "Hello").legacy_key(),
+            None
+        );
+        assert_eq!(
+            CacheKey::new("ja-JP", "google:v3", "Hello").legacy_key(),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn legacy_entries_are_migrated_with_the_google_backend() {
+        let provider = MemoryCacheProvider::default();
+        provider.insert("Rust's release", "Rustの&#39;リリース&#39;");
+
+        let google = CacheKey::new("ja-JP", "google:v2", "Rust's release");
+        let deepl = CacheKey::new("ja-JP", "deepl:v2", "Rust's release");
+        let values = get_many_migrating(&provider, &[google.clone(), deepl.clone()])
+            .await
+            .unwrap();
+
+        let entry = CacheEntry::decode(values[0].clone().unwrap());
+        assert_eq!(entry.translated, "Rustの'リリース'");
+        assert_eq!(entry.backend, Some("google".to_string()));
+        assert_eq!(values[1], None);
+
+        // 新しいキーでの保存は待たないので、終わるまで待つ
+        for _ in 0..100 {
+            if provider.value(&google.encode()).is_some() {
+                break;
+            }
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(
+            provider.value(&google.encode()).map(CacheEntry::decode),
+            Some(entry)
+        );
+        assert_eq!(provider.value(&deepl.encode()), None);
+    }
+}
@@ -0,0 +1,329 @@
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+
+use super::provider::{CacheProvider, GetManyFuture, IncrementFuture, SetManyFuture};
+
+use std::{error::Error, future::Future, pin::Pin};
+
+pub struct LruCacheProviderOptions {
+    // 後ろにある保存先 (S3, WebDav など)
+    pub inner: Box<dyn CacheProvider>,
+    // メモリに残しておく件数の上限 (0の場合は件数で制限しない)
+    pub max_entries: usize,
+    // メモリに残しておくキーと値のバイト数の合計の上限 (0の場合はバイト数で制限しない)
+    pub max_bytes: usize,
+}
+
+// ヒット率などの統計
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct LruStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+    pub entries: usize,
+    pub bytes: usize,
+    pub max_entries: usize,
+    pub max_bytes: usize,
+}
+
+#[derive(Default)]
+struct LruState {
+    // キー -> (値, 最後に使った順番)
+    entries: HashMap<String, (String, u64)>,
+    // 最後に使った順番 -> キー (小さいものから追い出す)
+    order: BTreeMap<u64, String>,
+    tick: u64,
+    bytes: usize,
+    hits: u64,
+    misses: u64,
+    evictions: u64,
+}
+
+impl LruState {
+    fn touch(&mut self, key: &str) -> Option<String> {
+        self.tick += 1;
+        let tick = self.tick;
+        let (value, last_used) = self.entries.get_mut(key)?;
+        self.order.remove(last_used);
+        *last_used = tick;
+        self.order.insert(tick, key.to_string());
+        Some(value.clone())
+    }
+
+    fn insert(&mut self, key: String, value: String, max_entries: usize, max_bytes: usize) {
+        let size = key.len() + value.len();
+        // 1件で上限を超えるものは残さない
+        if max_bytes > 0 && size > max_bytes {
+            self.remove(&key);
+            return;
+        }
+
+        self.remove(&key);
+        self.tick += 1;
+        self.order.insert(self.tick, key.clone());
+        self.entries.insert(key, (value, self.tick));
+        self.bytes += size;
+
+        while (max_entries > 0 && self.entries.len() > max_entries)
+            || (max_bytes > 0 && self.bytes > max_bytes)
+        {
+            let oldest = match self.order.keys().next() {
+                Some(oldest) => *oldest,
+                None => break,
+            };
+            let key = self.order.remove(&oldest).unwrap();
+            if let Some((value, _)) = self.entries.remove(&key) {
+                self.bytes -= key.len() + value.len();
+                self.evictions += 1;
+            }
+        }
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some((value, last_used)) = self.entries.remove(key) {
+            self.order.remove(&last_used);
+            self.bytes -= key.len() + value.len();
+        }
+    }
+}
+
+// 他の保存先の前に置くプロセス内のLRUキャッシュ
+//   取得はメモリになければ後ろの保存先から読んで残し、保存はメモリと後ろの保存先の両方に書く
+#[derive(Clone)]
+pub struct LruCacheProvider {
+    inner: Box<dyn CacheProvider>,
+    state: Arc<Mutex<LruState>>,
+    max_entries: usize,
+    max_bytes: usize,
+}
+
+impl CacheProvider for LruCacheProvider {
+    fn get(
+        &self,
+        key: String,
+    ) -> Pin<Box<dyn Future<Output = Result<Option<String>, Box<dyn Error + '_>>> + Send + 'static>>
+    {
+        let provider = self.clone();
+        Box::pin(async move {
+            let values = provider.get_many_through(vec![key]).await?;
+            Ok(values.into_iter().next().flatten())
+        })
+    }
+
+    fn set(
+        &self,
+        key: String,
+        value: String,
+    ) -> Pin<Box<dyn Future<Output = Result<(), Box<dyn Error + '_>>> + Send + 'static>> {
+        self.set_many(vec![(key, value)])
+    }
+
+    fn get_many(&self, keys: Vec<String>) -> GetManyFuture<'_> {
+        let provider = self.clone();
+        Box::pin(async move {
+            let values = provider.get_many_through(keys).await?;
+            Ok(values)
+        })
+    }
+
+    fn set_many(&self, entries: Vec<(String, String)>) -> SetManyFuture<'_> {
+        {
+            let mut state = self.state.lock().unwrap();
+            for (key, value) in entries.iter() {
+                state.insert(key.clone(), value.clone(), self.max_entries, self.max_bytes);
+            }
+        }
+
+        let inner = self.inner.clone();
+        Box::pin(async move {
+            match inner.set_many(entries).await {
+                Ok(()) => Ok(()),
+                Err(e) => Err(e.to_string().into()),
+            }
+        })
+    }
+
+    // 集計は他のプロセスからも足されるので、メモリには残さずに後ろの保存先で足す
+    fn increment(&self, key: String, delta: u64) -> IncrementFuture<'_> {
+        self.state.lock().unwrap().remove(&key);
+
+        let inner = self.inner.clone();
+        Box::pin(async move {
+            match inner.increment(key, delta).await {
+                Ok(value) => Ok(value),
+                Err(e) => Err(e.to_string().into()),
+            }
+        })
+    }
+
+    fn clone_box(&self) -> Box<dyn CacheProvider> {
+        Box::new(self.clone())
+    }
+}
+
+impl LruCacheProvider {
+    pub fn new(options: LruCacheProviderOptions) -> Self {
+        LruCacheProvider {
+            inner: options.inner,
+            state: Arc::new(Mutex::new(LruState::default())),
+            max_entries: options.max_entries,
+            max_bytes: options.max_bytes,
+        }
+    }
+
+    pub fn stats(&self) -> LruStats {
+        let state = self.state.lock().unwrap();
+        LruStats {
+            hits: state.hits,
+            misses: state.misses,
+            evictions: state.evictions,
+            entries: state.entries.len(),
+            bytes: state.bytes,
+            max_entries: self.max_entries,
+            max_bytes: self.max_bytes,
+        }
+    }
+
+    // メモリにないものだけを後ろの保存先からまとめて取得する
+    async fn get_many_through(&self, keys: Vec<String>) -> Result<Vec<Option<String>>, String> {
+        let mut values: Vec<Option<String>> = Vec::with_capacity(keys.len());
+        let mut missing: Vec<usize> = Vec::new();
+        {
+            let mut state = self.state.lock().unwrap();
+            for (index, key) in keys.iter().enumerate() {
+                let value = state.touch(key);
+                match value {
+                    Some(_) => state.hits += 1,
+                    None => {
+                        state.misses += 1;
+                        missing.push(index);
+                    }
+                }
+                values.push(value);
+            }
+        }
+        if missing.is_empty() {
+            return Ok(values);
+        }
+
+        let missing_keys: Vec<String> = missing.iter().map(|index| keys[*index].clone()).collect();
+        let inner = self.inner.clone();
+        let fetched = inner
+            .get_many(missing_keys)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let mut state = self.state.lock().unwrap();
+        for (index, value) in missing.into_iter().zip(fetched) {
+            if let Some(value) = value.as_ref() {
+                state.insert(
+                    keys[index].clone(),
+                    value.clone(),
+                    self.max_entries,
+                    self.max_bytes,
+                );
+            }
+            values[index] = value;
+        }
+        Ok(values)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache_provider::memory::MemoryCacheProvider;
+
+    fn lru(inner: &MemoryCacheProvider, max_entries: usize, max_bytes: usize) -> LruCacheProvider {
+        LruCacheProvider::new(LruCacheProviderOptions {
+            inner: inner.clone_box(),
+            max_entries,
+            max_bytes,
+        })
+    }
+
+    async fn get(provider: &LruCacheProvider, key: &str) -> Option<String> {
+        provider.get(key.to_string()).await.unwrap()
+    }
+
+    async fn set(provider: &LruCacheProvider, key: &str, value: &str) {
+        provider
+            .set(key.to_string(), value.to_string())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn least_recently_used_entries_are_evicted_by_count() {
+        let inner = MemoryCacheProvider::default();
+        let provider = lru(&inner, 2, 0);
+        set(&provider, "a", "1").await;
+        set(&provider, "b", "2").await;
+        // a を使ったので、次に追い出されるのは b
+        assert_eq!(get(&provider, "a").await, Some("1".to_string()));
+        set(&provider, "c", "3").await;
+
+        let stats = provider.stats();
+        assert_eq!((stats.entries, stats.evictions), (2, 1));
+        assert_eq!((stats.hits, stats.misses), (1, 0));
+
+        // 追い出されたものは後ろの保存先から読み直す
+        assert_eq!(get(&provider, "c").await, Some("3".to_string()));
+        assert_eq!(get(&provider, "b").await, Some("2".to_string()));
+        let stats = provider.stats();
+        assert_eq!((stats.hits, stats.misses), (2, 1));
+        assert_eq!(stats.evictions, 2);
+    }
+
+    #[tokio::test]
+    async fn entries_are_evicted_to_stay_within_the_byte_budget() {
+        let inner = MemoryCacheProvider::default();
+        let provider = lru(&inner, 0, 10);
+        set(&provider, "a", "1234").await;
+        set(&provider, "b", "1234").await;
+        assert_eq!(provider.stats().bytes, 10);
+
+        set(&provider, "c", "12").await;
+        let stats = provider.stats();
+        assert_eq!((stats.entries, stats.bytes, stats.evictions), (2, 8, 1));
+
+        // 1件で上限を超えるものはメモリに残さずに後ろの保存先にだけ書く
+        set(&provider, "big", "12345678901").await;
+        assert_eq!(provider.stats().bytes, 8);
+        assert_eq!(inner.value("big"), Some("12345678901".to_string()));
+        assert_eq!(get(&provider, "big").await, Some("12345678901".to_string()));
+        assert_eq!(provider.stats().misses, 1);
+    }
+
+    #[tokio::test]
+    async fn hits_and_misses_are_counted() {
+        let inner = MemoryCacheProvider::default();
+        inner.insert("stored", "value");
+        let provider = lru(&inner, 10, 0);
+
+        assert_eq!(get(&provider, "missing").await, None);
+        assert_eq!(get(&provider, "stored").await, Some("value".to_string()));
+        assert_eq!(get(&provider, "stored").await, Some("value".to_string()));
+        let values = provider
+            .get_many(vec!["stored".to_string(), "missing".to_string()])
+            .await
+            .unwrap();
+        assert_eq!(values, vec![Some("value".to_string()), None]);
+
+        // 見つからなかったものはメモリに残さない
+        let stats = provider.stats();
+        assert_eq!((stats.hits, stats.misses, stats.entries), (2, 3, 1));
+    }
+
+    #[tokio::test]
+    async fn increments_are_not_kept_in_memory() {
+        let inner = MemoryCacheProvider::default();
+        let provider = lru(&inner, 10, 0);
+        set(&provider, "count", "1").await;
+
+        assert_eq!(provider.increment("count".to_string(), 2).await.unwrap(), 3);
+        assert_eq!(provider.stats().entries, 0);
+        assert_eq!(get(&provider, "count").await, Some("3".to_string()));
+    }
+}
@@ -0,0 +1,50 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use super::provider::CacheProvider;
+
+use std::{error::Error, future::Future, pin::Pin};
+
+// テスト用にプロセス内の HashMap に保存する
+//   clone したものは同じ HashMap を使うので、複数のプロセスから共有される保存先の代わりになる
+#[derive(Clone, Default)]
+pub struct MemoryCacheProvider {
+    pub values: Arc<Mutex<HashMap<String, String>>>,
+}
+
+impl CacheProvider for MemoryCacheProvider {
+    fn get(
+        &self,
+        key: String,
+    ) -> Pin<Box<dyn Future<Output = Result<Option<String>, Box<dyn Error + '_>>> + Send + 'static>>
+    {
+        let value = self.values.lock().unwrap().get(&key).cloned();
+        Box::pin(async move { Ok(value) })
+    }
+
+    fn set(
+        &self,
+        key: String,
+        value: String,
+    ) -> Pin<Box<dyn Future<Output = Result<(), Box<dyn Error + '_>>> + Send + 'static>> {
+        self.values.lock().unwrap().insert(key, value);
+        Box::pin(async move { Ok(()) })
+    }
+
+    fn clone_box(&self) -> Box<dyn CacheProvider> {
+        Box::new(self.clone())
+    }
+}
+
+impl MemoryCacheProvider {
+    pub fn value(&self, key: &str) -> Option<String> {
+        self.values.lock().unwrap().get(key).cloned()
+    }
+
+    pub fn insert(&self, key: &str, value: &str) {
+        self.values
+            .lock()
+            .unwrap()
+            .insert(key.to_string(), value.to_string());
+    }
+}
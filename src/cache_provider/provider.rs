@@ -1,9 +1,60 @@
-use std::{error::Error, future::Future, pin::Pin};
+use std::{error::Error, future::Future, pin::Pin, sync::Arc};
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+// まとめて取得・保存する場合に同時に送るリクエストの数 (まとめて送る方法がない保存先で使う)
+pub const DEFAULT_BATCH_CONCURRENCY: usize = 8;
+
+// get_many, set_many の戻り値
+pub type GetManyFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<Vec<Option<String>>, Box<dyn Error + 'a>>> + Send + 'static>>;
+pub type SetManyFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<(), Box<dyn Error + 'a>>> + Send + 'static>>;
+// increment の戻り値
+pub type IncrementFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<u64, Box<dyn Error + 'a>>> + Send + 'static>>;
 
 pub trait CacheProvider: Send + Sync {
     fn get(&self, key: String) -> Pin<Box<dyn Future<Output = Result<Option<String>, Box<dyn Error+ '_>>> + Send+ 'static>>;
     fn set(&self, key: String, value: String) -> Pin<Box<dyn Future<Output = Result<(), Box<dyn Error+ '_>>> + Send+ 'static>>;
 
+    // 複数のキーをまとめて取得する (結果はキーと同じ順番で返す)
+    fn get_many(&self, keys: Vec<String>) -> GetManyFuture<'_> {
+        concurrent_get_many(self.clone_box(), keys, self.batch_concurrency())
+    }
+
+    // 複数のキーと値をまとめて保存する
+    fn set_many(&self, entries: Vec<(String, String)>) -> SetManyFuture<'_> {
+        concurrent_set_many(self.clone_box(), entries, self.batch_concurrency())
+    }
+
+    // まとめて送る方法がない保存先で、get_many, set_many のときに同時に送るリクエストの数
+    fn batch_concurrency(&self) -> usize {
+        DEFAULT_BATCH_CONCURRENCY
+    }
+
+    // 数値として保存されている値に delta を足し、足した後の値を返す (保存されていない場合は0から足す)
+    //   まとめて足す方法がない保存先では読み込んでから保存し直すので、別のプロセスと同時に足すと片方が失われることがある
+    fn increment(&self, key: String, delta: u64) -> IncrementFuture<'_> {
+        let provider = self.clone_box();
+        Box::pin(async move {
+            // エラーはスレッドをまたげるように文字列にする
+            let stored = provider.get(key.clone()).await.map_err(|e| e.to_string())?;
+            let current = stored
+                .and_then(|value| value.trim().parse::<u64>().ok())
+                .unwrap_or(0);
+            if delta == 0 {
+                return Ok(current);
+            }
+            let value = current + delta;
+            provider
+                .set(key, value.to_string())
+                .await
+                .map_err(|e| e.to_string())?;
+            Ok(value)
+        })
+    }
+
     fn clone_box(&self) -> Box<dyn CacheProvider>;
 }
 
@@ -12,3 +63,64 @@ impl Clone for Box<dyn CacheProvider> {
         self.clone_box()
     }
 }
+
+// get を同時に concurrency 件まで並べて実行する
+pub fn concurrent_get_many<'a>(
+    provider: Box<dyn CacheProvider>,
+    keys: Vec<String>,
+    concurrency: usize,
+) -> GetManyFuture<'a> {
+    Box::pin(async move {
+        let semaphore = Arc::new(Semaphore::new(concurrency.max(1)));
+        let mut tasks = JoinSet::new();
+        for (index, key) in keys.iter().cloned().enumerate() {
+            let provider = provider.clone();
+            let semaphore = semaphore.clone();
+            tasks.spawn(async move {
+                let _permit = semaphore.acquire_owned().await.unwrap();
+                // エラーはスレッドをまたげるように文字列にする
+                let result = provider.get(key).await.map_err(|e| e.to_string());
+                (index, result)
+            });
+        }
+
+        let mut values: Vec<Option<String>> = keys.iter().map(|_| None).collect();
+        while let Some(joined) = tasks.join_next().await {
+            let (index, result) = joined?;
+            values[index] = result?;
+        }
+        Ok(values)
+    })
+}
+
+// set を同時に concurrency 件まで並べて実行する
+pub fn concurrent_set_many<'a>(
+    provider: Box<dyn CacheProvider>,
+    entries: Vec<(String, String)>,
+    concurrency: usize,
+) -> SetManyFuture<'a> {
+    Box::pin(async move {
+        let semaphore = Arc::new(Semaphore::new(concurrency.max(1)));
+        let mut tasks = JoinSet::new();
+        for (key, value) in entries {
+            let provider = provider.clone();
+            let semaphore = semaphore.clone();
+            tasks.spawn(async move {
+                let _permit = semaphore.acquire_owned().await.unwrap();
+                provider.set(key, value).await.map_err(|e| e.to_string())
+            });
+        }
+
+        // 失敗したものがあっても残りは保存し、最後のエラーを返す
+        let mut last_error: Option<String> = None;
+        while let Some(joined) = tasks.join_next().await {
+            if let Err(e) = joined? {
+                last_error = Some(e);
+            }
+        }
+        match last_error {
+            Some(e) => Err(e.into()),
+            None => Ok(()),
+        }
+    })
+}
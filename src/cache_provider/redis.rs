@@ -0,0 +1,294 @@
+use deadpool_redis::{Config, Pool, PoolConfig, Runtime};
+use sha2::{Digest, Sha256};
+use std::time::Duration;
+
+use super::key::CacheKey;
+use super::provider::{CacheProvider, GetManyFuture, IncrementFuture, SetManyFuture};
+
+use std::{error::Error, future::Future, pin::Pin};
+
+// 1つのコマンドで送るキーの数の上限 (これを超える場合は分けてパイプラインで送る)
+const MAX_KEYS_PER_COMMAND: usize = 500;
+
+pub struct RedisCacheProviderOptions {
+    // 例: redis://127.0.0.1:6379/0
+    pub redis_url: String,
+    // 他のサービスと同じRedisを使う場合にキーの前に付ける文字列
+    pub key_prefix: String,
+    // 翻訳のキャッシュの有効期限 (Noneの場合は期限なし、集計や翻訳メモリには付けない)
+    pub ttl: Option<Duration>,
+    // プールに残しておく接続の数の上限
+    pub max_connections: usize,
+}
+
+// Redisに保存する
+//   キーは S3, WebDav と同じくキーの sha256 で、key_prefix を前に付ける
+#[derive(Clone)]
+pub struct RedisCacheProvider {
+    pool: Pool,
+    key_prefix: String,
+    ttl: Option<Duration>,
+}
+
+impl CacheProvider for RedisCacheProvider {
+    fn get(
+        &self,
+        key: String,
+    ) -> Pin<Box<dyn Future<Output = Result<Option<String>, Box<dyn Error + '_>>> + Send + 'static>>
+    {
+        let provider = self.clone();
+        Box::pin(async move {
+            let values = provider.mget(vec![key]).await?;
+            Ok(values.into_iter().next().flatten())
+        })
+    }
+
+    fn set(
+        &self,
+        key: String,
+        value: String,
+    ) -> Pin<Box<dyn Future<Output = Result<(), Box<dyn Error + '_>>> + Send + 'static>> {
+        self.set_many(vec![(key, value)])
+    }
+
+    fn get_many(&self, keys: Vec<String>) -> GetManyFuture<'_> {
+        let provider = self.clone();
+        Box::pin(async move {
+            let values = provider.mget(keys).await?;
+            Ok(values)
+        })
+    }
+
+    fn set_many(&self, entries: Vec<(String, String)>) -> SetManyFuture<'_> {
+        let provider = self.clone();
+        Box::pin(async move {
+            provider.mset(entries).await?;
+            Ok(())
+        })
+    }
+
+    fn increment(&self, key: String, delta: u64) -> IncrementFuture<'_> {
+        let provider = self.clone();
+        Box::pin(async move {
+            let value = provider.incr_by(key, delta).await?;
+            Ok(value)
+        })
+    }
+
+    fn clone_box(&self) -> Box<dyn CacheProvider> {
+        Box::new(self.clone())
+    }
+}
+
+impl RedisCacheProvider {
+    pub fn new(options: RedisCacheProviderOptions) -> Result<Self, Box<dyn Error>> {
+        let mut config = Config::from_url(options.redis_url);
+        config.pool = Some(PoolConfig::new(options.max_connections.max(1)));
+        // 接続はプールから最初に取り出すときに行う
+        let pool = config.create_pool(Some(Runtime::Tokio1))?;
+
+        Ok(RedisCacheProvider {
+            pool,
+            key_prefix: options.key_prefix,
+            ttl: options.ttl,
+        })
+    }
+
+    fn redis_key(&self, key: &str) -> String {
+        // keyをsha256でハッシュ化
+        let mut hasher = Sha256::new();
+        hasher.update(key.as_bytes());
+        let hashed_key = hasher.finalize();
+        format!("{}{:x}", self.key_prefix, hashed_key)
+    }
+
+    // キーと同じ順番で結果が返るように、MGET を MAX_KEYS_PER_COMMAND 件ずつに分ける
+    fn mget_commands(&self, keys: &[String]) -> Vec<Vec<String>> {
+        keys.chunks(MAX_KEYS_PER_COMMAND)
+            .map(|chunk| {
+                let mut command = vec!["MGET".to_string()];
+                command.extend(chunk.iter().map(|key| self.redis_key(key)));
+                command
+            })
+            .collect()
+    }
+
+    // 有効期限は翻訳のキャッシュにだけ付けて SET EX で送り、集計や翻訳メモリなどのそれ以外は MSET で送る
+    fn mset_commands(&self, entries: Vec<(String, String)>) -> Vec<Vec<String>> {
+        let mut commands: Vec<Vec<String>> = Vec::new();
+        let mut without_ttl: Vec<(String, String)> = Vec::new();
+        for (key, value) in entries {
+            let is_translation = CacheKey::decode(&key).is_some();
+            match self.ttl {
+                Some(ttl) if is_translation => {
+                    // 0秒は期限切れとして扱われるので最低1秒にする
+                    let seconds = ttl.as_secs().max(1);
+                    commands.push(vec![
+                        "SET".to_string(),
+                        self.redis_key(&key),
+                        value,
+                        "EX".to_string(),
+                        seconds.to_string(),
+                    ]);
+                }
+                _ => without_ttl.push((self.redis_key(&key), value)),
+            }
+        }
+        for chunk in without_ttl.chunks(MAX_KEYS_PER_COMMAND) {
+            let mut command = vec!["MSET".to_string()];
+            for (key, value) in chunk {
+                command.push(key.clone());
+                command.push(value.clone());
+            }
+            commands.push(command);
+        }
+        commands
+    }
+
+    fn pipeline(commands: &[Vec<String>], ignore_results: bool) -> redis::Pipeline {
+        let mut pipeline = redis::pipe();
+        for command in commands {
+            pipeline.cmd(&command[0]).arg(&command[1..]);
+            if ignore_results {
+                pipeline.ignore();
+            }
+        }
+        pipeline
+    }
+
+    // MGET をまとめてパイプラインで送る (結果はキーと同じ順番で返す)
+    //   エラーはスレッドをまたげるように文字列にする
+    async fn mget(&self, keys: Vec<String>) -> Result<Vec<Option<String>>, String> {
+        if keys.is_empty() {
+            return Ok(Vec::new());
+        }
+        let pipeline = RedisCacheProvider::pipeline(&self.mget_commands(&keys), false);
+
+        let mut connection = self.pool.get().await.map_err(|e| e.to_string())?;
+        let values: Vec<Vec<Option<String>>> = pipeline
+            .query_async(&mut connection)
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok(values.into_iter().flatten().collect())
+    }
+
+    async fn mset(&self, entries: Vec<(String, String)>) -> Result<(), String> {
+        if entries.is_empty() {
+            return Ok(());
+        }
+        let pipeline = RedisCacheProvider::pipeline(&self.mset_commands(entries), true);
+
+        let mut connection = self.pool.get().await.map_err(|e| e.to_string())?;
+        pipeline
+            .query_async::<_, ()>(&mut connection)
+            .await
+            .map_err(|e| e.to_string())
+    }
+
+    // INCRBY で足すので、別のプロセスと同時に足しても失われない (集計なので有効期限は付けない)
+    async fn incr_by(&self, key: String, delta: u64) -> Result<u64, String> {
+        let mut connection = self.pool.get().await.map_err(|e| e.to_string())?;
+        redis::cmd("INCRBY")
+            .arg(self.redis_key(&key))
+            .arg(delta)
+            .query_async::<_, u64>(&mut connection)
+            .await
+            .map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn provider(ttl: Option<Duration>) -> RedisCacheProvider {
+        // 接続はプールから取り出すまで行われないので、サーバーがなくても作れる
+        RedisCacheProvider::new(RedisCacheProviderOptions {
+            redis_url: "redis://127.0.0.1:1/0".to_string(),
+            key_prefix: "rss:".to_string(),
+            ttl,
+            max_connections: 1,
+        })
+        .unwrap()
+    }
+
+    fn translation_key(title: &str) -> String {
+        CacheKey::new("ja-JP", "google:v2", title).encode()
+    }
+
+    #[test]
+    fn keys_are_hashed_with_the_prefix() {
+        let provider = provider(None);
+        let key = provider.redis_key("Hello");
+        assert_eq!(
+            key,
+            "rss:185f8db32271fe25f561a6fc938b2e264306ec304eda518007d1764826381969"
+        );
+        assert_eq!(
+            provider.mget_commands(&["Hello".to_string()]),
+            vec![vec!["MGET".to_string(), key]]
+        );
+    }
+
+    #[test]
+    fn commands_are_split_into_chunks() {
+        let provider = provider(None);
+        let keys: Vec<String> = (0..MAX_KEYS_PER_COMMAND * 2 + 1)
+            .map(|index| index.to_string())
+            .collect();
+
+        let lengths: Vec<usize> = provider
+            .mget_commands(&keys)
+            .iter()
+            .map(|command| command.len() - 1)
+            .collect();
+        assert_eq!(lengths, vec![MAX_KEYS_PER_COMMAND, MAX_KEYS_PER_COMMAND, 1]);
+
+        let entries = keys.into_iter().map(|key| (key.clone(), key)).collect();
+        let lengths: Vec<usize> = provider
+            .mset_commands(entries)
+            .iter()
+            .map(|command| (command.len() - 1) / 2)
+            .collect();
+        assert_eq!(lengths, vec![MAX_KEYS_PER_COMMAND, MAX_KEYS_PER_COMMAND, 1]);
+    }
+
+    #[test]
+    fn ttl_is_only_set_on_translations() {
+        let provider_without_ttl = provider(None);
+        let provider = provider(Some(Duration::from_secs(60)));
+        let commands = provider.mset_commands(vec![
+            (translation_key("Hello"), "a".to_string()),
+            ("spend:daily:2024-01-30".to_string(), "b".to_string()),
+            ("memory:google:v2:ja-JP".to_string(), "c".to_string()),
+        ]);
+
+        assert_eq!(
+            commands,
+            vec![
+                vec![
+                    "SET".to_string(),
+                    provider.redis_key(&translation_key("Hello")),
+                    "a".to_string(),
+                    "EX".to_string(),
+                    "60".to_string(),
+                ],
+                vec![
+                    "MSET".to_string(),
+                    provider.redis_key("spend:daily:2024-01-30"),
+                    "b".to_string(),
+                    provider.redis_key("memory:google:v2:ja-JP"),
+                    "c".to_string(),
+                ],
+            ]
+        );
+
+        // 有効期限がない場合はすべて MSET で送る
+        let commands = provider_without_ttl.mset_commands(vec![
+            (translation_key("Hello"), "a".to_string()),
+            ("spend:daily:2024-01-30".to_string(), "b".to_string()),
+        ]);
+        assert_eq!(commands.len(), 1);
+        assert_eq!(commands[0][0], "MSET");
+    }
+}
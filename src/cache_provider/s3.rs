@@ -18,11 +18,14 @@ pub struct S3CacheProviderOptions {
     pub secret_key: String,
     pub endpoint_url: String,
     pub bucket_name: String,
+    // まとめて取得・保存する場合に同時に送るリクエストの数
+    pub batch_concurrency: usize,
 }
 
 pub struct S3CacheProvider {
     client: S3Client,
     bucket_name: String,
+    batch_concurrency: usize,
 }
 
 impl CacheProvider for S3CacheProvider {
@@ -86,10 +89,16 @@ impl CacheProvider for S3CacheProvider {
         })
     }
 
+    // 1件ずつのリクエストしかないので、get_many, set_many では同時に並べて送る
+    fn batch_concurrency(&self) -> usize {
+        self.batch_concurrency
+    }
+
     fn clone_box(&self) -> Box<dyn CacheProvider> {
         Box::new(S3CacheProvider {
             client: self.client.clone(),
             bucket_name: self.bucket_name.clone(),
+            batch_concurrency: self.batch_concurrency,
         })
     }
 }
@@ -113,6 +122,7 @@ impl S3CacheProvider {
         S3CacheProvider {
             client,
             bucket_name: options.bucket_name,
+            batch_concurrency: options.batch_concurrency,
         }
     }
 }
@@ -1,8 +1,9 @@
-use sqlx::{Any, Pool};
+use sqlx::{Any, Connection, Pool};
 use std::{fmt, sync::Arc};
 use tokio::sync::Mutex;
 
-use super::provider::CacheProvider;
+use super::key::CacheKey;
+use super::provider::{CacheProvider, GetManyFuture, IncrementFuture, SetManyFuture};
 
 use std::{error::Error, future::Future, pin::Pin};
 
@@ -38,10 +39,14 @@ impl CacheProvider for SqlCacheProvider {
             let connection = connection_pool.lock().await;
             let mut pool = connection.acquire().await?;
 
+            let key = SqlCacheProvider::columns(key);
             let result: Result<(Vec<u8>,), sqlx::error::Error> = sqlx::query_as(
-                "SELECT `translated_title` FROM rss_cache WHERE raw_title = ? LIMIT 1",
+                "SELECT `translated_title` FROM rss_cache WHERE raw_title = ? AND target_language = ? AND backend = ? AND schema_version = ? LIMIT 1",
             )
-            .bind(key)
+            .bind(key.raw_title)
+            .bind(key.to)
+            .bind(key.backend)
+            .bind(key.schema_version as i32)
             .fetch_one(&mut *pool)
             .await;
 
@@ -76,17 +81,175 @@ impl CacheProvider for SqlCacheProvider {
             let connection = connection_pool.lock().await;
             let mut pool = connection.acquire().await?;
 
-            let exec_query =
-                sqlx::query("INSERT INTO rss_cache (raw_title, translated_title) VALUES (?, ?)")
-                    .bind(key)
-                    .bind(value);
+            // 集計のように同じキーで何度も保存するものがあるので、消してから入れ直す
+            let key = SqlCacheProvider::columns(key);
+            let mut transaction = pool.begin().await?;
+            sqlx::query(
+                "DELETE FROM rss_cache WHERE raw_title = ? AND target_language = ? AND backend = ? AND schema_version = ?",
+            )
+            .bind(key.raw_title.clone())
+            .bind(key.to.clone())
+            .bind(key.backend.clone())
+            .bind(key.schema_version as i32)
+            .execute(&mut *transaction)
+            .await?;
+            sqlx::query(
+                "INSERT INTO rss_cache (raw_title, target_language, backend, schema_version, translated_title) VALUES (?, ?, ?, ?, ?)",
+            )
+            .bind(key.raw_title)
+            .bind(key.to)
+            .bind(key.backend)
+            .bind(key.schema_version as i32)
+            .bind(value)
+            .execute(&mut *transaction)
+            .await?;
+            transaction.commit().await?;
+
+            Ok(())
+        })
+    }
+
+    fn get_many(&self, keys: Vec<String>) -> GetManyFuture<'_> {
+        let connection_pool = Arc::clone(&self.connection_pool);
+
+        let sql_thread = tokio::spawn(async move {
+            let connection = connection_pool.lock().await;
+            let mut pool = connection.acquire().await?;
+
+            let keys: Vec<CacheKey> = keys.into_iter().map(SqlCacheProvider::columns).collect();
+            let mut values: Vec<Option<String>> = keys.iter().map(|_| None).collect();
+            // 元のタイトルの IN (...) でまとめて取得し、言語やバックエンドが一致するものを選ぶ
+            for chunk in keys.chunks(SqlCacheProvider::MAX_KEYS_PER_QUERY) {
+                let mut raw_titles: Vec<&String> = chunk.iter().map(|key| &key.raw_title).collect();
+                raw_titles.sort();
+                raw_titles.dedup();
+                let placeholders = vec!["?"; raw_titles.len()].join(", ");
+                let query = format!(
+                    "SELECT raw_title, target_language, backend, schema_version, `translated_title` FROM rss_cache WHERE raw_title IN ({})",
+                    placeholders
+                );
+                let mut select_query =
+                    sqlx::query_as::<_, (Vec<u8>, String, String, i32, Vec<u8>)>(&query);
+                for raw_title in raw_titles {
+                    select_query = select_query.bind(raw_title.clone());
+                }
+                let rows = select_query.fetch_all(&mut *pool).await?;
+
+                for (raw_title, to, backend, schema_version, translated_title) in rows {
+                    let row_key = CacheKey {
+                        schema_version: schema_version as u32,
+                        to,
+                        backend,
+                        raw_title: String::from_utf8_lossy(&raw_title).to_string(),
+                    };
+                    for (index, key) in keys.iter().enumerate() {
+                        if *key == row_key {
+                            values[index] =
+                                Some(String::from_utf8(translated_title.clone()).unwrap());
+                        }
+                    }
+                }
+            }
+
+            Ok::<Vec<Option<String>>, sqlx::Error>(values)
+        });
+
+        Box::pin(async move {
+            match sql_thread.await.unwrap() {
+                Ok(values) => Ok(values),
+                Err(e) => Err(SqlCacheProvider::create_get_error(e.to_string())),
+            }
+        })
+    }
+
+    fn set_many(&self, entries: Vec<(String, String)>) -> SetManyFuture<'_> {
+        let connection_pool = Arc::clone(&self.connection_pool);
+
+        Box::pin(async move {
+            let connection = connection_pool.lock().await;
+            let mut pool = connection.acquire().await?;
+
+            let mut transaction = pool.begin().await?;
+            for chunk in entries.chunks(SqlCacheProvider::MAX_KEYS_PER_QUERY) {
+                let rows: Vec<(CacheKey, String)> = chunk
+                    .iter()
+                    .map(|(key, value)| (SqlCacheProvider::columns(key.clone()), value.clone()))
+                    .collect();
 
-            exec_query.execute(&mut *pool).await?;
+                // set と同じく、消してから入れ直す
+                let delete_query = format!(
+                    "DELETE FROM rss_cache WHERE (raw_title, target_language, backend, schema_version) IN ({})",
+                    vec!["(?, ?, ?, ?)"; rows.len()].join(", ")
+                );
+                let mut delete_query = sqlx::query(&delete_query);
+                for (key, _) in rows.iter() {
+                    delete_query = delete_query
+                        .bind(key.raw_title.clone())
+                        .bind(key.to.clone())
+                        .bind(key.backend.clone())
+                        .bind(key.schema_version as i32);
+                }
+                delete_query.execute(&mut *transaction).await?;
+
+                let insert_query = format!(
+                    "INSERT INTO rss_cache (raw_title, target_language, backend, schema_version, translated_title) VALUES {}",
+                    vec!["(?, ?, ?, ?, ?)"; rows.len()].join(", ")
+                );
+                let mut insert_query = sqlx::query(&insert_query);
+                for (key, value) in rows {
+                    insert_query = insert_query
+                        .bind(key.raw_title)
+                        .bind(key.to)
+                        .bind(key.backend)
+                        .bind(key.schema_version as i32)
+                        .bind(value);
+                }
+                insert_query.execute(&mut *transaction).await?;
+            }
+            transaction.commit().await?;
 
             Ok(())
         })
     }
 
+    fn increment(&self, key: String, delta: u64) -> IncrementFuture<'_> {
+        let connection_pool = Arc::clone(&self.connection_pool);
+
+        Box::pin(async move {
+            let connection = connection_pool.lock().await;
+            let mut pool = connection.acquire().await?;
+
+            // 行がない場合の INSERT と足す UPDATE を1つの文で行うので、別のプロセスと同時に足しても失われない
+            //   (UPDATE と INSERT を分けると、同時に新しいキーを足したときに片方が重複エラーになる)
+            //   足した行はトランザクションが終わるまでロックされるので、続く SELECT では自分が足した後の値が読める
+            let key = SqlCacheProvider::columns(key);
+            let mut transaction = pool.begin().await?;
+            sqlx::query(
+                "INSERT INTO rss_cache (raw_title, target_language, backend, schema_version, translated_title) VALUES (?, ?, ?, ?, ?) ON DUPLICATE KEY UPDATE translated_title = CAST(CAST(translated_title AS UNSIGNED) + VALUES(translated_title) AS CHAR)",
+            )
+            .bind(key.raw_title.clone())
+            .bind(key.to.clone())
+            .bind(key.backend.clone())
+            .bind(key.schema_version as i32)
+            .bind(delta.to_string())
+            .execute(&mut *transaction)
+            .await?;
+            let row: (Vec<u8>,) = sqlx::query_as(
+                "SELECT `translated_title` FROM rss_cache WHERE raw_title = ? AND target_language = ? AND backend = ? AND schema_version = ? LIMIT 1",
+            )
+            .bind(key.raw_title)
+            .bind(key.to)
+            .bind(key.backend)
+            .bind(key.schema_version as i32)
+            .fetch_one(&mut *transaction)
+            .await?;
+            transaction.commit().await?;
+
+            let value = String::from_utf8_lossy(&row.0).trim().parse::<u64>()?;
+            Ok(value)
+        })
+    }
+
     fn clone_box(&self) -> Box<dyn CacheProvider> {
         Box::new(SqlCacheProvider {
             connection_pool: self.connection_pool.clone(),
@@ -95,13 +258,97 @@ impl CacheProvider for SqlCacheProvider {
 }
 
 impl SqlCacheProvider {
+    // 1回のクエリに含めるキーの数 (プレースホルダーの数の上限を超えないようにする)
+    const MAX_KEYS_PER_QUERY: usize = 200;
+
     pub fn new(options: SqlCacheProviderOptions) -> Self {
         SqlCacheProvider {
             connection_pool: Arc::new(Mutex::new(options.connection_pool)),
         }
     }
 
+    // キーをカラムに分ける
+    //   翻訳のキャッシュのキーでない場合 (集計などや以前の形式のキー) は言語とバックエンドを空にする
+    fn columns(key: String) -> CacheKey {
+        match CacheKey::decode(&key) {
+            Some(key) => key,
+            None => CacheKey {
+                schema_version: 1,
+                to: String::new(),
+                backend: String::new(),
+                raw_title: key,
+            },
+        }
+    }
+
     pub fn create_get_error(message: String) -> Box<dyn Error> {
         Box::new(GetError { message: message })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::any::{install_default_drivers, AnyPoolOptions};
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    // マイグレーションを適用したMySQLを TEST_DATABASE_URL で指定した場合だけ実行する
+    async fn provider() -> Option<SqlCacheProvider> {
+        let database_url = match std::env::var("TEST_DATABASE_URL") {
+            Ok(database_url) if !database_url.is_empty() => database_url,
+            _ => {
+                println!("skipped: TEST_DATABASE_URL is not set");
+                return None;
+            }
+        };
+        install_default_drivers();
+        let connection_pool = AnyPoolOptions::new()
+            .max_connections(4)
+            .connect(&database_url)
+            .await
+            .unwrap();
+        Some(SqlCacheProvider::new(SqlCacheProviderOptions {
+            connection_pool,
+        }))
+    }
+
+    #[tokio::test]
+    async fn concurrent_increments_are_not_lost() {
+        // 別々のプロセスの代わりに、接続プールを分けた2つの保存先から同じ新しいキーに足す
+        let (first, second) = match (provider().await, provider().await) {
+            (Some(first), Some(second)) => (first, second),
+            _ => return,
+        };
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        let key = format!("spend:test:{}:total", nanos);
+
+        let mut tasks = tokio::task::JoinSet::new();
+        for index in 0..20 {
+            let provider = match index % 2 {
+                0 => first.clone_box(),
+                _ => second.clone_box(),
+            };
+            let key = key.clone();
+            tasks.spawn(async move { provider.increment(key, 3).await.map_err(|e| e.to_string()) });
+        }
+        while let Some(joined) = tasks.join_next().await {
+            joined.unwrap().unwrap();
+        }
+
+        assert_eq!(first.increment(key.clone(), 0).await.unwrap(), 60);
+        assert_eq!(
+            second.get(key.clone()).await.unwrap(),
+            Some("60".to_string())
+        );
+
+        let connection = first.connection_pool.lock().await;
+        sqlx::query("DELETE FROM rss_cache WHERE raw_title = ?")
+            .bind(key)
+            .execute(&*connection)
+            .await
+            .unwrap();
+    }
+}
@@ -10,10 +10,13 @@ pub struct WebDavCacheProviderOptions {
     pub user_id: String,
     pub user_password: String,
     pub webdav_url: String,
+    // まとめて取得・保存する場合に同時に送るリクエストの数
+    pub batch_concurrency: usize,
 }
 
 pub struct WebDavCacheProvider {
     client: WebDavClient,
+    batch_concurrency: usize,
 }
 
 impl CacheProvider for WebDavCacheProvider {
@@ -59,9 +62,15 @@ impl CacheProvider for WebDavCacheProvider {
         })
     }
 
+    // 1件ずつのリクエストしかないので、get_many, set_many では同時に並べて送る
+    fn batch_concurrency(&self) -> usize {
+        self.batch_concurrency
+    }
+
     fn clone_box(&self) -> Box<dyn CacheProvider> {
         Box::new(WebDavCacheProvider {
             client: self.client.clone(),
+            batch_concurrency: self.batch_concurrency,
         })
     }
 }
@@ -74,6 +83,7 @@ impl WebDavCacheProvider {
                 &options.user_id,
                 &options.user_password,
             ),
+            batch_concurrency: options.batch_concurrency,
         }
     }
 }
@@ -1,6 +1,7 @@
 use std::str::FromStr;
 
 use atom_syndication::Category as AtomCategory;
+use atom_syndication::Content as AtomContent;
 use atom_syndication::FixedDateTime;
 use atom_syndication::Link as AtomLink;
 use atom_syndication::Person as AtomPerson;
@@ -8,6 +9,7 @@ use atom_syndication::Text as AtomText;
 use feed_rs::model::Feed;
 
 use feed_rs::model::Link;
+use feed_rs::model::Text;
 
 use super::feed_generator::FeedGenerator;
 
@@ -38,9 +40,18 @@ impl FeedGenerator for AtomGenerator {
                 .collect()
         }
 
+        // rs_feedのTextをcontent_typeに合わせてatom_syndication::Textに変換
+        fn to_text(text: Text) -> AtomText {
+            if text.content_type == mime::TEXT_HTML {
+                AtomText::html(text.content)
+            } else {
+                AtomText::plain(text.content)
+            }
+        }
+
         // フィードのタイトル
         let feed_title: AtomText = match feed.title {
-            Some(title) => AtomText::plain(title.content),
+            Some(title) => to_text(title),
             None => AtomText::plain("Untitled"),
         };
         // フィードのアイコン
@@ -113,7 +124,7 @@ impl FeedGenerator for AtomGenerator {
         for item in feed.entries.iter() {
             // アイテムのタイトル
             let item_title: AtomText = match item.clone().title {
-                Some(title) => AtomText::plain(title.content),
+                Some(title) => to_text(title),
                 None => AtomText::plain("Untitle"),
             };
             // アイテムのID
@@ -137,6 +148,24 @@ impl FeedGenerator for AtomGenerator {
                 }
                 None => AtomText::plain(""),
             };
+            // アイテムの本文
+            let item_content: Option<AtomContent> = match item.clone().content {
+                Some(content) => {
+                    let mut atom_content = AtomContent::default();
+                    atom_content.set_value(content.body);
+                    atom_content.set_src(content.src.map(|src| src.href));
+                    // content_typeに合わせて作る
+                    if content.content_type == mime::TEXT_HTML {
+                        atom_content.set_content_type("html".to_string());
+                    } else if content.content_type == mime::TEXT_PLAIN {
+                        atom_content.set_content_type("text".to_string());
+                    } else {
+                        atom_content.set_content_type(content.content_type.to_string());
+                    }
+                    Some(atom_content)
+                }
+                None => None,
+            };
             // アイテムのカテゴリ
             let item_category: Vec<AtomCategory> = item
                 .categories
@@ -179,6 +208,7 @@ impl FeedGenerator for AtomGenerator {
             }
             atom_entry.set_links(item_link);
             atom_entry.set_summary(item_description);
+            atom_entry.set_content(item_content);
             atom_entry.set_categories(item_category);
             atom_entry.set_authors(item_author);
             if item_published.is_some() {
@@ -205,3 +235,66 @@ impl AtomGenerator {
         AtomGenerator {}
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ATOM: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+  <title>Tom &amp; Jerry</title>
+  <id>urn:example:feed</id>
+  <updated>2024-01-01T00:00:00Z</updated>
+  <entry>
+    <title>It&apos;s &lt;new&gt; &amp; &quot;shiny&quot;</title>
+    <id>urn:example:1</id>
+    <updated>2024-01-01T00:00:00Z</updated>
+    <summary type="html">&lt;p&gt;A &amp;amp; B&lt;/p&gt;</summary>
+  </entry>
+</feed>"#;
+
+    fn parse(xml: &str) -> Feed {
+        feed_rs::parser::parse(xml.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn titles_round_trip_special_characters() {
+        let output = AtomGenerator::new().generate_feed(parse(ATOM));
+        let reparsed = parse(&output);
+
+        assert_eq!(reparsed.title.unwrap().content, "Tom & Jerry");
+        assert_eq!(
+            reparsed.entries[0].title.as_ref().unwrap().content,
+            "It's <new> & \"shiny\""
+        );
+    }
+
+    #[test]
+    fn translated_title_is_escaped_once() {
+        let mut feed = parse(ATOM);
+        feed.entries[0].title = Some(Text {
+            content_type: mime::TEXT_PLAIN,
+            src: None,
+            content: "トム & ジェリーの\"新作\"".to_string(),
+        });
+        let output = AtomGenerator::new().generate_feed(feed);
+
+        assert!(output.contains("トム &amp; ジェリーの"));
+        assert!(!output.contains("&amp;#"));
+        let reparsed = parse(&output);
+        assert_eq!(
+            reparsed.entries[0].title.as_ref().unwrap().content,
+            "トム & ジェリーの\"新作\""
+        );
+    }
+
+    #[test]
+    fn html_summary_keeps_markup() {
+        let output = AtomGenerator::new().generate_feed(parse(ATOM));
+        let reparsed = parse(&output);
+
+        let summary = reparsed.entries[0].summary.as_ref().unwrap();
+        assert_eq!(summary.content_type, mime::TEXT_HTML);
+        assert_eq!(summary.content, "<p>A &amp; B</p>");
+    }
+}
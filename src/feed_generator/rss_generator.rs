@@ -54,6 +54,11 @@ impl FeedGenerator for RssGenerator {
                 Some(description) => Some(description.content),
                 None => None,
             };
+            // アイテムの本文 (content:encoded)
+            let item_content = match item.content {
+                Some(content) => content.body,
+                None => None,
+            };
             //アイテムの更新日時
             let item_pub_date: Option<String> = match item.updated {
                 Some(updated) => Some(updated.to_rfc2822()),
@@ -91,6 +96,7 @@ impl FeedGenerator for RssGenerator {
                 .title(item_title)
                 .link(item_link)
                 .description(item_description)
+                .content(item_content)
                 .pub_date(item_pub_date)
                 .categories(item_category)
                 .author(item_author)
@@ -112,3 +118,72 @@ impl RssGenerator {
         RssGenerator {}
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use feed_rs::model::Text;
+
+    const RSS: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<rss version="2.0" xmlns:content="http://purl.org/rss/1.0/modules/content/">
+  <channel>
+    <title>Tom &amp; Jerry</title>
+    <link>https://example.com/</link>
+    <description>Cartoons &lt;3</description>
+    <item>
+      <title>It&apos;s &lt;new&gt; &amp; &quot;shiny&quot;</title>
+      <link>https://example.com/1</link>
+      <description>&lt;p&gt;A &amp;amp; B&lt;/p&gt;</description>
+      <content:encoded><![CDATA[<p>C &amp; D</p>]]></content:encoded>
+    </item>
+  </channel>
+</rss>"#;
+
+    fn parse(xml: &str) -> Feed {
+        feed_rs::parser::parse(xml.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn titles_round_trip_special_characters() {
+        let output = RssGenerator::new().generate_feed(parse(RSS));
+        let reparsed = parse(&output);
+
+        assert_eq!(reparsed.title.unwrap().content, "Tom & Jerry");
+        assert_eq!(
+            reparsed.entries[0].title.as_ref().unwrap().content,
+            "It's <new> & \"shiny\""
+        );
+    }
+
+    #[test]
+    fn translated_title_is_escaped_once() {
+        let mut feed = parse(RSS);
+        feed.entries[0].title = Some(Text {
+            content_type: mime::TEXT_PLAIN,
+            src: None,
+            content: "トム & ジェリーの\"新作\"".to_string(),
+        });
+        let output = RssGenerator::new().generate_feed(feed);
+
+        assert!(output.contains("トム &amp; ジェリーの"));
+        assert!(!output.contains("&amp;#"));
+        let reparsed = parse(&output);
+        assert_eq!(
+            reparsed.entries[0].title.as_ref().unwrap().content,
+            "トム & ジェリーの\"新作\""
+        );
+    }
+
+    #[test]
+    fn description_and_content_keep_markup() {
+        let output = RssGenerator::new().generate_feed(parse(RSS));
+        let reparsed = parse(&output);
+
+        let entry = &reparsed.entries[0];
+        assert_eq!(entry.summary.as_ref().unwrap().content, "<p>A &amp; B</p>");
+        assert_eq!(
+            entry.content.as_ref().unwrap().body.as_deref(),
+            Some("<p>C &amp; D</p>")
+        );
+    }
+}
@@ -3,3 +3,5 @@ pub mod translate;
 pub mod feed_generator;
 pub mod cache_provider;
 pub mod html_data;
+pub mod spend;
+pub mod translation_memory;
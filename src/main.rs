@@ -1,3 +1,4 @@
+use std::collections::{HashMap, HashSet};
 use std::time::Duration;
 
 use actix_web::HttpRequest;
@@ -5,24 +6,58 @@ use actix_web::{get, middleware::Logger, web, App, HttpResponse, HttpServer, Res
 use cache_provider::sql::{SqlCacheProvider, SqlCacheProviderOptions};
 use feed_rs::model::{FeedType, Text};
 use serde::Deserialize;
+use sha2::{Digest, Sha256};
 
 use rss_trans::rss as rtr;
 use rss_trans::translate;
+use rss_trans::translate::backend::{TranslatResult, TranslateOptions, TranslationBackend};
+use rss_trans::translate::batch::BatchLimits;
+use rss_trans::translate::deepl::{DeepLBackend, DeepLBackendOptions};
+use rss_trans::translate::detect::{detect_language, is_same_language};
+use rss_trans::translate::fallback::{FallbackBackend, FallbackBackendOptions};
+use rss_trans::translate::html_segment::{decode_entities, to_plain_text, HtmlDocument};
+use rss_trans::translate::google::{
+    GoogleTranslateApiVersion, GoogleTranslateBackend, GoogleTranslateBackendOptions,
+};
+use rss_trans::translate::libre_translate::{LibreTranslateBackend, LibreTranslateBackendOptions};
+use rss_trans::translate::llm::{LlmBackend, LlmBackendOptions, DEFAULT_PROMPT_TEMPLATE};
+use rss_trans::translate::output_mode::{OutputMode, TitleTemplates};
+use rss_trans::translate::pseudo::{PseudoBackend, PseudoBackendOptions, PseudoMode};
+use rss_trans::translate::rate_limit::RateLimiter;
+use rss_trans::translate::retry::{RequestPolicy, RetryPolicy};
+use rss_trans::translate::rewrite_rules::RewriteConfig;
+use rss_trans::translate::romanize::RomanizeBackend;
+use rss_trans::translate::term_protection::{MaskedText, TermProtectionConfig};
 use rss_trans::html_data;
 mod feed_generator;
 use feed_generator::atom_generator::AtomGenerator;
 use feed_generator::feed_generator::FeedGenerator;
 use feed_generator::rss_generator::RssGenerator;
 mod cache_provider;
-use cache_provider::provider::CacheProvider;
+mod spend;
+use spend::{SpendKey, SpendTracker, SpendTrackerOptions};
+mod translation_memory;
+use translation_memory::{MemoryMatch, TranslationMemory, TranslationMemoryOptions};
+use cache_provider::entry::CacheEntry;
+use cache_provider::file::{FileCacheProvider, FileCacheProviderOptions};
+use cache_provider::key::{get_many_migrating, CacheKey};
+use cache_provider::lru::{LruCacheProvider, LruCacheProviderOptions};
+use cache_provider::provider::{CacheProvider, DEFAULT_BATCH_CONCURRENCY};
+use cache_provider::redis::{RedisCacheProvider, RedisCacheProviderOptions};
 use cache_provider::s3::{S3CacheProvider, S3CacheProviderOptions};
 use cache_provider::webdav::{WebDavCacheProvider, WebDavCacheProviderOptions};
 use sqlx::any::install_default_drivers;
 
 struct AppState {
     rss_provider: rtr::RssProvider,
-    translate_provider: translate::TranslateProvider,
+    translate_provider: Box<dyn TranslationBackend>,
     translated_cache_provider: Option<Box<dyn CacheProvider>>,
+    title_templates: TitleTemplates,
+    term_protection: TermProtectionConfig,
+    rewrite_rules: RewriteConfig,
+    spend_tracker: SpendTracker,
+    translation_memory: Option<TranslationMemory>,
+    lru_cache_provider: Option<LruCacheProvider>,
 }
 
 #[get("/")]
@@ -31,16 +66,127 @@ async fn index() -> impl Responder {
     HttpResponse::Ok().body(html_data::HTMLIndexData)
 }
 
+// 翻訳APIに送った文字数の集計を返す
+#[get("/usage")]
+async fn usage(req: HttpRequest) -> impl Responder {
+    let spend_tracker = req
+        .app_data::<web::Data<AppState>>()
+        .unwrap()
+        .spend_tracker
+        .clone();
+
+    let report = spend_tracker.report().await;
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .body(serde_json::to_string(&report).unwrap())
+}
+
+// プロセス内のLRUキャッシュのヒット率などを返す
+#[get("/cache/stats")]
+async fn cache_stats(req: HttpRequest) -> impl Responder {
+    let lru_cache_provider = req
+        .app_data::<web::Data<AppState>>()
+        .unwrap()
+        .lru_cache_provider
+        .clone();
+
+    match lru_cache_provider {
+        Some(lru_cache_provider) => HttpResponse::Ok()
+            .content_type("application/json")
+            .body(serde_json::to_string(&lru_cache_provider.stats()).unwrap()),
+        None => HttpResponse::NotFound().body("Error (in-process cache is disabled)"),
+    }
+}
+
 #[derive(Deserialize)]
 struct RssReqQuery {
     url: String,
     to: Option<String>,
+    formality: Option<String>,
+    glossary: Option<String>,
+    model: Option<String>,
+    // 概要 (RSSのdescription, Atomのsummary) も翻訳する
+    translate_summary: Option<bool>,
+    // 本文 (RSSのcontent:encoded, Atomのcontent) も翻訳する
+    translate_content: Option<bool>,
+    // タイトルの見せ方 (translated, bilingual, original-first, romanized)
+    mode: Option<String>,
+    // {translated} と {original} を含むタイトルのテンプレート
+    template: Option<String>,
+}
+
+// タイトルをプレーンテキストにする
+//   HTMLのタイトルはタグを除き、エンティティはデコードしてから翻訳する (出力時にエスケープされる)
+fn title_text(title: &Text) -> String {
+    if title.content_type == mime::TEXT_HTML {
+        return to_plain_text(&title.content).trim().to_string();
+    }
+    decode_entities(&title.content).trim().to_string()
+}
+
+// 概要や本文から翻訳するテキストを取り出す
+//   HTMLの場合はタグを除いたテキストごとに分割する
+fn text_segments(content: &str, content_type: &mime::Mime) -> Vec<String> {
+    if *content_type == mime::TEXT_HTML {
+        return HtmlDocument::parse(content).segments();
+    }
+    let segment = content.trim();
+    if segment.is_empty() {
+        return Vec::new();
+    }
+    vec![segment.to_string()]
+}
+
+// 翻訳結果で概要や本文を置き換える
+fn translate_text(
+    content: &str,
+    content_type: &mime::Mime,
+    translated_texts: &HashMap<String, String>,
+) -> String {
+    if *content_type == mime::TEXT_HTML {
+        return HtmlDocument::parse(content).render(|segment| translated_texts.get(segment).cloned());
+    }
+    match translated_texts.get(content.trim()) {
+        Some(translated) => translated.trim().to_string(),
+        None => content.to_string(),
+    }
+}
+
+// TRANSLATION_BACKEND で指定できるバックエンドの名前
+const BACKEND_NAMES: [&str; 5] = ["google", "libretranslate", "deepl", "llm", "pseudo"];
+
+// カンマ区切りのバックエンドの名前を読み込む (指定がない場合はGoogle)
+//   綴りを間違えたものを別のバックエンドで翻訳しないように、知らない名前はエラーにする
+fn parse_backend_names(value: Option<&str>) -> Result<Vec<String>, String> {
+    let names: Vec<String> = value
+        .unwrap_or("")
+        .split(',')
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .collect();
+    if let Some(unknown) = names
+        .iter()
+        .find(|name| !BACKEND_NAMES.contains(&name.as_str()))
+    {
+        return Err(format!(
+            "unknown translation backend: {} (supported: {})",
+            unknown,
+            BACKEND_NAMES.join(", ")
+        ));
+    }
+    if names.is_empty() {
+        return Ok(vec!["google".to_string()]);
+    }
+    Ok(names)
 }
 
 #[derive(Clone)]
 struct TranslateTitle {
     pub raw: String,
     pub is_cached: bool,
+    // 翻訳先と同じ言語で書かれているため翻訳しない
+    pub is_same_language: bool,
+    pub source_language: Option<String>,
     pub translated: Option<String>,
 }
 
@@ -51,7 +197,7 @@ async fn rss(req: HttpRequest) -> impl Responder {
         .unwrap()
         .rss_provider
         .clone();
-    let mut translate_provider = req
+    let translate_provider = req
         .app_data::<web::Data<AppState>>()
         .unwrap()
         .translate_provider
@@ -61,6 +207,31 @@ async fn rss(req: HttpRequest) -> impl Responder {
         .unwrap()
         .translated_cache_provider
         .clone();
+    let title_templates = req
+        .app_data::<web::Data<AppState>>()
+        .unwrap()
+        .title_templates
+        .clone();
+    let term_protection = req
+        .app_data::<web::Data<AppState>>()
+        .unwrap()
+        .term_protection
+        .clone();
+    let rewrite_rules = req
+        .app_data::<web::Data<AppState>>()
+        .unwrap()
+        .rewrite_rules
+        .clone();
+    let spend_tracker = req
+        .app_data::<web::Data<AppState>>()
+        .unwrap()
+        .spend_tracker
+        .clone();
+    let translation_memory = req
+        .app_data::<web::Data<AppState>>()
+        .unwrap()
+        .translation_memory
+        .clone();
 
     // queryパラメータからurlを取得
     let req_query: RssReqQuery = match web::Query::<RssReqQuery>::from_query(req.query_string()) {
@@ -80,8 +251,48 @@ async fn rss(req: HttpRequest) -> impl Responder {
         None => "ja-JP".to_string(),
     };
 
+    // クエリパラメータからmodeを取得
+    let mode = match req_query.mode.clone() {
+        Some(mode) => match mode.parse::<OutputMode>() {
+            Ok(mode) => mode,
+            Err(e) => {
+                return HttpResponse::BadRequest().body(format!("Error (invalid mode): {}", e));
+            }
+        },
+        None => OutputMode::Translated,
+    };
+
+    // romanizedの場合は翻訳APIを使わずにタイトルをラテン文字に書き換える
+    //   翻訳ではないので、キャッシュや翻訳メモリ、予算の集計、用語集や書き換えのルールは使わない
+    let romanize = mode == OutputMode::Romanized;
+    let (translate_provider, translated_cache_provider, translation_memory) = match romanize {
+        true => (
+            Box::new(RomanizeBackend::new()) as Box<dyn TranslationBackend>,
+            None,
+            None,
+        ),
+        false => (
+            translate_provider,
+            translated_cache_provider,
+            translation_memory,
+        ),
+    };
+    let (term_protection, rewrite_rules, spend_tracker) = match romanize {
+        true => (
+            TermProtectionConfig::default(),
+            RewriteConfig::default(),
+            // 保存も予算もない集計に差し替えて、集計に含めないようにする
+            SpendTracker::new(SpendTrackerOptions {
+                cache_provider: None,
+                daily_budget_chars: None,
+                monthly_budget_chars: None,
+            }),
+        ),
+        false => (term_protection, rewrite_rules, spend_tracker),
+    };
+
     // URLからRSSを取得
-    let feeds = match rss_provider.get_rss_feeds(url).await {
+    let feeds = match rss_provider.get_rss_feeds(url.clone()).await {
         Ok(feeds) => feeds,
         Err(e) => {
             return HttpResponse::InternalServerError()
@@ -89,92 +300,334 @@ async fn rss(req: HttpRequest) -> impl Responder {
         }
     };
 
+    let translate_summary = !romanize && req_query.translate_summary.unwrap_or(false);
+    let translate_content = !romanize && req_query.translate_content.unwrap_or(false);
+
+    // 翻訳するテキストを集める (タイトルと、指定された場合は概要や本文)
+    let mut target_texts: Vec<String> = Vec::new();
+    for entry in feeds.entries.iter() {
+        if let Some(title) = entry.title.as_ref() {
+            target_texts.push(title_text(title));
+        }
+        if translate_summary {
+            if let Some(summary) = entry.summary.clone() {
+                target_texts.extend(text_segments(&summary.content, &summary.content_type));
+            }
+        }
+        if translate_content {
+            if let Some(content) = entry.content.clone() {
+                if let Some(body) = content.body {
+                    target_texts.extend(text_segments(&body, &content.content_type));
+                }
+            }
+        }
+    }
+    // 同じテキストは1回だけ翻訳する
+    let mut seen_texts = HashSet::new();
+    target_texts.retain(|text| seen_texts.insert(text.clone()));
+
     // 翻訳用のタイトル集合を用意
-    let target_titles: Vec<TranslateTitle> = feeds
-        .clone()
-        .entries
-        .iter()
-        .map(|entry| {
-            let raw = entry.title.clone().unwrap();
+    let target_titles: Vec<TranslateTitle> = target_texts
+        .into_iter()
+        .map(|raw| {
+            // 翻訳元の言語をローカルで判定しておく
+            let source_language = detect_language(&raw);
+            let is_same_language = !romanize
+                && source_language
+                    .as_deref()
+                    .map(|source_language| is_same_language(source_language, &to))
+                    .unwrap_or(false);
             TranslateTitle {
-                raw: raw.content,
+                raw,
                 is_cached: false,
+                is_same_language,
+                source_language,
                 translated: None,
             }
         })
         .collect();
-    // キャッシュから翻訳済みのタイトルを取得
-    let mut translated_titles: Vec<TranslateTitle> = Vec::new();
-    for target_title in target_titles.iter() {
-        if translated_cache_provider.clone().is_none() {
-            translated_titles.push(TranslateTitle {
-                raw: target_title.raw.clone(),
-                is_cached: false,
-                translated: None,
-            });
-            continue;
+    // 翻訳の前後に適用する書き換えのルールと、用語集や翻訳しない用語 (フィードごとのものも含む)
+    let rewriter = rewrite_rules.rewriter_for(&url, &to);
+    let term_protector = term_protection.protector_for(&url, &to);
+
+    // キャッシュのキーに含めるバックエンド (モデルを指定した場合は別の翻訳として扱う)
+    let mut cache_backend = match req_query.model.as_deref() {
+        Some(model) => format!(
+            "{}:{}:{}",
+            translate_provider.name(),
+            translate_provider.version(),
+            model
+        ),
+        None => format!(
+            "{}:{}",
+            translate_provider.name(),
+            translate_provider.version()
+        ),
+    };
+    // 敬語や用語集、翻訳しない用語、翻訳前の書き換えのルールで翻訳結果が変わるので、指定されている場合はそのハッシュも含める
+    let translation_settings = [
+        ("formality", req_query.formality.clone().unwrap_or_default()),
+        ("glossary", req_query.glossary.clone().unwrap_or_default()),
+        ("terms", term_protector.fingerprint()),
+        ("rewrite", rewriter.source_fingerprint()),
+    ];
+    if translation_settings
+        .iter()
+        .any(|(_, setting)| !setting.is_empty())
+    {
+        let mut hasher = Sha256::new();
+        for (name, setting) in translation_settings {
+            hasher.update(format!("{}={}\n", name, setting).as_bytes());
         }
+        let hashed_settings = format!("{:x}", hasher.finalize());
+        cache_backend = format!("{}:{}", cache_backend, &hashed_settings[..16]);
+    }
 
-        let raw: String = target_title.raw.clone();
-        let translated_cache_provider = translated_cache_provider.clone().unwrap();
-        let cached_title = translated_cache_provider.get(raw).await;
-        if cached_title.is_err() {
-            let err = cached_title.err().unwrap();
+    // キャッシュから翻訳済みのタイトルをまとめて取得
+    let cache_keys: Vec<CacheKey> = target_titles
+        .iter()
+        .filter(|title| !title.is_same_language)
+        .map(|title| CacheKey::new(&to, &cache_backend, &title.raw))
+        .collect();
+    let mut cached_titles: HashMap<String, String> = HashMap::new();
+    if let Some(translated_cache_provider) = translated_cache_provider.clone() {
+        let cached_values =
+            get_many_migrating(translated_cache_provider.as_ref(), &cache_keys).await;
+        if cached_values.is_err() {
+            let err = cached_values.err().unwrap();
             // ログにもエラーを出す
             println!("Error (failed to get title from cache): {}", err);
             return HttpResponse::InternalServerError()
                 .body(format!("Error (failed to get title from cache): {}", err));
         }
-        let cached_title = cached_title.unwrap();
-        println!("cached_title: {:?}", cached_title);
-        match cached_title {
-            Some(title) => translated_titles.push(TranslateTitle {
-                raw: target_title.raw.clone(),
+        for (cache_key, cached_value) in cache_keys.iter().zip(cached_values.unwrap()) {
+            if let Some(cached_value) = cached_value {
+                cached_titles.insert(cache_key.raw_title.clone(), cached_value);
+            }
+        }
+        println!(
+            "cached_titles: {}/{}",
+            cached_titles.len(),
+            cache_keys.len()
+        );
+    }
+
+    let mut translated_titles: Vec<TranslateTitle> = Vec::new();
+    // 翻訳メモリから使い回した翻訳 (元のテキスト, 見つかった翻訳)
+    let mut memory_matches: Vec<(String, MemoryMatch)> = Vec::new();
+    for target_title in target_titles.iter() {
+        // 翻訳先と同じ言語であればそのまま使う
+        if target_title.is_same_language {
+            translated_titles.push(TranslateTitle {
+                translated: Some(target_title.raw.clone()),
+                ..target_title.clone()
+            });
+            continue;
+        }
+
+        if let Some(value) = cached_titles.remove(&target_title.raw) {
+            let entry = CacheEntry::decode(value);
+            translated_titles.push(TranslateTitle {
                 is_cached: true,
-                translated: Some(title),
-            }),
-            None => translated_titles.push(TranslateTitle {
-                raw: target_title.raw.clone(),
-                is_cached: false,
-                translated: None,
-            }),
+                source_language: entry
+                    .source_language
+                    .or(target_title.source_language.clone()),
+                translated: Some(entry.translated),
+                ..target_title.clone()
+            });
+            continue;
+        }
+
+        // キャッシュにない場合は翻訳メモリから似ているタイトルの翻訳を探す
+        if let Some(translation_memory) = translation_memory.as_ref() {
+            let memory_match = translation_memory
+                .lookup(&cache_backend, &to, &target_title.raw)
+                .await;
+            if let Some(memory_match) = memory_match {
+                println!(
+                    "translation memory hit: {:?} -> {:?} (score: {:.3})",
+                    target_title.raw, memory_match.source, memory_match.score
+                );
+                translated_titles.push(TranslateTitle {
+                    is_cached: true,
+                    translated: Some(memory_match.translated.clone()),
+                    ..target_title.clone()
+                });
+                memory_matches.push((target_title.raw.clone(), memory_match));
+                continue;
+            }
         }
+
+        translated_titles.push(target_title.clone());
     }
 
     // キャッシュにないタイトルを翻訳
     let translate_target_titles: Vec<String> = translated_titles
         .iter()
-        .filter(|title| !title.is_cached)
+        .filter(|title| !title.is_cached && !title.is_same_language)
         .map(|title| title.raw.clone())
         .collect();
 
-    let additional_translated_titles = if translate_target_titles.len() > 0 {
-        match translate_provider
-            .translate(translate_target_titles, to)
-            .await
-        {
-            Ok(translated) => translated,
+    // 書き換えのルールを適用し、用語集や翻訳しない用語をプレースホルダーに置き換えてから翻訳する
+    let masked_titles: Vec<MaskedText> = translate_target_titles
+        .iter()
+        .map(|title| term_protector.mask(&rewriter.rewrite_source(title)))
+        .collect();
+
+    // 予算の残りに収まる分だけ翻訳APIに送り、超えた分は原文のまま返す
+    let mut unique_texts: Vec<String> = Vec::new();
+    let mut seen_masked_texts: HashSet<String> = HashSet::new();
+    for masked in masked_titles.iter() {
+        if seen_masked_texts.insert(masked.text.clone()) {
+            unique_texts.push(masked.text.clone());
+        }
+    }
+    let text_chars: Vec<u64> = unique_texts
+        .iter()
+        .map(|text| text.chars().count() as u64)
+        .collect();
+    let reserved = spend_tracker.reserve(&text_chars).await;
+    let mut send_texts: Vec<String> = Vec::new();
+    let mut reserved_chars: u64 = 0;
+    for ((text, chars), reserved) in unique_texts.into_iter().zip(text_chars).zip(reserved) {
+        if reserved {
+            reserved_chars += chars;
+            send_texts.push(text);
+        }
+    }
+    if send_texts.len() < seen_masked_texts.len() {
+        println!(
+            "translation budget exceeded, skipped {} texts",
+            seen_masked_texts.len() - send_texts.len()
+        );
+    }
+
+    // 翻訳に失敗したテキスト (原文のまま返す)
+    let mut failed_titles: Vec<String> = Vec::new();
+    // 置き換え後の文字列 -> 翻訳できなかった理由
+    let mut translate_errors: HashMap<String, String> = HashMap::new();
+    let mut translated_by_masked: HashMap<String, TranslatResult> = HashMap::new();
+    if !send_texts.is_empty() {
+        let translated = translate_provider
+            .translate(
+                send_texts.clone(),
+                TranslateOptions {
+                    to: to.clone(),
+                    formality: req_query.formality.clone(),
+                    glossary_id: req_query.glossary.clone(),
+                    model: req_query.model.clone(),
+                    mime_type: None,
+                },
+            )
+            .await;
+        match translated {
+            // 一部だけ失敗した場合も、翻訳できたものは使う
+            Ok(translated) => {
+                for (masked_text, translated) in send_texts.iter().zip(translated) {
+                    match translated {
+                        Ok(translated) => {
+                            translated_by_masked.insert(masked_text.clone(), translated);
+                        }
+                        Err(e) => {
+                            translate_errors.insert(masked_text.clone(), e.to_string());
+                        }
+                    }
+                }
+            }
             Err(e) => {
-                return HttpResponse::InternalServerError()
-                    .body(format!("Error (failed to translation): {}", e));
+                println!("Error (failed to translation): {}", e);
+                for masked_text in send_texts.iter() {
+                    translate_errors.insert(masked_text.clone(), e.to_string());
+                }
             }
         }
-    } else {
-        Vec::new()
-    };
+    }
+    // 翻訳APIに送った文字数をバックエンドごとに記録する
+    let mut spent_chars: HashMap<String, u64> = HashMap::new();
+    for (masked_text, translated) in translated_by_masked.iter() {
+        *spent_chars.entry(translated.backend.clone()).or_insert(0) +=
+            masked_text.chars().count() as u64;
+    }
+    let spent: Vec<(SpendKey, u64)> = spent_chars
+        .into_iter()
+        .map(|(backend, chars)| {
+            let key = SpendKey {
+                backend,
+                to: to.clone(),
+                feed: url.clone(),
+            };
+            (key, chars)
+        })
+        .collect();
+    // 確保した分を戻してから、翻訳できた分だけを記録する
+    spend_tracker.record(reserved_chars, spent).await;
 
-    // 追加で翻訳したタイトルをキャッシュに保存
-    if translated_cache_provider.clone().is_some() {
-        let translated_cache_provider = translated_cache_provider.clone().unwrap();
+    // プレースホルダーを元に戻す
+    //   置き換え後の文字列が同じであれば翻訳も同じなので、置き換え後の文字列で対応付ける
+    let mut additional_translated_titles: Vec<TranslatResult> = Vec::new();
+    for (raw, masked) in translate_target_titles.iter().zip(masked_titles.iter()) {
+        match translated_by_masked.get(&masked.text) {
+            Some(translated) => additional_translated_titles.push(TranslatResult {
+                translated: term_protector.unmask(masked, &translated.translated),
+                raw_text: raw.clone(),
+                backend: translated.backend.clone(),
+            }),
+            None => {
+                let reason = match send_texts.contains(&masked.text) {
+                    true => translate_errors
+                        .get(&masked.text)
+                        .cloned()
+                        .unwrap_or("no translation was returned".to_string()),
+                    false => "translation budget exceeded".to_string(),
+                };
+                println!("Error (failed to translate {:?}): {}", raw, reason);
+                failed_titles.push(raw.clone());
+            }
+        }
+    }
 
+    // 追加で翻訳したタイトルをキャッシュにまとめて保存
+    if let Some(translated_cache_provider) = translated_cache_provider.clone() {
+        let source_language_of = |raw: &str| {
+            target_titles
+                .iter()
+                .find(|title| title.raw == raw)
+                .and_then(|title| title.source_language.clone())
+        };
+        let mut cache_entries: Vec<(String, String)> = Vec::new();
         for translated_title in additional_translated_titles.iter() {
-            let key = translated_title.raw_text.clone();
-            let value = translated_title.translated.clone();
-            // 終了を待たないで非同期で行う
-            //   エラーが発生した場合はログに残す
-            let translated_cache_provider = translated_cache_provider.clone();
+            let raw = &translated_title.raw_text;
+            // 判定した翻訳元の言語も一緒に保存する
+            let value = CacheEntry {
+                translated: translated_title.translated.clone(),
+                source_language: source_language_of(raw),
+                backend: Some(translated_title.backend.clone()),
+                match_score: None,
+            };
+            cache_entries.push((
+                CacheKey::new(&to, &cache_backend, raw).encode(),
+                value.encode(),
+            ));
+        }
+        // 翻訳メモリから使い回した翻訳も、どれだけ似ていたかと一緒に保存する
+        for (raw, memory_match) in memory_matches.iter() {
+            let value = CacheEntry {
+                translated: memory_match.translated.clone(),
+                source_language: source_language_of(raw),
+                backend: Some("memory".to_string()),
+                match_score: Some(memory_match.score),
+            };
+            cache_entries.push((
+                CacheKey::new(&to, &cache_backend, raw).encode(),
+                value.encode(),
+            ));
+        }
+
+        // 終了を待たないで非同期で行う
+        //   エラーが発生した場合はログに残す
+        if !cache_entries.is_empty() {
             tokio::spawn(async move {
-                let set_result = translated_cache_provider.set(key, value).await;
+                let set_result = translated_cache_provider.set_many(cache_entries).await;
                 if set_result.is_err() {
                     let err = set_result.err().unwrap();
                     println!("Error (failed to set title to cache): {}", err);
@@ -183,48 +636,92 @@ async fn rss(req: HttpRequest) -> impl Responder {
         }
     }
 
+    // 翻訳APIで翻訳したものを翻訳メモリに追加
+    if let Some(translation_memory) = translation_memory.as_ref() {
+        let translations = additional_translated_titles
+            .iter()
+            .map(|title| (title.raw_text.clone(), title.translated.clone()))
+            .collect();
+        translation_memory.record(&cache_backend, &to, translations);
+    }
+
     // 翻訳済みのタイトルを集合に追加
     let saved_translated_titles: Vec<TranslateTitle> = translated_titles
         .iter()
         .map(|translated_title| {
             let raw = translated_title.raw.clone();
-            if translated_title.is_cached {
-                return TranslateTitle {
-                    raw: raw.clone(),
-                    is_cached: true,
-                    translated: translated_title.translated.clone(),
-                };
+            if translated_title.is_cached || translated_title.is_same_language {
+                return translated_title.clone();
             }
 
+            // 翻訳に失敗したものは未翻訳のままにする
             let translated = additional_translated_titles
                 .iter()
                 .find(|title| title.raw_text == raw)
-                .unwrap()
-                .translated
-                .clone();
+                .map(|title| title.translated.clone());
 
             TranslateTitle {
-                raw: raw,
-                is_cached: true,
-                translated: Some(translated),
+                is_cached: translated.is_some(),
+                translated,
+                ..translated_title.clone()
             }
         })
         .collect();
 
+    // 翻訳後の書き換えのルールを適用する
+    //   キャッシュには書き換える前の翻訳を保存しているので、ルールを変えた場合もすぐに反映される
+    let translated_texts: HashMap<String, String> = saved_translated_titles
+        .into_iter()
+        .filter_map(|title| {
+            let translated = match title.is_same_language {
+                true => title.translated,
+                false => title
+                    .translated
+                    .map(|translated| rewriter.rewrite_translated(&translated)),
+            };
+            translated.map(|translated| (title.raw, translated))
+        })
+        .collect();
+
     // タイトルを翻訳済みに差し替える
     let new_feeds = feeds.clone();
     let new_items = new_feeds
         .entries
         .iter()
-        .zip(saved_translated_titles.iter())
-        .map(|(item, translated_title)| {
+        .map(|item| {
             let mut new_item = item.clone();
-            let title = translated_title.translated.clone().unwrap();
-            new_item.title = Some(Text {
-                content_type: mime::TEXT_PLAIN,
-                src: None,
-                content: title.trim().to_string(),
-            });
+            if let Some(title) = item.title.as_ref() {
+                let original = title_text(title);
+                if let Some(translated) = translated_texts.get(&original) {
+                    // modeに合わせて元のタイトルも並べる
+                    let content = title_templates.format(
+                        mode,
+                        req_query.template.as_deref(),
+                        &original,
+                        translated,
+                    );
+                    new_item.title = Some(Text {
+                        content_type: mime::TEXT_PLAIN,
+                        src: None,
+                        content: content.trim().to_string(),
+                    });
+                }
+            }
+            // 概要と本文は元の形式 (HTMLかテキストか) のまま差し替える
+            if translate_summary {
+                if let Some(summary) = new_item.summary.as_mut() {
+                    summary.content =
+                        translate_text(&summary.content, &summary.content_type, &translated_texts);
+                }
+            }
+            if translate_content {
+                if let Some(content) = new_item.content.as_mut() {
+                    if let Some(body) = content.body.clone() {
+                        content.body =
+                            Some(translate_text(&body, &content.content_type, &translated_texts));
+                    }
+                }
+            }
             new_item
         })
         .collect();
@@ -248,13 +745,55 @@ async fn rss(req: HttpRequest) -> impl Responder {
     let feed_str = unwraped_generator.generate_feed(feeds);
     let content_type = unwraped_generator.content_type();
 
-    HttpResponse::Ok().content_type(content_type).body(feed_str)
+    // 翻訳できなかった件数をヘッダーで返す
+    HttpResponse::Ok()
+        .content_type(content_type)
+        .insert_header(("X-Translation-Failures", failed_titles.len().to_string()))
+        .body(feed_str)
 }
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    let service_account_file = std::env::var("GOOGLE_APPLICATION_CREDENTIALS").unwrap();
-    let project_id = std::env::var("GOOGLE_CLOUD_PROJECT").unwrap();
+    let translation_backend = std::env::var("TRANSLATION_BACKEND");
+    let translation_backend_timeout_ms = std::env::var("TRANSLATION_BACKEND_TIMEOUT_MS");
+    let translation_batch_max_segments = std::env::var("TRANSLATION_BATCH_MAX_SEGMENTS");
+    let translation_batch_max_chars = std::env::var("TRANSLATION_BATCH_MAX_CHARS");
+    let translation_concurrency = std::env::var("TRANSLATION_CONCURRENCY");
+    let translation_max_retries = std::env::var("TRANSLATION_MAX_RETRIES");
+    let translation_retry_base_delay_ms = std::env::var("TRANSLATION_RETRY_BASE_DELAY_MS");
+    let translation_retry_max_delay_ms = std::env::var("TRANSLATION_RETRY_MAX_DELAY_MS");
+    let translation_rate_limit_rps = std::env::var("TRANSLATION_RATE_LIMIT_RPS");
+    let translation_rate_limit_chars_per_minute =
+        std::env::var("TRANSLATION_RATE_LIMIT_CHARS_PER_MINUTE");
+
+    let service_account_file = std::env::var("GOOGLE_APPLICATION_CREDENTIALS");
+    let project_id = std::env::var("GOOGLE_CLOUD_PROJECT");
+    let gce_metadata_host = std::env::var("GCE_METADATA_HOST");
+    let google_translate_api_version = std::env::var("GOOGLE_TRANSLATE_API_VERSION");
+    let google_translate_api_url = std::env::var("GOOGLE_TRANSLATE_API_URL");
+    let google_translate_location = std::env::var("GOOGLE_TRANSLATE_LOCATION");
+    let google_translate_model = std::env::var("GOOGLE_TRANSLATE_MODEL");
+    let google_translate_glossary_id = std::env::var("GOOGLE_TRANSLATE_GLOSSARY_ID");
+    let google_translate_mime_type = std::env::var("GOOGLE_TRANSLATE_MIME_TYPE");
+
+    let libretranslate_url = std::env::var("LIBRETRANSLATE_URL");
+    let libretranslate_api_key = std::env::var("LIBRETRANSLATE_API_KEY");
+
+    let deepl_auth_key = std::env::var("DEEPL_AUTH_KEY");
+    let deepl_api_url = std::env::var("DEEPL_API_URL");
+    let deepl_formality = std::env::var("DEEPL_FORMALITY");
+    let deepl_glossary_id = std::env::var("DEEPL_GLOSSARY_ID");
+
+    let llm_api_url = std::env::var("LLM_API_URL");
+    let llm_api_key = std::env::var("LLM_API_KEY");
+    let llm_model = std::env::var("LLM_MODEL");
+    let llm_temperature = std::env::var("LLM_TEMPERATURE");
+    let llm_prompt_template_file = std::env::var("LLM_PROMPT_TEMPLATE_FILE");
+
+    let pseudo_mode = std::env::var("PSEUDO_MODE");
+    let pseudo_dictionary_file = std::env::var("PSEUDO_DICTIONARY_FILE");
+    let pseudo_latency_ms = std::env::var("PSEUDO_LATENCY_MS");
+    let pseudo_failure_rate = std::env::var("PSEUDO_FAILURE_RATE");
 
     let cache_mode = std::env::var("CACHE_MODE");
 
@@ -270,15 +809,224 @@ async fn main() -> std::io::Result<()> {
 
     let database_url = std::env::var("DATABASE_URL");
 
+    let cache_file_directory = std::env::var("CACHE_FILE_DIRECTORY");
+    let cache_file_compaction_interval_secs = std::env::var("CACHE_FILE_COMPACTION_INTERVAL_SECS");
+    let cache_file_max_age_days = std::env::var("CACHE_FILE_MAX_AGE_DAYS");
+
+    let redis_url = std::env::var("REDIS_URL");
+    let redis_key_prefix = std::env::var("REDIS_KEY_PREFIX");
+    let redis_ttl_secs = std::env::var("REDIS_TTL_SECS");
+    let redis_max_connections = std::env::var("REDIS_MAX_CONNECTIONS");
+
+    let cache_batch_concurrency = std::env::var("CACHE_BATCH_CONCURRENCY");
+    let cache_lru_max_entries = std::env::var("CACHE_LRU_MAX_ENTRIES");
+    let cache_lru_max_bytes = std::env::var("CACHE_LRU_MAX_BYTES");
+
+    let bilingual_template = std::env::var("BILINGUAL_TEMPLATE");
+    let original_first_template = std::env::var("ORIGINAL_FIRST_TEMPLATE");
+
+    let term_protection_file = std::env::var("TERM_PROTECTION_FILE");
+    let rewrite_rules_file = std::env::var("REWRITE_RULES_FILE");
+
+    let translation_daily_char_budget = std::env::var("TRANSLATION_DAILY_CHAR_BUDGET");
+    let translation_monthly_char_budget = std::env::var("TRANSLATION_MONTHLY_CHAR_BUDGET");
+
+    let translation_memory_threshold = std::env::var("TRANSLATION_MEMORY_THRESHOLD");
+    let translation_memory_max_entries = std::env::var("TRANSLATION_MEMORY_MAX_ENTRIES");
+
     env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
 
     let rss_provider = rtr::RssProvider::new();
-    let translate_provider =
-        translate::TranslateProvider::new(translate::TranslateProviderInitConfig {
-            project_id: project_id.clone(),
-            service_account_json: service_account_file,
-        });
+    // バックエンドごとのデフォルト値を環境変数で上書きする
+    let batch_limits = |defaults: BatchLimits| BatchLimits {
+        max_segments: translation_batch_max_segments
+            .as_deref()
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(defaults.max_segments),
+        max_chars: translation_batch_max_chars
+            .as_deref()
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(defaults.max_chars),
+        concurrency: translation_concurrency
+            .as_deref()
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(defaults.concurrency),
+    };
+
+    // 流量制限はプロセス全体で1つのバケットを共有する
+    let default_retry_policy = RetryPolicy::default();
+    let request_policy = RequestPolicy {
+        retry: RetryPolicy {
+            max_retries: translation_max_retries
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(default_retry_policy.max_retries),
+            base_delay: translation_retry_base_delay_ms
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .map(Duration::from_millis)
+                .unwrap_or(default_retry_policy.base_delay),
+            max_delay: translation_retry_max_delay_ms
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .map(Duration::from_millis)
+                .unwrap_or(default_retry_policy.max_delay),
+        },
+        rate_limiter: RateLimiter::new(
+            translation_rate_limit_rps
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(0.0),
+            translation_rate_limit_chars_per_minute
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(0.0),
+        ),
+    };
+
+    // カンマ区切りで複数指定した場合は、先頭から順にフォールバックする
+    let backend_names = match parse_backend_names(translation_backend.as_deref().ok()) {
+        Ok(backend_names) => backend_names,
+        Err(e) => {
+            return Err(std::io::Error::other(format!(
+                "Error (invalid TRANSLATION_BACKEND): {}",
+                e
+            )));
+        }
+    };
+
+    // Googleは認証情報の検出とプロジェクトIDの解決に通信が必要なので先に作っておく
+    //   解決できなかった場合はパニックさせずに起動時のエラーにする
+    let uses_google = backend_names.iter().any(|name| name == "google");
+    let google_backend = match uses_google {
+        true => {
+            let options = GoogleTranslateBackendOptions {
+                project_id: project_id.ok().filter(|project_id| !project_id.is_empty()),
+                credentials_file: service_account_file.ok().filter(|file| !file.is_empty()),
+                // テスト用にメタデータサーバーの向き先を変えられるようにする
+                metadata_server_url: gce_metadata_host.ok()
+                    .filter(|host| !host.is_empty())
+                    .map(|host| format!("http://{}/", host)),
+                endpoint_url: google_translate_api_url.ok().filter(|url| !url.is_empty()),
+                api_version: match google_translate_api_version.as_deref() {
+                    Ok("v3") => GoogleTranslateApiVersion::V3,
+                    _ => GoogleTranslateApiVersion::V2,
+                },
+                location: google_translate_location.ok()
+                    .filter(|location| !location.is_empty())
+                    .unwrap_or("global".to_string()),
+                model: google_translate_model.ok().filter(|model| !model.is_empty()),
+                glossary_id: google_translate_glossary_id.ok().filter(|id| !id.is_empty()),
+                mime_type: google_translate_mime_type.ok().filter(|mime| !mime.is_empty()),
+                batch_limits: batch_limits(GoogleTranslateBackend::DEFAULT_BATCH_LIMITS),
+                request_policy: request_policy.clone(),
+            };
+            match GoogleTranslateBackend::new(options).await {
+                Ok(backend) => Some(backend),
+                Err(e) => {
+                    return Err(std::io::Error::other(format!(
+                        "Error (failed to set up google translation backend): {}",
+                        e
+                    )));
+                }
+            }
+        }
+        false => None,
+    };
+
+    let build_backend = |name: &str| -> Box<dyn TranslationBackend> {
+        match name {
+            "libretranslate" => Box::new(LibreTranslateBackend::new(LibreTranslateBackendOptions {
+                endpoint_url: libretranslate_url.clone().unwrap(),
+                api_key: libretranslate_api_key.clone().ok().filter(|key| !key.is_empty()),
+                batch_limits: batch_limits(LibreTranslateBackend::DEFAULT_BATCH_LIMITS),
+                request_policy: request_policy.clone(),
+            })),
+            "deepl" => Box::new(DeepLBackend::new(DeepLBackendOptions {
+                auth_key: deepl_auth_key.clone().unwrap(),
+                endpoint_url: deepl_api_url.clone().ok().filter(|url| !url.is_empty()),
+                formality: deepl_formality.clone().ok().filter(|formality| !formality.is_empty()),
+                glossary_id: deepl_glossary_id.clone().ok().filter(|id| !id.is_empty()),
+                batch_limits: batch_limits(DeepLBackend::DEFAULT_BATCH_LIMITS),
+                request_policy: request_policy.clone(),
+            })),
+            "llm" => {
+                // プロンプトはファイルで差し替えられるようにする
+                let prompt_template = match llm_prompt_template_file.clone() {
+                    Ok(path) if !path.is_empty() => std::fs::read_to_string(path).unwrap(),
+                    _ => DEFAULT_PROMPT_TEMPLATE.to_string(),
+                };
+                Box::new(LlmBackend::new(LlmBackendOptions {
+                    endpoint_url: llm_api_url.clone().unwrap(),
+                    api_key: llm_api_key.clone().ok().filter(|key| !key.is_empty()),
+                    model: llm_model.clone().unwrap(),
+                    temperature: llm_temperature.clone().ok()
+                        .and_then(|temperature| temperature.parse().ok())
+                        .unwrap_or(0.0),
+                    prompt_template,
+                    batch_limits: batch_limits(LlmBackend::DEFAULT_BATCH_LIMITS),
+                    request_policy: request_policy.clone(),
+                }))
+            }
+            // 外部のAPIを使わずにローカルで動作確認するためのバックエンド
+            "pseudo" => {
+                let dictionary = match pseudo_dictionary_file.clone() {
+                    Ok(path) if !path.is_empty() => {
+                        serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap()
+                    }
+                    _ => HashMap::new(),
+                };
+                Box::new(PseudoBackend::new(PseudoBackendOptions {
+                    mode: match pseudo_mode.as_deref() {
+                        Ok(mode) if !mode.is_empty() => mode.parse::<PseudoMode>().unwrap(),
+                        _ => PseudoMode::Brackets,
+                    },
+                    dictionary,
+                    latency: Duration::from_millis(
+                        pseudo_latency_ms
+                            .clone()
+                            .ok()
+                            .and_then(|value| value.parse().ok())
+                            .unwrap_or(0),
+                    ),
+                    failure_rate: pseudo_failure_rate
+                        .clone()
+                        .ok()
+                        .and_then(|value| value.parse().ok())
+                        .unwrap_or(0.0),
+                    batch_limits: batch_limits(PseudoBackend::DEFAULT_BATCH_LIMITS),
+                }))
+            }
+            // 名前は parse_backend_names で確認済みなので残りはGoogleだけ
+            _ => Box::new(google_backend.clone().unwrap()),
+        }
+    };
+    let translate_provider: Box<dyn TranslationBackend> = match backend_names.len() {
+        1 => build_backend(&backend_names[0]),
+        _ => Box::new(FallbackBackend::new(FallbackBackendOptions {
+            backends: backend_names
+                .iter()
+                .map(|name| build_backend(name))
+                .collect(),
+            timeout: translation_backend_timeout_ms
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .map(Duration::from_millis),
+        })),
+    };
+    println!(
+        "translation backend: {} ({})",
+        translate_provider.name(),
+        translate_provider.version()
+    );
 
+    let cache_batch_concurrency = cache_batch_concurrency
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(DEFAULT_BATCH_CONCURRENCY);
     let translated_cache_provider: Option<Box<dyn CacheProvider>> = match cache_mode {
         Ok(cache_mode) => match cache_mode.as_str() {
             "webdav" => Some(Box::new(WebDavCacheProvider::new(
@@ -286,6 +1034,7 @@ async fn main() -> std::io::Result<()> {
                     user_id: webdav_user_id.unwrap().clone(),
                     user_password: webdav_user_password.unwrap().clone(),
                     webdav_url: webdav_url.unwrap().clone(),
+                    batch_concurrency: cache_batch_concurrency,
                 },
             ))),
             "s3" => Some(Box::new(S3CacheProvider::new(S3CacheProviderOptions {
@@ -294,6 +1043,7 @@ async fn main() -> std::io::Result<()> {
                 secret_key: aws_secret_key.unwrap(),
                 bucket_name: s3_bucket_name.unwrap(),
                 endpoint_url: s3_endpoint_url.unwrap(),
+                batch_concurrency: cache_batch_concurrency,
             }))),
             "rdbms" => {
                 let database_uri = database_url.unwrap();
@@ -314,15 +1064,144 @@ async fn main() -> std::io::Result<()> {
                     connection_pool: connection,
                 })))
             }
+            "file" => {
+                let directory = cache_file_directory
+                    .ok()
+                    .filter(|directory| !directory.is_empty())
+                    .unwrap_or("./cache".to_string());
+                println!("cache directory: {}", directory);
+
+                let provider = FileCacheProvider::new(FileCacheProviderOptions {
+                    directory: directory.into(),
+                    batch_concurrency: cache_batch_concurrency,
+                    max_age: cache_file_max_age_days
+                        .ok()
+                        .and_then(|value| value.parse::<u64>().ok())
+                        .filter(|days| *days > 0)
+                        .map(|days| Duration::from_secs(days * 24 * 60 * 60)),
+                });
+                // 間隔が設定されている場合は、古いエントリーや残った一時ファイルを定期的に消す
+                let compaction_interval = cache_file_compaction_interval_secs
+                    .ok()
+                    .and_then(|value| value.parse::<u64>().ok())
+                    .filter(|secs| *secs > 0);
+                if let Some(secs) = compaction_interval {
+                    provider.start_compaction(Duration::from_secs(secs));
+                }
+                Some(Box::new(provider))
+            }
+            "redis" => {
+                let redis_url = redis_url.unwrap();
+                let provider = RedisCacheProvider::new(RedisCacheProviderOptions {
+                    redis_url,
+                    key_prefix: redis_key_prefix.unwrap_or_default(),
+                    ttl: redis_ttl_secs
+                        .ok()
+                        .and_then(|value| value.parse::<u64>().ok())
+                        .filter(|secs| *secs > 0)
+                        .map(Duration::from_secs),
+                    max_connections: redis_max_connections
+                        .ok()
+                        .and_then(|value| value.parse().ok())
+                        .unwrap_or(16),
+                })
+                .unwrap();
+                Some(Box::new(provider))
+            }
             _ => None,
         },
         Err(_) => None,
     };
 
+    // 保存先の前にプロセス内のLRUキャッシュを置く (上限が設定されている場合のみ)
+    let cache_lru_max_entries: usize = cache_lru_max_entries
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(0);
+    let cache_lru_max_bytes: usize = cache_lru_max_bytes
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(0);
+    let lru_cache_provider = match translated_cache_provider.clone() {
+        Some(inner) if cache_lru_max_entries > 0 || cache_lru_max_bytes > 0 => {
+            Some(LruCacheProvider::new(LruCacheProviderOptions {
+                inner,
+                max_entries: cache_lru_max_entries,
+                max_bytes: cache_lru_max_bytes,
+            }))
+        }
+        _ => None,
+    };
+    // 集計など他のプロセスと共有するものは、LRUキャッシュを通さずに保存先から直接読む
+    let shared_cache_provider = translated_cache_provider.clone();
+    let translated_cache_provider = match lru_cache_provider.clone() {
+        Some(lru_cache_provider) => Some(lru_cache_provider.clone_box()),
+        None => translated_cache_provider,
+    };
+
+    let default_title_templates = TitleTemplates::default();
+    let title_templates = TitleTemplates {
+        bilingual: bilingual_template
+            .ok()
+            .filter(|template| !template.is_empty())
+            .unwrap_or(default_title_templates.bilingual),
+        original_first: original_first_template
+            .ok()
+            .filter(|template| !template.is_empty())
+            .unwrap_or(default_title_templates.original_first),
+    };
+
+    // 用語集と翻訳しない用語の設定
+    let term_protection = match term_protection_file {
+        Ok(path) if !path.is_empty() => TermProtectionConfig::load(&path).unwrap(),
+        _ => TermProtectionConfig::default(),
+    };
+
+    // 翻訳の前後に適用する書き換えのルール
+    let rewrite_rules = match rewrite_rules_file {
+        Ok(path) if !path.is_empty() => RewriteConfig::load(&path).unwrap(),
+        _ => RewriteConfig::default(),
+    };
+
+    // 翻訳APIに送った文字数の集計はキャッシュと同じ保存先に保存する
+    let spend_tracker = SpendTracker::new(SpendTrackerOptions {
+        cache_provider: shared_cache_provider.clone(),
+        daily_budget_chars: translation_daily_char_budget
+            .ok()
+            .and_then(|value| value.parse().ok()),
+        monthly_budget_chars: translation_monthly_char_budget
+            .ok()
+            .and_then(|value| value.parse().ok()),
+    });
+
+    // 翻訳メモリ (閾値が設定されている場合のみ有効にする)
+    let translation_memory = match translation_memory_threshold
+        .ok()
+        .and_then(|value| value.parse::<f64>().ok())
+    {
+        Some(threshold) if threshold > 0.0 => {
+            Some(TranslationMemory::new(TranslationMemoryOptions {
+                cache_provider: shared_cache_provider.clone(),
+                threshold: threshold.min(1.0),
+                max_entries: translation_memory_max_entries
+                    .ok()
+                    .and_then(|value| value.parse().ok())
+                    .unwrap_or(1000),
+            }))
+        }
+        _ => None,
+    };
+
     let app_state = web::Data::new(AppState {
         rss_provider: rss_provider.clone(),
-        translate_provider: translate_provider.clone(),
-        translated_cache_provider: translated_cache_provider,
+        translate_provider,
+        translated_cache_provider: translated_cache_provider.clone(),
+        title_templates,
+        term_protection,
+        rewrite_rules,
+        spend_tracker,
+        translation_memory,
+        lru_cache_provider,
     });
 
     HttpServer::new(move || {
@@ -331,8 +1210,130 @@ async fn main() -> std::io::Result<()> {
             .app_data(app_state.clone())
             .service(index)
             .service(rss)
+            .service(usage)
+            .service(cache_stats)
     })
     .bind(("0.0.0.0", 8080))?
     .run()
     .await
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    const FEED: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0">
+  <channel>
+    <title>Example</title>
+    <link>https://example.com/</link>
+    <description>Example feed</description>
+    <item><title>Hello world</title><link>https://example.com/1</link></item>
+    <item><title>Good morning everyone</title><link>https://example.com/2</link></item>
+  </channel>
+</rss>"#;
+
+    // 何度でも FEED を返すサーバーを立てて、そのURLを返す
+    async fn serve_feed() -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/feed.xml", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buffer = [0u8; 4096];
+                let _ = stream.read(&mut buffer).await;
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/rss+xml\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    FEED.len(),
+                    FEED
+                );
+                stream.write_all(response.as_bytes()).await.unwrap();
+                stream.shutdown().await.unwrap();
+            }
+        });
+        url
+    }
+
+    fn app_state(failure_rate: f64, max_segments: usize) -> web::Data<AppState> {
+        web::Data::new(AppState {
+            rss_provider: rtr::RssProvider::new(),
+            translate_provider: Box::new(PseudoBackend::new(PseudoBackendOptions {
+                mode: PseudoMode::Brackets,
+                dictionary: HashMap::new(),
+                latency: Duration::ZERO,
+                failure_rate,
+                batch_limits: BatchLimits {
+                    max_segments,
+                    max_chars: 5000,
+                    concurrency: 1,
+                },
+            })),
+            translated_cache_provider: None,
+            title_templates: TitleTemplates::default(),
+            term_protection: TermProtectionConfig::default(),
+            rewrite_rules: RewriteConfig::default(),
+            spend_tracker: SpendTracker::new(SpendTrackerOptions {
+                cache_provider: None,
+                daily_budget_chars: None,
+                monthly_budget_chars: None,
+            }),
+            translation_memory: None,
+            lru_cache_provider: None,
+        })
+    }
+
+    async fn get_rss(state: web::Data<AppState>, feed_url: &str) -> (String, String) {
+        let app = test::init_service(App::new().app_data(state).service(rss)).await;
+        let request = test::TestRequest::get()
+            .uri(&format!("/rss?url={}&to=ja-JP", feed_url))
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert!(response.status().is_success());
+        let failures = response
+            .headers()
+            .get("X-Translation-Failures")
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_string();
+        let body = String::from_utf8(test::read_body(response).await.to_vec()).unwrap();
+        (body, failures)
+    }
+
+    #[actix_web::test]
+    async fn backend_names_are_checked() {
+        assert_eq!(parse_backend_names(None).unwrap(), vec!["google"]);
+        assert_eq!(parse_backend_names(Some(" ")).unwrap(), vec!["google"]);
+        assert_eq!(
+            parse_backend_names(Some("deepl, llm,pseudo")).unwrap(),
+            vec!["deepl", "llm", "pseudo"]
+        );
+
+        // 知らない名前や大文字小文字が違うものはGoogleにせずにエラーにする
+        assert!(parse_backend_names(Some("deepL")).is_err());
+        assert!(parse_backend_names(Some("deepl,libre")).is_err());
+    }
+
+    #[actix_web::test]
+    async fn rss_titles_are_translated_with_the_pseudo_backend() {
+        let feed_url = serve_feed().await;
+        let (body, failures) = get_rss(app_state(0.0, 50), &feed_url).await;
+
+        assert!(body.contains("[ja-JP] Hello world"), "{}", body);
+        assert!(body.contains("[ja-JP] Good morning everyone"), "{}", body);
+        assert_eq!(failures, "0");
+    }
+
+    #[actix_web::test]
+    async fn rss_keeps_the_original_title_when_a_batch_fails() {
+        let feed_url = serve_feed().await;
+        // 1件ずつ送り、2回に1回失敗させる
+        let (body, failures) = get_rss(app_state(0.5, 1), &feed_url).await;
+
+        assert_eq!(body.matches("[ja-JP] ").count(), 1, "{}", body);
+        assert!(body.contains("Hello world") && body.contains("Good morning everyone"));
+        assert_eq!(failures, "1");
+    }
+}
@@ -0,0 +1,433 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+use crate::cache_provider::provider::CacheProvider;
+
+// 集計の単位 (バックエンド, 翻訳先の言語, フィードのURL)
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SpendKey {
+    pub backend: String,
+    pub to: String,
+    pub feed: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SpendEntry {
+    #[serde(flatten)]
+    pub key: SpendKey,
+    pub chars: u64,
+}
+
+// 1日または1ヶ月分の集計
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct PeriodSpend {
+    // 例: 2024-01-31, 2024-01
+    pub period: String,
+    pub total_chars: u64,
+    pub budget_chars: Option<u64>,
+    pub entries: Vec<SpendEntry>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct SpendReport {
+    pub daily: PeriodSpend,
+    pub monthly: PeriodSpend,
+}
+
+#[derive(Default)]
+struct PeriodCounter {
+    period: String,
+    counts: HashMap<SpendKey, u64>,
+    // 他のプロセスの分も含めた合計 (保存先の値を使う)
+    total: u64,
+}
+
+impl PeriodCounter {
+    fn to_period_spend(&self, budget_chars: Option<u64>) -> PeriodSpend {
+        let mut entries: Vec<SpendEntry> = self
+            .counts
+            .iter()
+            .map(|(key, chars)| SpendEntry {
+                key: key.clone(),
+                chars: *chars,
+            })
+            .collect();
+        entries.sort_by(|a, b| {
+            Reverse(a.chars)
+                .cmp(&Reverse(b.chars))
+                .then_with(|| a.key.backend.cmp(&b.key.backend))
+                .then_with(|| a.key.feed.cmp(&b.key.feed))
+        });
+
+        PeriodSpend {
+            period: self.period.clone(),
+            total_chars: self.total,
+            budget_chars,
+            entries,
+        }
+    }
+}
+
+#[derive(Default)]
+struct SpendState {
+    daily: PeriodCounter,
+    monthly: PeriodCounter,
+    // 翻訳APIに送っている途中で、まだ記録していない文字数
+    reserved: u64,
+}
+
+pub struct SpendTrackerOptions {
+    // 集計の保存先 (Noneの場合はプロセス内でのみ集計する)
+    pub cache_provider: Option<Box<dyn CacheProvider>>,
+    pub daily_budget_chars: Option<u64>,
+    pub monthly_budget_chars: Option<u64>,
+}
+
+// 翻訳APIに送った文字数を集計し、予算を超えないようにする
+//   合計はキャッシュの保存先の "spend:daily:<日付>:total" と "spend:monthly:<年月>:total" に足していき、
+//   内訳は "spend:daily:<日付>" と "spend:monthly:<年月>" に保存する
+//   複数のプロセスで同じ保存先を使う場合も、予算は保存先の合計で判定する
+#[derive(Clone)]
+pub struct SpendTracker {
+    state: Arc<Mutex<SpendState>>,
+    cache_provider: Option<Box<dyn CacheProvider>>,
+    daily_budget_chars: Option<u64>,
+    monthly_budget_chars: Option<u64>,
+}
+
+impl SpendTracker {
+    pub fn new(options: SpendTrackerOptions) -> Self {
+        SpendTracker {
+            state: Arc::new(Mutex::new(SpendState::default())),
+            cache_provider: options.cache_provider,
+            daily_budget_chars: options.daily_budget_chars,
+            monthly_budget_chars: options.monthly_budget_chars,
+        }
+    }
+
+    fn periods(now: DateTime<Utc>) -> (String, String) {
+        (
+            now.format("%Y-%m-%d").to_string(),
+            now.format("%Y-%m").to_string(),
+        )
+    }
+
+    fn storage_key(kind: &str, period: &str) -> String {
+        format!("spend:{}:{}", kind, period)
+    }
+
+    fn total_key(kind: &str, period: &str) -> String {
+        format!("spend:{}:{}:total", kind, period)
+    }
+
+    // 保存されている内訳を読み込む (読み込めない場合は0から数える)
+    async fn load_counts(&self, kind: &str, period: &str) -> HashMap<SpendKey, u64> {
+        let cache_provider = match self.cache_provider.clone() {
+            Some(cache_provider) => cache_provider,
+            None => return HashMap::new(),
+        };
+
+        let stored = match cache_provider
+            .get(SpendTracker::storage_key(kind, period))
+            .await
+        {
+            Ok(stored) => stored,
+            Err(e) => {
+                println!("Error (failed to load spend counters): {}", e);
+                None
+            }
+        };
+        match stored.map(|stored| serde_json::from_str::<PeriodSpend>(&stored)) {
+            Some(Ok(spend)) => spend
+                .entries
+                .into_iter()
+                .map(|entry| (entry.key, entry.chars))
+                .collect(),
+            Some(Err(e)) => {
+                println!("Error (failed to parse spend counters): {}", e);
+                HashMap::new()
+            }
+            None => HashMap::new(),
+        }
+    }
+
+    // 保存先の合計に delta を足して、足した後の合計を返す (0を足すと今の合計を読み込む)
+    async fn add_total(&self, kind: &str, counter: &PeriodCounter, delta: u64) -> u64 {
+        let cache_provider = match self.cache_provider.clone() {
+            Some(cache_provider) => cache_provider,
+            None => return counter.total + delta,
+        };
+
+        let incremented = cache_provider
+            .increment(SpendTracker::total_key(kind, &counter.period), delta)
+            .await;
+        match incremented {
+            Ok(total) => total,
+            Err(e) => {
+                println!("Error (failed to update spend counters): {}", e);
+                counter.total + delta
+            }
+        }
+    }
+
+    async fn load(&self, kind: &str, period: &str) -> PeriodCounter {
+        let mut counter = PeriodCounter {
+            period: period.to_string(),
+            counts: self.load_counts(kind, period).await,
+            total: 0,
+        };
+        counter.total = self.add_total(kind, &counter, 0).await;
+        counter
+    }
+
+    // 内訳は最新のものを読み込んでから足して保存する
+    //   (合計と違って、別のプロセスと同時に保存すると片方の内訳が失われることがある)
+    async fn add(&self, kind: &str, counter: &mut PeriodCounter, spent: &[(SpendKey, u64)]) {
+        let delta: u64 = spent.iter().map(|(_, chars)| chars).sum();
+        counter.total = self.add_total(kind, counter, delta).await;
+
+        if self.cache_provider.is_some() {
+            counter.counts = self.load_counts(kind, &counter.period).await;
+        }
+        for (key, chars) in spent {
+            *counter.counts.entry(key.clone()).or_insert(0) += chars;
+        }
+
+        let cache_provider = match self.cache_provider.clone() {
+            Some(cache_provider) => cache_provider,
+            None => return,
+        };
+        let key = SpendTracker::storage_key(kind, &counter.period);
+        let value = serde_json::to_string(&counter.to_period_spend(None)).unwrap();
+        let set_result = cache_provider.set(key, value).await;
+        if let Err(e) = set_result {
+            println!("Error (failed to save spend counters): {}", e);
+        }
+    }
+
+    // 日付や月が変わっていれば集計を切り替える
+    async fn roll_over(&self, state: &mut SpendState, now: DateTime<Utc>) {
+        let (today, this_month) = SpendTracker::periods(now);
+        if state.daily.period != today {
+            state.daily = self.load("daily", &today).await;
+        }
+        if state.monthly.period != this_month {
+            state.monthly = self.load("monthly", &this_month).await;
+        }
+    }
+
+    // 今日と今月の予算の残りのうち少ない方 (予算が設定されていない場合はNone)
+    //   送っている途中の文字数も使ったものとして引く
+    fn remaining_chars(&self, state: &SpendState) -> Option<u64> {
+        let daily = self
+            .daily_budget_chars
+            .map(|budget| budget.saturating_sub(state.daily.total + state.reserved));
+        let monthly = self
+            .monthly_budget_chars
+            .map(|budget| budget.saturating_sub(state.monthly.total + state.reserved));
+        match (daily, monthly) {
+            (Some(daily), Some(monthly)) => Some(daily.min(monthly)),
+            (daily, monthly) => daily.or(monthly),
+        }
+    }
+
+    // 翻訳APIに送る前に、予算の残りからそれぞれの文字数を確保する (戻り値は確保できたかどうか)
+    //   確保した分は record するまで残りから引いておくので、同時に来たリクエストで予算を超えない
+    pub async fn reserve(&self, chars: &[u64]) -> Vec<bool> {
+        self.reserve_at(chars, Utc::now()).await
+    }
+
+    async fn reserve_at(&self, chars: &[u64], now: DateTime<Utc>) -> Vec<bool> {
+        let mut state = self.state.lock().await;
+        self.roll_over(&mut state, now).await;
+        // 他のプロセスが使った分を反映する
+        if self.daily_budget_chars.is_some() || self.monthly_budget_chars.is_some() {
+            state.daily.total = self.add_total("daily", &state.daily, 0).await;
+            state.monthly.total = self.add_total("monthly", &state.monthly, 0).await;
+        }
+
+        chars
+            .iter()
+            .map(|chars| {
+                let fits = self
+                    .remaining_chars(&state)
+                    .map(|remaining| *chars <= remaining)
+                    .unwrap_or(true);
+                if fits {
+                    state.reserved += chars;
+                }
+                fits
+            })
+            .collect()
+    }
+
+    // 確保した文字数を戻し、実際に翻訳APIに送った文字数を記録する
+    pub async fn record(&self, reserved: u64, spent: Vec<(SpendKey, u64)>) {
+        self.record_at(reserved, spent, Utc::now()).await
+    }
+
+    async fn record_at(&self, reserved: u64, spent: Vec<(SpendKey, u64)>, now: DateTime<Utc>) {
+        let mut state = self.state.lock().await;
+        state.reserved = state.reserved.saturating_sub(reserved);
+
+        let spent: Vec<(SpendKey, u64)> =
+            spent.into_iter().filter(|(_, chars)| *chars > 0).collect();
+        if spent.is_empty() {
+            return;
+        }
+        self.roll_over(&mut state, now).await;
+
+        // 書き込みの順番が入れ替わらないようにロックを持ったまま保存する
+        let SpendState { daily, monthly, .. } = &mut *state;
+        self.add("daily", daily, &spent).await;
+        self.add("monthly", monthly, &spent).await;
+    }
+
+    // 他のプロセスの分も含めて集計を読み込み直す
+    pub async fn report(&self) -> SpendReport {
+        self.report_at(Utc::now()).await
+    }
+
+    async fn report_at(&self, now: DateTime<Utc>) -> SpendReport {
+        let mut state = self.state.lock().await;
+        let (today, this_month) = SpendTracker::periods(now);
+        if self.cache_provider.is_some() || state.daily.period != today {
+            state.daily = self.load("daily", &today).await;
+        }
+        if self.cache_provider.is_some() || state.monthly.period != this_month {
+            state.monthly = self.load("monthly", &this_month).await;
+        }
+
+        SpendReport {
+            daily: state.daily.to_period_spend(self.daily_budget_chars),
+            monthly: state.monthly.to_period_spend(self.monthly_budget_chars),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache_provider::memory::MemoryCacheProvider;
+    use chrono::TimeZone;
+
+    fn tracker(
+        provider: Option<&MemoryCacheProvider>,
+        daily: Option<u64>,
+        monthly: Option<u64>,
+    ) -> SpendTracker {
+        SpendTracker::new(SpendTrackerOptions {
+            cache_provider: provider.map(|provider| provider.clone_box()),
+            daily_budget_chars: daily,
+            monthly_budget_chars: monthly,
+        })
+    }
+
+    fn key(backend: &str, feed: &str) -> SpendKey {
+        SpendKey {
+            backend: backend.to_string(),
+            to: "ja".to_string(),
+            feed: feed.to_string(),
+        }
+    }
+
+    fn at(year: i32, month: u32, day: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(year, month, day, 12, 0, 0).unwrap()
+    }
+
+    #[tokio::test]
+    async fn budget_is_exhausted_including_reservations() {
+        let tracker = tracker(None, Some(10), None);
+        let now = at(2024, 1, 30);
+
+        // 6 + 4 で予算ちょうどになり、入らない 6 は飛ばす
+        assert_eq!(
+            tracker.reserve_at(&[6, 6, 4], now).await,
+            vec![true, false, true]
+        );
+        // 記録する前でも、確保した分は他のリクエストから使えない
+        assert_eq!(tracker.reserve_at(&[1], now).await, vec![false]);
+
+        // 送ったうち 4 文字だけが翻訳できた場合は残りを戻す
+        tracker
+            .record_at(10, vec![(key("google", "a"), 4)], now)
+            .await;
+        assert_eq!(tracker.reserve_at(&[6, 1], now).await, vec![true, false]);
+    }
+
+    #[tokio::test]
+    async fn periods_roll_over() {
+        let tracker = tracker(None, Some(100), Some(150));
+
+        tracker
+            .record_at(0, vec![(key("google", "a"), 80)], at(2024, 1, 30))
+            .await;
+        assert_eq!(
+            tracker.reserve_at(&[30], at(2024, 1, 30)).await,
+            vec![false]
+        );
+
+        // 日付が変わると日ごとの予算は戻るが、月ごとの予算は残りの 70 まで
+        assert_eq!(
+            tracker.reserve_at(&[80, 70], at(2024, 1, 31)).await,
+            vec![false, true]
+        );
+        tracker
+            .record_at(70, vec![(key("google", "a"), 70)], at(2024, 1, 31))
+            .await;
+        let report = tracker.report_at(at(2024, 1, 31)).await;
+        assert_eq!(report.daily.period, "2024-01-31");
+        assert_eq!(report.daily.total_chars, 70);
+        assert_eq!(report.monthly.period, "2024-01");
+        assert_eq!(report.monthly.total_chars, 150);
+
+        // 月が変わると月ごとの予算も戻る
+        assert_eq!(tracker.reserve_at(&[100], at(2024, 2, 1)).await, vec![true]);
+        assert_eq!(
+            tracker.report_at(at(2024, 2, 1)).await.monthly.total_chars,
+            0
+        );
+    }
+
+    #[tokio::test]
+    async fn usage_adds_up_spend_from_every_process() {
+        let provider = MemoryCacheProvider::default();
+        let first = tracker(Some(&provider), Some(100), None);
+        let second = tracker(Some(&provider), Some(100), None);
+        let now = at(2024, 1, 30);
+
+        // 両方のプロセスが読み込んだ後に記録しても、上書きせずに足す
+        first.reserve_at(&[5], now).await;
+        second.reserve_at(&[10], now).await;
+        first.record_at(5, vec![(key("google", "a"), 5)], now).await;
+        second
+            .record_at(
+                10,
+                vec![(key("google", "a"), 7), (key("deepl", "b"), 3)],
+                now,
+            )
+            .await;
+
+        let report = first.report_at(now).await;
+        assert_eq!(report.daily.total_chars, 15);
+        assert_eq!(report.daily.budget_chars, Some(100));
+        let entries: Vec<(String, u64)> = report
+            .daily
+            .entries
+            .iter()
+            .map(|entry| (entry.key.backend.clone(), entry.chars))
+            .collect();
+        assert_eq!(
+            entries,
+            vec![("google".to_string(), 12), ("deepl".to_string(), 3)]
+        );
+        assert_eq!(report.monthly.total_chars, 15);
+
+        // 他のプロセスが使った分も予算から引く
+        assert_eq!(first.reserve_at(&[86, 85], now).await, vec![false, true]);
+    }
+}
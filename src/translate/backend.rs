@@ -0,0 +1,60 @@
+use std::{future::Future, pin::Pin};
+
+use super::error::TranslateError;
+
+pub struct TranslatResult {
+    pub translated: String,
+    pub raw_text: String,
+    // 翻訳したバックエンドの名前
+    pub backend: String,
+}
+
+// 翻訳時に指定するオプション
+//   バックエンドが対応していないオプションは無視される
+#[derive(Clone, Default)]
+pub struct TranslateOptions {
+    pub to: String,
+    pub formality: Option<String>,
+    pub glossary_id: Option<String>,
+    pub model: Option<String>,
+    pub mime_type: Option<String>,
+}
+
+impl TranslateOptions {
+    pub fn new(to: String) -> Self {
+        TranslateOptions {
+            to,
+            ..Default::default()
+        }
+    }
+}
+
+// テキストごとの翻訳結果
+//   バッチの一部だけが失敗した場合も、翻訳できたものは Ok で返す
+pub type TranslatedItem = Result<TranslatResult, TranslateError>;
+
+pub type TranslateFuture<T> =
+    Pin<Box<dyn Future<Output = Result<T, TranslateError>> + Send + 'static>>;
+
+pub trait TranslationBackend: Send + Sync {
+    // 複数の文字列をまとめて翻訳する (結果は target_strs と同じ順番で返す)
+    //   全体が失敗した場合 (送る前の準備に失敗したなど) だけ Err を返す
+    fn translate(
+        &self,
+        target_strs: Vec<String>,
+        options: TranslateOptions,
+    ) -> TranslateFuture<Vec<TranslatedItem>>;
+    // 翻訳先として指定できる言語コードの一覧
+    fn supported_languages(&self) -> TranslateFuture<Vec<String>>;
+
+    fn name(&self) -> String;
+    fn version(&self) -> String;
+
+    fn clone_box(&self) -> Box<dyn TranslationBackend>;
+}
+
+impl Clone for Box<dyn TranslationBackend> {
+    fn clone(&self) -> Box<dyn TranslationBackend> {
+        self.clone_box()
+    }
+}
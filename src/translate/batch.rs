@@ -0,0 +1,175 @@
+use std::future::Future;
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+
+use super::backend::TranslatedItem;
+use super::error::TranslateError;
+
+// 1リクエストあたりの上限と同時に送るリクエスト数
+#[derive(Clone, Copy, Debug)]
+pub struct BatchLimits {
+    pub max_segments: usize,
+    // 1リクエストに含める文字数 (コードポイント数) の上限
+    pub max_chars: usize,
+    pub concurrency: usize,
+}
+
+// 件数と文字数の両方の上限に収まるようにバッチに分ける
+//   1件で上限を超える場合はその1件だけでバッチにする
+pub fn plan_batches(texts: Vec<String>, limits: &BatchLimits) -> Vec<Vec<String>> {
+    let mut batches: Vec<Vec<String>> = Vec::new();
+    let mut batch: Vec<String> = Vec::new();
+    let mut batch_chars = 0;
+
+    for text in texts {
+        let chars = text.chars().count();
+        let is_full =
+            batch.len() >= limits.max_segments.max(1) || batch_chars + chars > limits.max_chars;
+        if !batch.is_empty() && is_full {
+            batches.push(std::mem::take(&mut batch));
+            batch_chars = 0;
+        }
+        batch_chars += chars;
+        batch.push(text);
+    }
+    if !batch.is_empty() {
+        batches.push(batch);
+    }
+
+    batches
+}
+
+// バッチを並列に送信し、結果を元の順番に並べて返す
+//   失敗したバッチは、そのバッチのテキストだけをエラーにして、成功したバッチの翻訳は残す
+pub async fn dispatch<F, Fut>(
+    texts: Vec<String>,
+    limits: BatchLimits,
+    translate_batch: F,
+) -> Vec<TranslatedItem>
+where
+    F: Fn(Vec<String>) -> Fut,
+    Fut: Future<Output = Result<Vec<TranslatedItem>, TranslateError>> + Send + 'static,
+{
+    let batches = plan_batches(texts, &limits);
+    let semaphore = Arc::new(Semaphore::new(limits.concurrency.max(1)));
+
+    // バッチと結果を対応付けられるように、送った順番にハンドルを残しておく
+    let mut tasks = Vec::new();
+    for batch in batches {
+        let semaphore = semaphore.clone();
+        let batch_len = batch.len();
+        let future = translate_batch(batch);
+        let task = tokio::spawn(async move {
+            let _permit = semaphore
+                .acquire_owned()
+                .await
+                .map_err(|e| TranslateError::Other(e.to_string()))?;
+            future.await
+        });
+        tasks.push((batch_len, task));
+    }
+
+    let mut results: Vec<TranslatedItem> = Vec::new();
+    for (batch_len, task) in tasks {
+        let batch_result = match task.await {
+            Ok(batch_result) => batch_result,
+            Err(e) => Err(TranslateError::Other(e.to_string())),
+        };
+        match batch_result {
+            // テキストごとのエラー (空の翻訳など) は pair_translations で付けたものをそのまま並べる
+            Ok(translated) => results.extend(translated),
+            Err(e) => results.extend((0..batch_len).map(|_| Err(e.clone()))),
+        }
+    }
+
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::translate::backend::TranslatResult;
+    use std::time::Duration;
+
+    fn limits(max_segments: usize, max_chars: usize) -> BatchLimits {
+        BatchLimits {
+            max_segments,
+            max_chars,
+            concurrency: 4,
+        }
+    }
+
+    fn texts(texts: &[&str]) -> Vec<String> {
+        texts.iter().map(|text| text.to_string()).collect()
+    }
+
+    #[test]
+    fn batches_that_fit_exactly_are_not_split() {
+        // 文字数はバイト数ではなくコードポイント数で数える
+        assert_eq!(
+            plan_batches(texts(&["あいう", "えお", "abcde"]), &limits(10, 5)),
+            vec![texts(&["あいう", "えお"]), texts(&["abcde"])]
+        );
+        assert_eq!(
+            plan_batches(texts(&["a", "b", "c", "d"]), &limits(2, 100)),
+            vec![texts(&["a", "b"]), texts(&["c", "d"])]
+        );
+        assert!(plan_batches(Vec::new(), &limits(2, 100)).is_empty());
+    }
+
+    #[test]
+    fn an_oversize_item_gets_its_own_batch() {
+        assert_eq!(
+            plan_batches(texts(&["ab", "abcdefgh", "c"]), &limits(10, 5)),
+            vec![texts(&["ab"]), texts(&["abcdefgh"]), texts(&["c"])]
+        );
+        // 件数の上限が0でも1件ずつ送る
+        assert_eq!(
+            plan_batches(texts(&["a", "b"]), &limits(0, 100)),
+            vec![texts(&["a"]), texts(&["b"])]
+        );
+    }
+
+    #[test]
+    fn planned_batches_keep_the_input_order() {
+        let input: Vec<String> = (0..100).map(|index| "x".repeat(index % 7 + 1)).collect();
+        let batches = plan_batches(input.clone(), &limits(3, 10));
+
+        assert!(batches.iter().all(|batch| batch.len() <= 3
+            && (batch.len() == 1 || batch.iter().map(|text| text.len()).sum::<usize>() <= 10)));
+        assert_eq!(batches.concat(), input);
+    }
+
+    #[tokio::test]
+    async fn dispatched_results_keep_the_input_order() {
+        let input = texts(&["a", "b", "c", "d", "e"]);
+        // 先に送ったバッチほど遅く返し、"c" を含むバッチだけ失敗させる
+        let results = dispatch(input.clone(), limits(1, 100), |batch| async move {
+            let delay = 5 * (5 - (batch[0].as_bytes()[0] - b'a')) as u64;
+            tokio::time::sleep(Duration::from_millis(delay)).await;
+            if batch[0] == "c" {
+                return Err(TranslateError::Other("failed".to_string()));
+            }
+            Ok(batch
+                .into_iter()
+                .map(|text| {
+                    Ok(TranslatResult {
+                        translated: text.to_uppercase(),
+                        raw_text: text,
+                        backend: "test".to_string(),
+                    })
+                })
+                .collect())
+        })
+        .await;
+
+        let translated: Vec<String> = results
+            .iter()
+            .map(|result| match result {
+                Ok(result) => result.translated.clone(),
+                Err(e) => e.to_string(),
+            })
+            .collect();
+        assert_eq!(translated, texts(&["A", "B", "failed", "D", "E"]));
+    }
+}
@@ -0,0 +1,373 @@
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use url::Url;
+
+use super::backend::{TranslateFuture, TranslateOptions, TranslatedItem, TranslationBackend};
+use super::batch::{self, BatchLimits};
+use super::error::{self, TranslateError};
+use super::retry::RequestPolicy;
+
+#[derive(Serialize, Deserialize)]
+struct Translated {
+    text: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Response {
+    translations: Vec<Translated>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SupportedLanguage {
+    language: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Glossary {
+    source_lang: String,
+    target_lang: String,
+}
+
+pub struct DeepLBackendOptions {
+    pub auth_key: String,
+    // 未指定の場合は認証キーからFree版かPro版かを判定する
+    pub endpoint_url: Option<String>,
+    // デプロイ全体でのデフォルト値 (リクエストで指定された場合はそちらを優先する)
+    pub formality: Option<String>,
+    pub glossary_id: Option<String>,
+    pub batch_limits: BatchLimits,
+    pub request_policy: RequestPolicy,
+}
+
+// リクエストのオプションとデプロイのデフォルト値をまとめたもの
+#[derive(Clone)]
+struct DeepLRequestOptions {
+    target_lang: String,
+    formality: Option<String>,
+    glossary_id: Option<String>,
+    source_lang: Option<String>,
+    // HTMLの断片を送る場合は tag_handling: html を指定する
+    is_html: bool,
+}
+
+#[derive(Clone)]
+pub struct DeepLBackend {
+    client: reqwest::Client,
+    base_url: Url,
+    auth_key: String,
+    formality: Option<String>,
+    glossary_id: Option<String>,
+    // グロッサリーID -> 翻訳元言語
+    glossary_source_langs: Arc<Mutex<HashMap<String, String>>>,
+    batch_limits: BatchLimits,
+    request_policy: RequestPolicy,
+}
+
+impl TranslationBackend for DeepLBackend {
+    fn translate(
+        &self,
+        target_strs: Vec<String>,
+        options: TranslateOptions,
+    ) -> TranslateFuture<Vec<TranslatedItem>> {
+        let backend = self.clone();
+        Box::pin(async move { backend.translate_batches(target_strs, options).await })
+    }
+
+    fn supported_languages(&self) -> TranslateFuture<Vec<String>> {
+        let backend = self.clone();
+        Box::pin(async move { backend.fetch_supported_languages().await })
+    }
+
+    fn name(&self) -> String {
+        "deepl".to_string()
+    }
+
+    // 敬語や用語集のデフォルトを設定している場合は別の翻訳として扱う
+    fn version(&self) -> String {
+        let mut version = "v2".to_string();
+        if let Some(formality) = self.formality.as_ref() {
+            version.push_str(&format!("/formality={}", formality));
+        }
+        if let Some(glossary_id) = self.glossary_id.as_ref() {
+            version.push_str(&format!("/glossary={}", glossary_id));
+        }
+        version
+    }
+
+    fn clone_box(&self) -> Box<dyn TranslationBackend> {
+        Box::new(self.clone())
+    }
+}
+
+impl DeepLBackend {
+    // DeepLは1リクエストあたり50件、128KiBまで
+    pub const DEFAULT_BATCH_LIMITS: BatchLimits = BatchLimits {
+        max_segments: 50,
+        max_chars: 30000,
+        concurrency: 4,
+    };
+
+    pub fn new(options: DeepLBackendOptions) -> Self {
+        let endpoint_url = match options.endpoint_url {
+            Some(endpoint_url) => endpoint_url,
+            // Free版の認証キーは末尾が ":fx" になっている
+            None if options.auth_key.ends_with(":fx") => "https://api-free.deepl.com/".to_string(),
+            None => "https://api.deepl.com/".to_string(),
+        };
+        let base_url = Url::parse(&endpoint_url).unwrap();
+
+        DeepLBackend {
+            client: reqwest::Client::new(),
+            base_url,
+            auth_key: options.auth_key,
+            formality: options.formality,
+            glossary_id: options.glossary_id,
+            glossary_source_langs: Arc::new(Mutex::new(HashMap::new())),
+            batch_limits: options.batch_limits,
+            request_policy: options.request_policy,
+        }
+    }
+
+    // DeepLの target_lang 形式に変換する
+    //   英語とポルトガル語は地域の指定が必要で、中国語は簡体字と繁体字を分ける
+    //   それ以外は言語部分だけを使う
+    fn to_target_lang(to: &str) -> String {
+        let upper = to.replace('_', "-").to_uppercase();
+        let mut parts = upper.split('-');
+        let language = parts.next().unwrap_or("").to_string();
+        let region = parts.next();
+        match (language.as_str(), region) {
+            ("EN", Some(region)) | ("PT", Some(region)) => format!("{}-{}", language, region),
+            ("EN", None) => "EN-US".to_string(),
+            ("PT", None) => "PT-PT".to_string(),
+            // 台湾、香港、マカオは繁体字で書かれる
+            ("ZH", Some("HANT" | "TW" | "HK" | "MO")) => "ZH-HANT".to_string(),
+            ("ZH", Some(_)) => "ZH-HANS".to_string(),
+            _ => language,
+        }
+    }
+
+    fn auth_header(&self) -> String {
+        format!("DeepL-Auth-Key {}", self.auth_key)
+    }
+
+    // グロッサリーを使う場合は source_lang の指定が必須なのでグロッサリーの情報から取得する
+    async fn get_glossary_source_lang(
+        &self,
+        glossary_id: &str,
+    ) -> Result<String, TranslateError> {
+        let mut source_langs = self.glossary_source_langs.lock().await;
+        if let Some(source_lang) = source_langs.get(glossary_id) {
+            return Ok(source_lang.clone());
+        }
+
+        let endpoint = self
+            .base_url
+            .join(&format!("v2/glossaries/{}", glossary_id))?;
+        let response = self
+            .client
+            .get(endpoint)
+            .header("Authorization", self.auth_header())
+            .send()
+            .await?;
+        let response_body = error::read_response(&self.name(), response).await?;
+        let glossary: Glossary = error::parse_response(&self.name(), &response_body)?;
+        println!(
+            "deepl glossary {}: {} -> {}",
+            glossary_id, glossary.source_lang, glossary.target_lang
+        );
+
+        let source_lang = glossary.source_lang.to_uppercase();
+        source_langs.insert(glossary_id.to_string(), source_lang.clone());
+        Ok(source_lang)
+    }
+
+    async fn translate_batches(
+        &self,
+        target_strs: Vec<String>,
+        options: TranslateOptions,
+    ) -> Result<Vec<TranslatedItem>, TranslateError> {
+        let glossary_id = options.glossary_id.or(self.glossary_id.clone());
+        let source_lang = match glossary_id.clone() {
+            Some(glossary_id) => Some(self.get_glossary_source_lang(&glossary_id).await?),
+            None => None,
+        };
+        let request_options = DeepLRequestOptions {
+            target_lang: DeepLBackend::to_target_lang(&options.to),
+            formality: options.formality.or(self.formality.clone()),
+            glossary_id,
+            source_lang,
+            is_html: options.mime_type.as_deref() == Some("text/html"),
+        };
+
+        let backend = self.clone();
+        let results = batch::dispatch(target_strs, self.batch_limits, move |batch| {
+            let backend = backend.clone();
+            let request_options = request_options.clone();
+            async move { backend.translate_batch(batch, request_options).await }
+        })
+        .await;
+        Ok(results)
+    }
+
+    async fn translate_batch(
+        &self,
+        batch: Vec<String>,
+        request_options: DeepLRequestOptions,
+    ) -> Result<Vec<TranslatedItem>, TranslateError> {
+        let endpoint = self.base_url.join("v2/translate")?;
+
+        let mut request_json = json!({
+            "text": batch,
+            "target_lang": request_options.target_lang
+        });
+        if let Some(formality) = request_options.formality {
+            request_json["formality"] = json!(formality);
+        }
+        if let Some(glossary_id) = request_options.glossary_id {
+            request_json["glossary_id"] = json!(glossary_id);
+            request_json["source_lang"] = json!(request_options.source_lang);
+        }
+        if request_options.is_html {
+            request_json["tag_handling"] = json!("html");
+        }
+
+        let chars = batch.iter().map(|text| text.chars().count()).sum();
+        let request = self
+            .client
+            .post(endpoint)
+            .header("Content-Type", "application/json")
+            .header("Authorization", self.auth_header())
+            .body(serde_json::to_string(&request_json)?);
+        let response = self.request_policy.send(request, chars).await?;
+        let response_body = error::read_response(&self.name(), response).await?;
+        let parsed_response: Response = error::parse_response(&self.name(), &response_body)?;
+        let translations = parsed_response
+            .translations
+            .into_iter()
+            .map(|translation| translation.text)
+            .collect();
+
+        error::pair_translations(&self.name(), &batch, translations)
+    }
+
+    async fn fetch_supported_languages(&self) -> Result<Vec<String>, TranslateError> {
+        let mut endpoint = self.base_url.join("v2/languages")?;
+        endpoint.query_pairs_mut().append_pair("type", "target");
+        let response = self
+            .client
+            .get(endpoint)
+            .header("Authorization", self.auth_header())
+            .send()
+            .await?;
+        let response_body = error::read_response(&self.name(), response).await?;
+        let languages: Vec<SupportedLanguage> =
+            error::parse_response(&self.name(), &response_body)?;
+
+        Ok(languages
+            .into_iter()
+            .map(|language| language.language)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::translate::test_server::TestServer;
+
+    fn backend(endpoint_url: Option<&str>, auth_key: &str) -> DeepLBackend {
+        DeepLBackend::new(DeepLBackendOptions {
+            auth_key: auth_key.to_string(),
+            endpoint_url: endpoint_url.map(|url| url.to_string()),
+            formality: Some("prefer_more".to_string()),
+            glossary_id: None,
+            batch_limits: DeepLBackend::DEFAULT_BATCH_LIMITS,
+            request_policy: RequestPolicy::default(),
+        })
+    }
+
+    #[test]
+    fn languages_are_mapped_to_target_langs() {
+        for (to, target_lang) in [
+            ("ja", "JA"),
+            ("ja-JP", "JA"),
+            ("de_DE", "DE"),
+            ("en", "EN-US"),
+            ("en-gb", "EN-GB"),
+            ("pt", "PT-PT"),
+            ("pt-BR", "PT-BR"),
+            ("zh", "ZH"),
+            ("zh-CN", "ZH-HANS"),
+            ("zh-Hans", "ZH-HANS"),
+            ("zh-TW", "ZH-HANT"),
+            ("zh-HK", "ZH-HANT"),
+            ("zh-Hant", "ZH-HANT"),
+            ("zh_Hant_TW", "ZH-HANT"),
+        ] {
+            assert_eq!(DeepLBackend::to_target_lang(to), target_lang, "{}", to);
+        }
+    }
+
+    #[test]
+    fn free_keys_use_the_free_endpoint() {
+        assert_eq!(
+            backend(None, "key:fx").base_url.as_str(),
+            "https://api-free.deepl.com/"
+        );
+        assert_eq!(
+            backend(None, "key").base_url.as_str(),
+            "https://api.deepl.com/"
+        );
+    }
+
+    #[tokio::test]
+    async fn requests_include_the_options_and_glossary_source_lang() {
+        let server = TestServer::start(vec![
+            (
+                "/v2/glossaries/glossary-1",
+                vec![(200, r#"{"source_lang":"en","target_lang":"ja"}"#)],
+            ),
+            (
+                "/v2/translate",
+                vec![(
+                    200,
+                    r#"{"translations":[{"text":"こんにちは"},{"text":"世界"}]}"#,
+                )],
+            ),
+        ])
+        .await;
+        let backend = backend(Some(&server.url), "secret");
+        let mut options = TranslateOptions::new("zh-TW".to_string());
+        options.glossary_id = Some("glossary-1".to_string());
+        options.mime_type = Some("text/html".to_string());
+
+        let translated = backend
+            .translate(vec!["Hello".to_string(), "World".to_string()], options)
+            .await
+            .unwrap();
+        assert_eq!(translated[1].as_ref().unwrap().translated, "世界");
+
+        let request = &server.requests("/v2/translate")[0];
+        assert_eq!(
+            request.header("Authorization"),
+            Some("DeepL-Auth-Key secret")
+        );
+        let body: serde_json::Value = serde_json::from_str(&request.body).unwrap();
+        assert_eq!(
+            body,
+            json!({
+                "text": ["Hello", "World"],
+                "target_lang": "ZH-HANT",
+                "formality": "prefer_more",
+                "glossary_id": "glossary-1",
+                "source_lang": "EN",
+                "tag_handling": "html"
+            })
+        );
+        assert_eq!(server.requests("/v2/glossaries/glossary-1").len(), 1);
+    }
+}
@@ -0,0 +1,154 @@
+use whatlang::Lang;
+
+// whatlang の ISO 639-3 を翻訳APIで使われる ISO 639-1 に変換する
+fn to_iso639_1(lang: Lang) -> &'static str {
+    match lang {
+        Lang::Epo => "eo",
+        Lang::Eng => "en",
+        Lang::Rus => "ru",
+        Lang::Cmn => "zh",
+        Lang::Spa => "es",
+        Lang::Por => "pt",
+        Lang::Ita => "it",
+        Lang::Ben => "bn",
+        Lang::Fra => "fr",
+        Lang::Deu => "de",
+        Lang::Ukr => "uk",
+        Lang::Kat => "ka",
+        Lang::Ara => "ar",
+        Lang::Hin => "hi",
+        Lang::Jpn => "ja",
+        Lang::Heb => "he",
+        Lang::Yid => "yi",
+        Lang::Pol => "pl",
+        Lang::Amh => "am",
+        Lang::Jav => "jv",
+        Lang::Kor => "ko",
+        Lang::Nob => "nb",
+        Lang::Dan => "da",
+        Lang::Swe => "sv",
+        Lang::Fin => "fi",
+        Lang::Tur => "tr",
+        Lang::Nld => "nl",
+        Lang::Hun => "hu",
+        Lang::Ces => "cs",
+        Lang::Ell => "el",
+        Lang::Bul => "bg",
+        Lang::Bel => "be",
+        Lang::Mar => "mr",
+        Lang::Kan => "kn",
+        Lang::Ron => "ro",
+        Lang::Slv => "sl",
+        Lang::Hrv => "hr",
+        Lang::Srp => "sr",
+        Lang::Mkd => "mk",
+        Lang::Lit => "lt",
+        Lang::Lav => "lv",
+        Lang::Est => "et",
+        Lang::Tam => "ta",
+        Lang::Vie => "vi",
+        Lang::Urd => "ur",
+        Lang::Tha => "th",
+        Lang::Guj => "gu",
+        Lang::Uzb => "uz",
+        Lang::Pan => "pa",
+        Lang::Aze => "az",
+        Lang::Ind => "id",
+        Lang::Tel => "te",
+        Lang::Pes => "fa",
+        Lang::Mal => "ml",
+        Lang::Ori => "or",
+        Lang::Mya => "my",
+        Lang::Nep => "ne",
+        Lang::Sin => "si",
+        Lang::Khm => "km",
+        Lang::Tuk => "tk",
+        Lang::Aka => "ak",
+        Lang::Zul => "zu",
+        Lang::Sna => "sn",
+        Lang::Afr => "af",
+        Lang::Lat => "la",
+        Lang::Slk => "sk",
+        Lang::Cat => "ca",
+        Lang::Tgl => "tl",
+        Lang::Hye => "hy",
+        Lang::Cym => "cy",
+    }
+}
+
+// テキストの言語をローカルで判定して ISO 639-1 の言語コードを返す
+//   タイトルのような短い文字列では判定が怪しいことが多いので、信頼できる結果だけを返す
+pub fn detect_language(text: &str) -> Option<String> {
+    let info = whatlang::detect(text)?;
+    if !info.is_reliable() {
+        return None;
+    }
+
+    Some(to_iso639_1(info.lang()).to_string())
+}
+
+// 地域によって書き方が変わる言語 (簡体字と繁体字、ブラジルとポルトガルのつづりなど)
+//   whatlang ではどの地域のものか判定できないので、地域を指定した場合は翻訳する
+const REGIONAL_VARIANTS: [&str; 3] = ["zh", "pt", "sr"];
+
+// 判定した言語のまま翻訳先として使えるかを判定する
+//   "ja" と "ja-JP" のように言語部分が一致していても、判定できない文字体系や地域の違いがある場合は別の言語として扱う
+pub fn is_same_language(detected: &str, to: &str) -> bool {
+    let mut subtags = to.split(['-', '_']);
+    let primary_language = subtags.next().unwrap_or(to);
+    if !detected.eq_ignore_ascii_case(primary_language) {
+        return false;
+    }
+
+    let subtags: Vec<&str> = subtags.filter(|subtag| !subtag.is_empty()).collect();
+    // 文字体系 (Hant, Latn など) は判定していないので翻訳する
+    if subtags
+        .iter()
+        .any(|subtag| subtag.len() == 4 && subtag.chars().all(|c| c.is_ascii_alphabetic()))
+    {
+        return false;
+    }
+    let primary_language = primary_language.to_ascii_lowercase();
+    subtags.is_empty() || !REGIONAL_VARIANTS.contains(&primary_language.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_reliable_languages() {
+        assert_eq!(
+            detect_language("Scientists discover new species of fish in the deep ocean"),
+            Some("en".to_string())
+        );
+        assert_eq!(
+            detect_language("今日はとても良い天気なので、公園へ散歩に行きました"),
+            Some("ja".to_string())
+        );
+        assert_eq!(
+            detect_language("Быстрая коричневая лиса прыгает через ленивую собаку"),
+            Some("ru".to_string())
+        );
+        // 短すぎて信頼できないものは判定しない
+        assert_eq!(detect_language("ok"), None);
+        assert_eq!(detect_language(""), None);
+    }
+
+    #[test]
+    fn same_language_only_when_the_variant_does_not_matter() {
+        assert!(is_same_language("ja", "ja"));
+        assert!(is_same_language("ja", "ja-JP"));
+        assert!(is_same_language("en", "EN_us"));
+        assert!(is_same_language("zh", "zh"));
+        assert!(!is_same_language("en", "ja-JP"));
+
+        // 簡体字と繁体字、地域ごとのつづり、文字体系は判定できないので翻訳する
+        assert!(!is_same_language("zh", "zh-TW"));
+        assert!(!is_same_language("zh", "zh-CN"));
+        assert!(!is_same_language("zh", "zh-Hant"));
+        assert!(!is_same_language("pt", "pt-BR"));
+        assert!(!is_same_language("sr", "sr-Latn"));
+        assert!(!is_same_language("uz", "uz-Cyrl"));
+    }
+}
@@ -0,0 +1,359 @@
+use serde_json::Value;
+use std::error::Error;
+use std::fmt;
+use std::time::Duration;
+
+use super::backend::{TranslatResult, TranslatedItem};
+
+// 翻訳バックエンドが返すエラー
+#[derive(Debug)]
+pub enum TranslateError {
+    // 接続できなかった、タイムアウトしたなど
+    Http(reqwest::Error),
+    // APIがエラーを返した (2xx以外のステータス、または {"error":{...}} 形式のレスポンス)
+    Api {
+        backend: String,
+        status: u16,
+        code: Option<String>,
+        message: String,
+    },
+    // レスポンスが想定した形式ではなかった
+    InvalidResponse {
+        backend: String,
+        message: String,
+    },
+    // 送ったテキストと返ってきた翻訳の件数が合わない
+    CountMismatch {
+        backend: String,
+        expected: usize,
+        actual: usize,
+    },
+    // 空でないテキストに対して空の翻訳が返ってきた
+    EmptyTranslation {
+        backend: String,
+        raw_text: String,
+    },
+    // Retry-After で指定された時間が長すぎるので再試行しなかった
+    RateLimited {
+        retry_after: Duration,
+    },
+    Timeout {
+        backend: String,
+        after: Duration,
+    },
+    Other(String),
+}
+
+impl fmt::Display for TranslateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TranslateError::Http(e) => write!(f, "http error: {}", e),
+            TranslateError::Api {
+                backend,
+                status,
+                code,
+                message,
+            } => match code {
+                Some(code) => write!(f, "{} returned {} ({}): {}", backend, status, code, message),
+                None => write!(f, "{} returned {}: {}", backend, status, message),
+            },
+            TranslateError::InvalidResponse { backend, message } => {
+                write!(f, "{} returned an invalid response: {}", backend, message)
+            }
+            TranslateError::CountMismatch {
+                backend,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{} returned {} translations for {} texts",
+                backend, actual, expected
+            ),
+            TranslateError::EmptyTranslation { backend, raw_text } => {
+                write!(
+                    f,
+                    "{} returned an empty translation for {:?}",
+                    backend, raw_text
+                )
+            }
+            TranslateError::RateLimited { retry_after } => write!(
+                f,
+                "rate limited: server asked to retry after {}s",
+                retry_after.as_secs()
+            ),
+            TranslateError::Timeout { backend, after } => {
+                write!(f, "{} timed out after {}ms", backend, after.as_millis())
+            }
+            TranslateError::Other(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl Error for TranslateError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            TranslateError::Http(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+// 失敗したバッチのエラーをテキストごとに持たせるために複製する
+//   reqwest::Error は複製できないので、同じメッセージの Other にする
+impl Clone for TranslateError {
+    fn clone(&self) -> Self {
+        match self {
+            TranslateError::Http(e) => TranslateError::Other(format!("http error: {}", e)),
+            TranslateError::Api {
+                backend,
+                status,
+                code,
+                message,
+            } => TranslateError::Api {
+                backend: backend.clone(),
+                status: *status,
+                code: code.clone(),
+                message: message.clone(),
+            },
+            TranslateError::InvalidResponse { backend, message } => {
+                TranslateError::InvalidResponse {
+                    backend: backend.clone(),
+                    message: message.clone(),
+                }
+            }
+            TranslateError::CountMismatch {
+                backend,
+                expected,
+                actual,
+            } => TranslateError::CountMismatch {
+                backend: backend.clone(),
+                expected: *expected,
+                actual: *actual,
+            },
+            TranslateError::EmptyTranslation { backend, raw_text } => {
+                TranslateError::EmptyTranslation {
+                    backend: backend.clone(),
+                    raw_text: raw_text.clone(),
+                }
+            }
+            TranslateError::RateLimited { retry_after } => TranslateError::RateLimited {
+                retry_after: *retry_after,
+            },
+            TranslateError::Timeout { backend, after } => TranslateError::Timeout {
+                backend: backend.clone(),
+                after: *after,
+            },
+            TranslateError::Other(message) => TranslateError::Other(message.clone()),
+        }
+    }
+}
+
+impl From<reqwest::Error> for TranslateError {
+    fn from(e: reqwest::Error) -> Self {
+        TranslateError::Http(e)
+    }
+}
+
+impl From<serde_json::Error> for TranslateError {
+    fn from(e: serde_json::Error) -> Self {
+        TranslateError::Other(format!("json error: {}", e))
+    }
+}
+
+impl From<url::ParseError> for TranslateError {
+    fn from(e: url::ParseError) -> Self {
+        TranslateError::Other(format!("invalid url: {}", e))
+    }
+}
+
+impl From<Box<dyn Error + Send + Sync>> for TranslateError {
+    fn from(e: Box<dyn Error + Send + Sync>) -> Self {
+        TranslateError::Other(e.to_string())
+    }
+}
+
+impl From<String> for TranslateError {
+    fn from(message: String) -> Self {
+        TranslateError::Other(message)
+    }
+}
+
+impl From<&str> for TranslateError {
+    fn from(message: &str) -> Self {
+        TranslateError::Other(message.to_string())
+    }
+}
+
+impl TranslateError {
+    fn invalid_response(backend: &str, message: impl fmt::Display) -> Self {
+        TranslateError::InvalidResponse {
+            backend: backend.to_string(),
+            message: message.to_string(),
+        }
+    }
+}
+
+// エラーのレスポンスからメッセージとコードを取り出す
+//   {"error":{"message":..,"status":..}} (Google, OpenAI互換), {"error":".."} (LibreTranslate), {"message":".."} (DeepL)
+fn parse_error_payload(body: &str) -> Option<(Option<String>, String)> {
+    let value: Value = serde_json::from_str(body).ok()?;
+    match value.get("error") {
+        Some(Value::Object(error)) => {
+            let message = error
+                .get("message")
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_string();
+            let code = ["status", "type", "code"]
+                .iter()
+                .filter_map(|key| error.get(*key))
+                .find_map(|code| match code {
+                    Value::String(code) => Some(code.clone()),
+                    Value::Number(code) => Some(code.to_string()),
+                    _ => None,
+                });
+            Some((code, message))
+        }
+        Some(Value::String(message)) => Some((None, message.clone())),
+        _ => value
+            .get("message")
+            .and_then(Value::as_str)
+            .map(|message| (None, message.to_string())),
+    }
+}
+
+// ステータスとエラーのペイロードを確認してレスポンスの本文を返す
+//   2xxでもエラーのペイロードが返ってきた場合はエラーにする
+pub async fn read_response(
+    backend: &str,
+    response: reqwest::Response,
+) -> Result<String, TranslateError> {
+    let status = response.status();
+    let response_body = response.text().await?;
+
+    let has_error_payload = serde_json::from_str::<Value>(&response_body)
+        .map(|value| value.get("error").is_some())
+        .unwrap_or(false);
+    if status.is_success() && !has_error_payload {
+        return Ok(response_body);
+    }
+
+    let (code, message) = parse_error_payload(&response_body).unwrap_or((None, response_body));
+    Err(TranslateError::Api {
+        backend: backend.to_string(),
+        status: status.as_u16(),
+        code,
+        message,
+    })
+}
+
+// レスポンスの本文をパースする (失敗した場合はバックエンド名付きのエラーにする)
+pub fn parse_response<T: serde::de::DeserializeOwned>(
+    backend: &str,
+    response_body: &str,
+) -> Result<T, TranslateError> {
+    serde_json::from_str(response_body).map_err(|e| TranslateError::invalid_response(backend, e))
+}
+
+// 送ったテキストと翻訳を対応付ける
+//   件数が合わない場合は、どのテキストが正しく翻訳されたか分からないのでバッチ全体をエラーにする
+//   空の翻訳が返ってきた場合は、そのテキストだけをエラーにして残りは翻訳として返す
+pub fn pair_translations(
+    backend: &str,
+    batch: &[String],
+    translations: Vec<String>,
+) -> Result<Vec<TranslatedItem>, TranslateError> {
+    if translations.len() != batch.len() {
+        return Err(TranslateError::CountMismatch {
+            backend: backend.to_string(),
+            expected: batch.len(),
+            actual: translations.len(),
+        });
+    }
+
+    Ok(batch
+        .iter()
+        .zip(translations)
+        .map(|(raw, translated)| {
+            if !raw.trim().is_empty() && translated.trim().is_empty() {
+                return Err(TranslateError::EmptyTranslation {
+                    backend: backend.to_string(),
+                    raw_text: raw.clone(),
+                });
+            }
+            Ok(TranslatResult {
+                translated,
+                raw_text: raw.clone(),
+                backend: backend.to_string(),
+            })
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(texts: &[&str]) -> Vec<String> {
+        texts.iter().map(|text| text.to_string()).collect()
+    }
+
+    #[test]
+    fn empty_translation_fails_only_its_own_text() {
+        let results = pair_translations(
+            "test",
+            &texts(&["Hello", "World", "Again"]),
+            texts(&["こんにちは", " ", "再び"]),
+        )
+        .unwrap();
+
+        assert_eq!(results[0].as_ref().unwrap().translated, "こんにちは");
+        assert!(matches!(
+            results[1],
+            Err(TranslateError::EmptyTranslation { ref raw_text, .. }) if raw_text == "World"
+        ));
+        assert_eq!(results[2].as_ref().unwrap().translated, "再び");
+    }
+
+    #[test]
+    fn count_mismatch_fails_the_whole_batch() {
+        let result = pair_translations("test", &texts(&["Hello", "World"]), texts(&["こんにちは"]));
+        assert!(matches!(
+            result,
+            Err(TranslateError::CountMismatch {
+                expected: 2,
+                actual: 1,
+                ..
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn empty_translation_does_not_discard_batch_mates() {
+        let limits = super::super::batch::BatchLimits {
+            max_segments: 3,
+            max_chars: 1000,
+            concurrency: 1,
+        };
+        let results = super::super::batch::dispatch(
+            texts(&["Hello", "World", "Again"]),
+            limits,
+            |batch| async move {
+                let translated = batch
+                    .iter()
+                    .map(|text| match text.as_str() {
+                        "World" => String::new(),
+                        _ => format!("[{}]", text),
+                    })
+                    .collect();
+                pair_translations("test", &batch, translated)
+            },
+        )
+        .await;
+
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].as_ref().unwrap().translated, "[Hello]");
+        assert!(results[1].is_err());
+        assert_eq!(results[2].as_ref().unwrap().translated, "[Again]");
+    }
+}
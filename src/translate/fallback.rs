@@ -0,0 +1,310 @@
+use std::time::Duration;
+
+use super::backend::{
+    TranslatResult, TranslateFuture, TranslateOptions, TranslatedItem, TranslationBackend,
+};
+use super::error::TranslateError;
+
+pub struct FallbackBackendOptions {
+    // 先頭から順に試す
+    pub backends: Vec<Box<dyn TranslationBackend>>,
+    // 1つのバックエンドで待つ時間の上限 (Noneの場合は待ち続ける)
+    pub timeout: Option<Duration>,
+}
+
+// 複数のバックエンドを順番に試す
+//   失敗したりタイムアウトした場合は、翻訳できなかったテキストだけを次のバックエンドに送る
+#[derive(Clone)]
+pub struct FallbackBackend {
+    backends: Vec<Box<dyn TranslationBackend>>,
+    timeout: Option<Duration>,
+}
+
+impl TranslationBackend for FallbackBackend {
+    fn translate(
+        &self,
+        target_strs: Vec<String>,
+        options: TranslateOptions,
+    ) -> TranslateFuture<Vec<TranslatedItem>> {
+        let backend = self.clone();
+        Box::pin(async move { backend.translate_with_fallback(target_strs, options).await })
+    }
+
+    // 先頭から順に試して、最初に取得できた一覧を返す
+    fn supported_languages(&self) -> TranslateFuture<Vec<String>> {
+        let backend = self.clone();
+        Box::pin(async move {
+            let mut last_error: TranslateError = "no translation backend".into();
+            for inner in backend.backends.iter() {
+                match inner.supported_languages().await {
+                    Ok(languages) => return Ok(languages),
+                    Err(e) => last_error = e,
+                }
+            }
+            Err(last_error)
+        })
+    }
+
+    fn name(&self) -> String {
+        self.backends
+            .iter()
+            .map(|backend| backend.name())
+            .collect::<Vec<String>>()
+            .join(">")
+    }
+
+    fn version(&self) -> String {
+        self.backends
+            .iter()
+            .map(|backend| backend.version())
+            .collect::<Vec<String>>()
+            .join(">")
+    }
+
+    fn clone_box(&self) -> Box<dyn TranslationBackend> {
+        Box::new(self.clone())
+    }
+}
+
+impl FallbackBackend {
+    pub fn new(options: FallbackBackendOptions) -> Self {
+        FallbackBackend {
+            backends: options.backends,
+            timeout: options.timeout,
+        }
+    }
+
+    async fn translate_inner(
+        &self,
+        backend: &dyn TranslationBackend,
+        target_strs: Vec<String>,
+        options: TranslateOptions,
+    ) -> Result<Vec<TranslatedItem>, TranslateError> {
+        let future = backend.translate(target_strs, options);
+        match self.timeout {
+            Some(timeout) => match tokio::time::timeout(timeout, future).await {
+                Ok(result) => result,
+                Err(_) => Err(TranslateError::Timeout {
+                    backend: backend.name(),
+                    after: timeout,
+                }),
+            },
+            None => future.await,
+        }
+    }
+
+    // 結果は入力と同じ順番で返す
+    //   どのバックエンドでも翻訳できなかったテキストは、最後のバックエンドのエラーを返す
+    async fn translate_with_fallback(
+        &self,
+        target_strs: Vec<String>,
+        options: TranslateOptions,
+    ) -> Result<Vec<TranslatedItem>, TranslateError> {
+        let mut results: Vec<Option<TranslatResult>> = target_strs.iter().map(|_| None).collect();
+        let mut errors: Vec<TranslateError> = target_strs
+            .iter()
+            .map(|_| "no translation backend is configured".into())
+            .collect();
+
+        for backend in self.backends.iter() {
+            let pending: Vec<usize> = (0..target_strs.len())
+                .filter(|index| results[*index].is_none())
+                .collect();
+            if pending.is_empty() {
+                break;
+            }
+
+            let pending_strs: Vec<String> = pending
+                .iter()
+                .map(|index| target_strs[*index].clone())
+                .collect();
+            let translated = match self
+                .translate_inner(backend.as_ref(), pending_strs, options.clone())
+                .await
+            {
+                Ok(translated) => translated,
+                Err(e) => {
+                    println!(
+                        "translation backend {} failed, falling back: {}",
+                        backend.name(),
+                        e
+                    );
+                    for index in pending.iter() {
+                        errors[*index] = e.clone();
+                    }
+                    continue;
+                }
+            };
+
+            // 送った順番で対応付け、失敗したものと返ってこなかったものだけを次のバックエンドに回す
+            let mut translated = translated.into_iter();
+            let mut failed = 0;
+            for index in pending.iter() {
+                match translated.next() {
+                    Some(Ok(translated)) if translated.raw_text == target_strs[*index] => {
+                        results[*index] = Some(translated);
+                    }
+                    Some(Ok(_)) => {
+                        failed += 1;
+                        errors[*index] =
+                            format!("{} returned a translation for another text", backend.name())
+                                .into();
+                    }
+                    Some(Err(e)) => {
+                        failed += 1;
+                        errors[*index] = e;
+                    }
+                    None => {
+                        failed += 1;
+                        errors[*index] =
+                            format!("{} did not return a translation", backend.name()).into();
+                    }
+                }
+            }
+            if failed > 0 {
+                println!(
+                    "translation backend {} failed for {} of {} texts, falling back",
+                    backend.name(),
+                    failed,
+                    pending.len()
+                );
+            }
+        }
+
+        Ok(results
+            .into_iter()
+            .zip(errors)
+            .map(|(result, error)| result.ok_or(error))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::translate::batch::{self, BatchLimits};
+    use std::sync::{Arc, Mutex};
+
+    // 指定したテキストを含むバッチだけを失敗させ、受け取ったテキストを記録する
+    #[derive(Clone)]
+    struct MockBackend {
+        name: String,
+        failing: Vec<String>,
+        received: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl MockBackend {
+        fn new(name: &str, failing: &[&str]) -> Self {
+            MockBackend {
+                name: name.to_string(),
+                failing: failing.iter().map(|text| text.to_string()).collect(),
+                received: Arc::new(Mutex::new(Vec::new())),
+            }
+        }
+    }
+
+    impl TranslationBackend for MockBackend {
+        fn translate(
+            &self,
+            target_strs: Vec<String>,
+            _options: TranslateOptions,
+        ) -> TranslateFuture<Vec<TranslatedItem>> {
+            let backend = self.clone();
+            Box::pin(async move {
+                backend.received.lock().unwrap().extend(target_strs.clone());
+                let limits = BatchLimits {
+                    max_segments: 1,
+                    max_chars: 1000,
+                    concurrency: 2,
+                };
+                Ok(batch::dispatch(target_strs, limits, move |batch| {
+                    let backend = backend.clone();
+                    async move {
+                        if batch.iter().any(|text| backend.failing.contains(text)) {
+                            return Err(format!("{} failed", backend.name).into());
+                        }
+                        Ok(batch
+                            .into_iter()
+                            .map(|text| {
+                                Ok(TranslatResult {
+                                    translated: format!("{}:{}", backend.name, text),
+                                    raw_text: text,
+                                    backend: backend.name.clone(),
+                                })
+                            })
+                            .collect())
+                    }
+                })
+                .await)
+            })
+        }
+
+        fn supported_languages(&self) -> TranslateFuture<Vec<String>> {
+            Box::pin(async move { Ok(Vec::new()) })
+        }
+
+        fn name(&self) -> String {
+            self.name.clone()
+        }
+
+        fn version(&self) -> String {
+            "test".to_string()
+        }
+
+        fn clone_box(&self) -> Box<dyn TranslationBackend> {
+            Box::new(self.clone())
+        }
+    }
+
+    fn texts(texts: &[&str]) -> Vec<String> {
+        texts.iter().map(|text| text.to_string()).collect()
+    }
+
+    #[tokio::test]
+    async fn only_failed_texts_go_to_the_next_backend() {
+        let first = MockBackend::new("first", &["b"]);
+        let second = MockBackend::new("second", &[]);
+        let fallback = FallbackBackend::new(FallbackBackendOptions {
+            backends: vec![Box::new(first.clone()), Box::new(second.clone())],
+            timeout: None,
+        });
+
+        let results = fallback
+            .translate(
+                texts(&["a", "b", "c"]),
+                TranslateOptions::new("ja".to_string()),
+            )
+            .await
+            .unwrap();
+        let translated: Vec<String> = results
+            .into_iter()
+            .map(|result| result.unwrap().translated)
+            .collect();
+        assert_eq!(translated, texts(&["first:a", "second:b", "first:c"]));
+        assert_eq!(*second.received.lock().unwrap(), texts(&["b"]));
+    }
+
+    #[tokio::test]
+    async fn unresolved_texts_do_not_discard_translated_ones() {
+        let first = MockBackend::new("first", &["b"]);
+        let second = MockBackend::new("second", &["b"]);
+        let fallback = FallbackBackend::new(FallbackBackendOptions {
+            backends: vec![Box::new(first), Box::new(second)],
+            timeout: None,
+        });
+
+        let results = fallback
+            .translate(
+                texts(&["a", "b", "c"]),
+                TranslateOptions::new("ja".to_string()),
+            )
+            .await
+            .unwrap();
+        assert_eq!(results[0].as_ref().unwrap().translated, "first:a");
+        assert_eq!(
+            results[1].as_ref().err().unwrap().to_string(),
+            "second failed"
+        );
+        assert_eq!(results[2].as_ref().unwrap().translated, "first:c");
+    }
+}
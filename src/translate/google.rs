@@ -0,0 +1,856 @@
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use url::Url;
+
+mod credentials;
+use credentials::GoogleCredentials;
+
+use super::backend::{TranslateFuture, TranslateOptions, TranslatedItem, TranslationBackend};
+use super::batch::{self, BatchLimits};
+use super::error::{self, TranslateError};
+use super::retry::RequestPolicy;
+
+#[derive(Serialize, Deserialize)]
+#[allow(non_snake_case)]
+struct Translated {
+    translatedText: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ReponseData {
+    translations: Vec<Translated>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Response {
+    data: ReponseData,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SupportedLanguage {
+    language: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SupportedLanguagesData {
+    languages: Vec<SupportedLanguage>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SupportedLanguagesResponse {
+    data: SupportedLanguagesData,
+}
+
+#[derive(Serialize, Deserialize)]
+#[allow(non_snake_case)]
+struct TranslatedV3 {
+    translatedText: String,
+}
+
+#[derive(Serialize, Deserialize)]
+#[allow(non_snake_case)]
+struct ResponseV3 {
+    translations: Vec<TranslatedV3>,
+    // グロッサリーを指定した場合はこちらにグロッサリー適用後の翻訳が入る
+    glossaryTranslations: Option<Vec<TranslatedV3>>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[allow(non_snake_case)]
+struct SupportedLanguageV3 {
+    languageCode: String,
+    supportTarget: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SupportedLanguagesResponseV3 {
+    languages: Vec<SupportedLanguageV3>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[allow(non_snake_case)]
+struct GlossaryLanguagePair {
+    sourceLanguageCode: String,
+}
+
+#[derive(Serialize, Deserialize)]
+#[allow(non_snake_case)]
+struct Glossary {
+    languagePair: Option<GlossaryLanguagePair>,
+}
+
+#[derive(Clone, PartialEq)]
+pub enum GoogleTranslateApiVersion {
+    // Cloud Translation Basic
+    V2,
+    // Cloud Translation Advanced
+    V3,
+}
+
+// 期限の少し前に更新しておくための猶予 (秒)
+const ACCESS_TOKEN_REFRESH_MARGIN_SEC: i64 = 300;
+
+#[derive(Default)]
+struct AccessToken {
+    token: String,
+    expires_at: i64,
+}
+
+#[derive(Clone)]
+pub struct GoogleTranslateBackend {
+    client: reqwest::Client,
+    base_url: Url,
+    project_id: String,
+    credentials: GoogleCredentials,
+    // リクエストごとにcloneされても同じトークンを使い回せるように共有する
+    access_token: Arc<Mutex<AccessToken>>,
+    api_version: GoogleTranslateApiVersion,
+    location: String,
+    model: Option<String>,
+    glossary_id: Option<String>,
+    mime_type: Option<String>,
+    // グロッサリーID -> 翻訳元言語
+    glossary_source_langs: Arc<Mutex<HashMap<String, Option<String>>>>,
+    batch_limits: BatchLimits,
+    request_policy: RequestPolicy,
+}
+
+pub struct GoogleTranslateBackendOptions {
+    // 未指定の場合は認証情報 (鍵のファイルやメタデータサーバー) から取得する
+    pub project_id: Option<String>,
+    // 未指定の場合は gcloud の認証情報やメタデータサーバーを探す
+    pub credentials_file: Option<String>,
+    // 例: http://metadata.google.internal/
+    pub metadata_server_url: Option<String>,
+    // 未指定の場合は https://translation.googleapis.com/ (検証用のスタブサーバーに向ける場合に指定する)
+    pub endpoint_url: Option<String>,
+    pub api_version: GoogleTranslateApiVersion,
+    // 以下はv3でのみ利用する
+    pub location: String,
+    pub model: Option<String>,
+    pub glossary_id: Option<String>,
+    // v2では text/html の場合だけ format: html として送る
+    pub mime_type: Option<String>,
+    pub batch_limits: BatchLimits,
+    pub request_policy: RequestPolicy,
+}
+
+impl TranslationBackend for GoogleTranslateBackend {
+    fn translate(
+        &self,
+        target_strs: Vec<String>,
+        options: TranslateOptions,
+    ) -> TranslateFuture<Vec<TranslatedItem>> {
+        let backend = self.clone();
+        Box::pin(async move {
+            match backend.api_version {
+                GoogleTranslateApiVersion::V2 => {
+                    backend.translate_batches(target_strs, options).await
+                }
+                GoogleTranslateApiVersion::V3 => {
+                    backend.translate_batches_v3(target_strs, options).await
+                }
+            }
+        })
+    }
+
+    fn supported_languages(&self) -> TranslateFuture<Vec<String>> {
+        let backend = self.clone();
+        Box::pin(async move {
+            match backend.api_version {
+                GoogleTranslateApiVersion::V2 => backend.fetch_supported_languages().await,
+                GoogleTranslateApiVersion::V3 => backend.fetch_supported_languages_v3().await,
+            }
+        })
+    }
+
+    fn name(&self) -> String {
+        "google".to_string()
+    }
+
+    fn version(&self) -> String {
+        match self.api_version {
+            GoogleTranslateApiVersion::V2 => "v2".to_string(),
+            GoogleTranslateApiVersion::V3 => {
+                let mut version = match self.model.clone() {
+                    Some(model) => format!("v3/{}", model),
+                    None => "v3".to_string(),
+                };
+                // 用語集のデフォルトを設定している場合は別の翻訳として扱う
+                if let Some(glossary_id) = self.glossary_id.as_ref() {
+                    version.push_str(&format!("/glossary={}", glossary_id));
+                }
+                version
+            }
+        }
+    }
+
+    fn clone_box(&self) -> Box<dyn TranslationBackend> {
+        Box::new(self.clone())
+    }
+}
+
+impl GoogleTranslateBackend {
+    // 1リクエストあたり128件、30,000コードポイントまでが推奨されている
+    pub const DEFAULT_BATCH_LIMITS: BatchLimits = BatchLimits {
+        max_segments: 128,
+        max_chars: 30000,
+        concurrency: 4,
+    };
+
+    // 認証情報の検出とプロジェクトIDの解決に通信が必要な場合があるので非同期で作る
+    pub async fn new(
+        options: GoogleTranslateBackendOptions,
+    ) -> Result<GoogleTranslateBackend, TranslateError> {
+        let credentials =
+            GoogleCredentials::detect(options.credentials_file, options.metadata_server_url)?;
+        println!("google credentials: {}", credentials.kind());
+        let base_url = Url::parse(
+            &options
+                .endpoint_url
+                .unwrap_or("https://translation.googleapis.com/".to_string()),
+        )?;
+
+        let client = reqwest::Client::new();
+        let project_id = match options.project_id {
+            Some(project_id) => project_id,
+            None => match credentials.project_id(&client).await? {
+                Some(project_id) => project_id,
+                None => {
+                    return Err(format!(
+                        "project id could not be determined from {} credentials",
+                        credentials.kind()
+                    )
+                    .into())
+                }
+            },
+        };
+        println!("google project: {}", project_id);
+
+        Ok(GoogleTranslateBackend {
+            project_id,
+            client,
+            base_url,
+            credentials,
+            access_token: Arc::new(Mutex::new(AccessToken::default())),
+            api_version: options.api_version,
+            location: options.location,
+            model: options.model,
+            glossary_id: options.glossary_id,
+            mime_type: options.mime_type,
+            glossary_source_langs: Arc::new(Mutex::new(HashMap::new())),
+            batch_limits: options.batch_limits,
+            request_policy: options.request_policy,
+        })
+    }
+
+    fn parent(&self) -> String {
+        format!("projects/{}/locations/{}", self.project_id, self.location)
+    }
+
+    // "nmt" や "translation-llm" のような短い名前をモデルのリソース名に変換する
+    fn model_resource_name(&self, model: &str) -> String {
+        if model.contains('/') {
+            return model.to_string();
+        }
+        format!("{}/models/general/{}", self.parent(), model)
+    }
+
+    fn glossary_resource_name(&self, glossary_id: &str) -> String {
+        if glossary_id.contains('/') {
+            return glossary_id.to_string();
+        }
+        format!("{}/glossaries/{}", self.parent(), glossary_id)
+    }
+
+    async fn get_access_token(&self) -> Result<String, TranslateError> {
+        let now_sec = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_err(|e| TranslateError::Other(e.to_string()))?
+            .as_secs() as i64;
+
+        // ロックを持ったまま更新することで、同時に来たリクエストが重複して更新しないようにする
+        let mut access_token = self.access_token.lock().await;
+        if access_token.expires_at - ACCESS_TOKEN_REFRESH_MARGIN_SEC > now_sec {
+            return Ok(access_token.token.clone());
+        }
+
+        let (token, expires_at) = self.credentials.fetch_access_token(&self.client).await?;
+        access_token.token = token.clone();
+        access_token.expires_at = expires_at;
+
+        Ok(token)
+    }
+
+    // 401が返ってきたトークンを破棄する
+    //   他のリクエストが既に更新していた場合はそちらを残す
+    async fn invalidate_access_token(&self, token: &str) {
+        let mut access_token = self.access_token.lock().await;
+        if access_token.token == token {
+            access_token.expires_at = 0;
+        }
+    }
+
+    // 認証ヘッダーを付けて送信する
+    //   429や5xxの再試行と流量制限は request_policy に任せる
+    //   401が返ってきた場合はトークンを取り直して1回だけ再送する
+    async fn send_authorized(
+        &self,
+        request: reqwest::RequestBuilder,
+        chars: usize,
+    ) -> Result<reqwest::Response, TranslateError> {
+        let retry_request = request.try_clone();
+        let api_key = self.get_access_token().await?;
+        let request = request
+            .header("Authorization", format!("Bearer {}", api_key))
+            .header("x-goog-user-project", &self.project_id);
+        let response = self.request_policy.send(request, chars).await?;
+        if response.status() != reqwest::StatusCode::UNAUTHORIZED {
+            return Ok(response);
+        }
+        let retry_request = match retry_request {
+            Some(retry_request) => retry_request,
+            None => return Ok(response),
+        };
+
+        println!("access token was rejected, refreshing and retrying");
+        self.invalidate_access_token(&api_key).await;
+        let api_key = self.get_access_token().await?;
+        let retry_request = retry_request
+            .header("Authorization", format!("Bearer {}", api_key))
+            .header("x-goog-user-project", &self.project_id);
+        let response = self.request_policy.send(retry_request, chars).await?;
+
+        Ok(response)
+    }
+
+    async fn translate_batches(
+        &self,
+        target_strs: Vec<String>,
+        options: TranslateOptions,
+    ) -> Result<Vec<TranslatedItem>, TranslateError> {
+        // 未指定の場合はHTMLとして扱われ、翻訳結果の記号が &#39; のようなエンティティで返ってくる
+        //   HTMLの断片を送る場合だけ html を指定する
+        let format = match options.mime_type.or(self.mime_type.clone()).as_deref() {
+            Some("text/html") => "html",
+            _ => "text",
+        };
+        let to = options.to;
+
+        let backend = self.clone();
+        let results = batch::dispatch(target_strs, self.batch_limits, move |batch| {
+            let backend = backend.clone();
+            let to = to.clone();
+            async move { backend.translate_batch(batch, to, format).await }
+        })
+        .await;
+        Ok(results)
+    }
+
+    async fn translate_batch(
+        &self,
+        batch: Vec<String>,
+        to: String,
+        format: &str,
+    ) -> Result<Vec<TranslatedItem>, TranslateError> {
+        let endpoint = self.base_url.join("language/translate/v2")?;
+        let chars = batch.iter().map(|text| text.chars().count()).sum();
+
+        let request_json = json!({
+            "q": batch,
+            "target": to,
+            "format": format
+        });
+
+        let response = self
+            .send_authorized(
+                self.client
+                    .post(endpoint)
+                    .header("Content-Type", "application/json")
+                    .body(serde_json::to_string(&request_json)?),
+                chars,
+            )
+            .await?;
+
+        let response_body = error::read_response(&self.name(), response).await?;
+
+        let parsed_response: Response = error::parse_response(&self.name(), &response_body)?;
+        let translated: Vec<String> = parsed_response
+            .data
+            .translations
+            .into_iter()
+            .map(|t| t.translatedText)
+            .collect();
+
+        error::pair_translations(&self.name(), &batch, translated)
+    }
+
+    async fn fetch_supported_languages(&self) -> Result<Vec<String>, TranslateError> {
+        let endpoint = self.base_url.join("language/translate/v2/languages")?;
+
+        let response = self.send_authorized(self.client.get(endpoint), 0).await?;
+        let response_body = error::read_response(&self.name(), response).await?;
+
+        let parsed_response: SupportedLanguagesResponse =
+            error::parse_response(&self.name(), &response_body)?;
+        let languages = parsed_response
+            .data
+            .languages
+            .into_iter()
+            .map(|language| language.language)
+            .collect();
+
+        Ok(languages)
+    }
+
+    // グロッサリーを使う場合は sourceLanguageCode の指定が必須なのでグロッサリーの情報から取得する
+    //   言語セットのグロッサリーの場合は翻訳元を決められないので None を返す
+    async fn get_glossary_source_lang(
+        &self,
+        glossary: &str,
+    ) -> Result<Option<String>, TranslateError> {
+        let mut source_langs = self.glossary_source_langs.lock().await;
+        if let Some(source_lang) = source_langs.get(glossary) {
+            return Ok(source_lang.clone());
+        }
+
+        let endpoint = self.base_url.join(&format!("v3/{}", glossary))?;
+        let response = self.send_authorized(self.client.get(endpoint), 0).await?;
+        let response_body = error::read_response(&self.name(), response).await?;
+        let parsed_response: Glossary = error::parse_response(&self.name(), &response_body)?;
+
+        let source_lang = parsed_response
+            .languagePair
+            .map(|language_pair| language_pair.sourceLanguageCode);
+        source_langs.insert(glossary.to_string(), source_lang.clone());
+        Ok(source_lang)
+    }
+
+    async fn translate_batches_v3(
+        &self,
+        target_strs: Vec<String>,
+        options: TranslateOptions,
+    ) -> Result<Vec<TranslatedItem>, TranslateError> {
+        let model = options.model.or(self.model.clone());
+        let glossary_id = options.glossary_id.or(self.glossary_id.clone());
+        let mime_type = options
+            .mime_type
+            .or(self.mime_type.clone())
+            .unwrap_or("text/plain".to_string());
+        let glossary = glossary_id.map(|glossary_id| self.glossary_resource_name(&glossary_id));
+        let source_lang = match glossary.clone() {
+            Some(glossary) => self.get_glossary_source_lang(&glossary).await?,
+            None => None,
+        };
+
+        // contents 以外はすべてのバッチで共通
+        let mut request_json = json!({
+            "targetLanguageCode": options.to,
+            "mimeType": mime_type
+        });
+        if let Some(model) = model {
+            request_json["model"] = json!(self.model_resource_name(&model));
+        }
+        if let Some(glossary) = glossary {
+            request_json["glossaryConfig"] = json!({ "glossary": glossary });
+        }
+        if let Some(source_lang) = source_lang {
+            request_json["sourceLanguageCode"] = json!(source_lang);
+        }
+
+        let backend = self.clone();
+        let results = batch::dispatch(target_strs, self.batch_limits, move |batch| {
+            let backend = backend.clone();
+            let request_json = request_json.clone();
+            async move { backend.translate_batch_v3(batch, request_json).await }
+        })
+        .await;
+        Ok(results)
+    }
+
+    async fn translate_batch_v3(
+        &self,
+        batch: Vec<String>,
+        mut request_json: serde_json::Value,
+    ) -> Result<Vec<TranslatedItem>, TranslateError> {
+        let endpoint = self
+            .base_url
+            .join(&format!("v3/{}:translateText", self.parent()))?;
+        let chars = batch.iter().map(|text| text.chars().count()).sum();
+        request_json["contents"] = json!(batch);
+
+        let response = self
+            .send_authorized(
+                self.client
+                    .post(endpoint)
+                    .header("Content-Type", "application/json")
+                    .body(serde_json::to_string(&request_json)?),
+                chars,
+            )
+            .await?;
+        let response_body = error::read_response(&self.name(), response).await?;
+        let parsed_response: ResponseV3 = error::parse_response(&self.name(), &response_body)?;
+        let translations = match parsed_response.glossaryTranslations {
+            Some(glossary_translations) => glossary_translations,
+            None => parsed_response.translations,
+        };
+        let translated: Vec<String> = translations
+            .into_iter()
+            .map(|t| t.translatedText)
+            .collect();
+
+        error::pair_translations(&self.name(), &batch, translated)
+    }
+
+    async fn fetch_supported_languages_v3(
+        &self,
+    ) -> Result<Vec<String>, TranslateError> {
+        let endpoint = self
+            .base_url
+            .join(&format!("v3/{}/supportedLanguages", self.parent()))?;
+
+        let response = self.send_authorized(self.client.get(endpoint), 0).await?;
+        let response_body = error::read_response(&self.name(), response).await?;
+
+        let parsed_response: SupportedLanguagesResponseV3 =
+            error::parse_response(&self.name(), &response_body)?;
+        let languages = parsed_response
+            .languages
+            .into_iter()
+            .filter(|language| language.supportTarget.unwrap_or(true))
+            .map(|language| language.languageCode)
+            .collect();
+
+        Ok(languages)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::translate::test_server::TestServer;
+
+    const TOKEN_PATH: &str = "/token";
+    const TRANSLATE_V3_PATH: &str = "/v3/projects/my-project/locations/global:translateText";
+    const TRANSLATE_V2_PATH: &str = "/language/translate/v2";
+    const V2_RESPONSE: &str = r#"{"data":{"translations":[{"translatedText":"こんにちは"}]}}"#;
+
+    // gcloud のファイルと同じ形式の認証情報で、トークンはテスト用のサーバーから取得する
+    async fn backend(
+        server: &TestServer,
+        directory: &tempfile::TempDir,
+        api_version: GoogleTranslateApiVersion,
+        model: Option<&str>,
+        glossary_id: Option<&str>,
+    ) -> GoogleTranslateBackend {
+        let credentials_file = directory.path().join("credentials.json");
+        let credentials = json!({
+            "type": "authorized_user",
+            "client_id": "client",
+            "client_secret": "secret",
+            "refresh_token": "refresh",
+            "token_uri": format!("{}token", server.url)
+        });
+        std::fs::write(&credentials_file, credentials.to_string()).unwrap();
+
+        GoogleTranslateBackend::new(GoogleTranslateBackendOptions {
+            project_id: Some("my-project".to_string()),
+            credentials_file: Some(credentials_file.to_str().unwrap().to_string()),
+            metadata_server_url: None,
+            endpoint_url: Some(server.url.clone()),
+            api_version,
+            location: "global".to_string(),
+            model: model.map(|model| model.to_string()),
+            glossary_id: glossary_id.map(|glossary_id| glossary_id.to_string()),
+            mime_type: None,
+            batch_limits: GoogleTranslateBackend::DEFAULT_BATCH_LIMITS,
+            request_policy: RequestPolicy::default(),
+        })
+        .await
+        .unwrap()
+    }
+
+    fn texts(texts: &[&str]) -> Vec<String> {
+        texts.iter().map(|text| text.to_string()).collect()
+    }
+
+    fn body(request: &crate::translate::test_server::ReceivedRequest) -> serde_json::Value {
+        serde_json::from_str(&request.body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn v3_requests_include_the_model_and_glossary() {
+        let server = TestServer::start(vec![
+            (
+                TOKEN_PATH,
+                vec![(200, r#"{"access_token":"token-1","expires_in":3600}"#)],
+            ),
+            (
+                "/v3/projects/my-project/locations/global/glossaries/terms",
+                vec![(
+                    200,
+                    r#"{"languagePair":{"sourceLanguageCode":"en","targetLanguageCode":"ja"}}"#,
+                )],
+            ),
+            (
+                TRANSLATE_V3_PATH,
+                vec![(
+                    200,
+                    r#"{"translations":[{"translatedText":"ハロー"}],"glossaryTranslations":[{"translatedText":"こんにちは"}]}"#,
+                )],
+            ),
+        ])
+        .await;
+        let directory = tempfile::tempdir().unwrap();
+        let backend = backend(
+            &server,
+            &directory,
+            GoogleTranslateApiVersion::V3,
+            Some("translation-llm"),
+            Some("terms"),
+        )
+        .await;
+        assert_eq!(backend.version(), "v3/translation-llm/glossary=terms");
+
+        let translated = backend
+            .translate(texts(&["Hello"]), TranslateOptions::new("ja".to_string()))
+            .await
+            .unwrap();
+        // 用語集を使った翻訳を優先する
+        assert_eq!(translated[0].as_ref().unwrap().translated, "こんにちは");
+
+        let request = &server.requests(TRANSLATE_V3_PATH)[0];
+        assert_eq!(request.header("Authorization"), Some("Bearer token-1"));
+        assert_eq!(request.header("x-goog-user-project"), Some("my-project"));
+        assert_eq!(
+            body(request),
+            json!({
+                "contents": ["Hello"],
+                "targetLanguageCode": "ja",
+                "mimeType": "text/plain",
+                "model": "projects/my-project/locations/global/models/general/translation-llm",
+                "glossaryConfig": {
+                    "glossary": "projects/my-project/locations/global/glossaries/terms"
+                },
+                "sourceLanguageCode": "en"
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn v3_request_options_override_the_defaults() {
+        let server = TestServer::start(vec![
+            (
+                TOKEN_PATH,
+                vec![(200, r#"{"access_token":"token-1","expires_in":3600}"#)],
+            ),
+            (
+                TRANSLATE_V3_PATH,
+                vec![(
+                    200,
+                    r#"{"translations":[{"translatedText":"<b>こんにちは</b>"}]}"#,
+                )],
+            ),
+        ])
+        .await;
+        let directory = tempfile::tempdir().unwrap();
+        let backend = backend(
+            &server,
+            &directory,
+            GoogleTranslateApiVersion::V3,
+            None,
+            None,
+        )
+        .await;
+        assert_eq!(backend.version(), "v3");
+
+        let mut options = TranslateOptions::new("ja".to_string());
+        options.model = Some("projects/other/locations/us-central1/models/custom".to_string());
+        options.mime_type = Some("text/html".to_string());
+        backend
+            .translate(texts(&["<b>Hello</b>"]), options)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            body(&server.requests(TRANSLATE_V3_PATH)[0]),
+            json!({
+                "contents": ["<b>Hello</b>"],
+                "targetLanguageCode": "ja",
+                "mimeType": "text/html",
+                "model": "projects/other/locations/us-central1/models/custom"
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn the_access_token_is_shared_across_clones() {
+        let server = TestServer::start(vec![
+            (
+                TOKEN_PATH,
+                vec![
+                    (200, r#"{"access_token":"token-1","expires_in":3600}"#),
+                    (200, r#"{"access_token":"token-2","expires_in":3600}"#),
+                ],
+            ),
+            (TRANSLATE_V2_PATH, vec![(200, V2_RESPONSE)]),
+        ])
+        .await;
+        let directory = tempfile::tempdir().unwrap();
+        let backend = backend(
+            &server,
+            &directory,
+            GoogleTranslateApiVersion::V2,
+            None,
+            None,
+        )
+        .await;
+
+        // リクエストごとに clone されたものから同時に翻訳しても、トークンは1回だけ取得する
+        let mut tasks = tokio::task::JoinSet::new();
+        for _ in 0..5 {
+            let backend = backend.clone_box();
+            tasks.spawn(async move {
+                backend
+                    .translate(texts(&["Hello"]), TranslateOptions::new("ja".to_string()))
+                    .await
+                    .unwrap()
+            });
+        }
+        while let Some(joined) = tasks.join_next().await {
+            assert_eq!(
+                joined.unwrap()[0].as_ref().unwrap().translated,
+                "こんにちは"
+            );
+        }
+
+        assert_eq!(server.requests(TOKEN_PATH).len(), 1);
+        let requests = server.requests(TRANSLATE_V2_PATH);
+        assert_eq!(requests.len(), 5);
+        assert!(requests
+            .iter()
+            .all(|request| request.header("Authorization") == Some("Bearer token-1")));
+    }
+
+    #[tokio::test]
+    async fn tokens_close_to_expiry_are_refreshed() {
+        let server = TestServer::start(vec![
+            (
+                TOKEN_PATH,
+                vec![
+                    // 更新の猶予より短いので、次のリクエストでは取り直す
+                    (200, r#"{"access_token":"token-1","expires_in":60}"#),
+                    (200, r#"{"access_token":"token-2","expires_in":3600}"#),
+                ],
+            ),
+            (TRANSLATE_V2_PATH, vec![(200, V2_RESPONSE)]),
+        ])
+        .await;
+        let directory = tempfile::tempdir().unwrap();
+        let backend = backend(
+            &server,
+            &directory,
+            GoogleTranslateApiVersion::V2,
+            None,
+            None,
+        )
+        .await;
+
+        for _ in 0..3 {
+            backend
+                .translate(texts(&["Hello"]), TranslateOptions::new("ja".to_string()))
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(server.requests(TOKEN_PATH).len(), 2);
+        let authorizations: Vec<String> = server
+            .requests(TRANSLATE_V2_PATH)
+            .iter()
+            .map(|request| request.header("Authorization").unwrap().to_string())
+            .collect();
+        assert_eq!(
+            authorizations,
+            vec!["Bearer token-1", "Bearer token-2", "Bearer token-2"]
+        );
+    }
+
+    #[tokio::test]
+    async fn a_rejected_token_is_refreshed_and_retried_once() {
+        let server = TestServer::start(vec![
+            (
+                TOKEN_PATH,
+                vec![
+                    (200, r#"{"access_token":"token-1","expires_in":3600}"#),
+                    (200, r#"{"access_token":"token-2","expires_in":3600}"#),
+                ],
+            ),
+            (
+                TRANSLATE_V2_PATH,
+                vec![
+                    (401, r#"{"error":{"code":401,"message":"invalid token"}}"#),
+                    (200, V2_RESPONSE),
+                ],
+            ),
+        ])
+        .await;
+        let directory = tempfile::tempdir().unwrap();
+        let backend = backend(
+            &server,
+            &directory,
+            GoogleTranslateApiVersion::V2,
+            None,
+            None,
+        )
+        .await;
+
+        let translated = backend
+            .translate(texts(&["Hello"]), TranslateOptions::new("ja".to_string()))
+            .await
+            .unwrap();
+        assert_eq!(translated[0].as_ref().unwrap().translated, "こんにちは");
+
+        let requests = server.requests(TRANSLATE_V2_PATH);
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[1].header("Authorization"), Some("Bearer token-2"));
+        assert_eq!(requests[0].body, requests[1].body);
+        assert_eq!(server.requests(TOKEN_PATH).len(), 2);
+    }
+
+    #[tokio::test]
+    async fn a_second_401_is_returned_as_an_error() {
+        let server = TestServer::start(vec![
+            (
+                TOKEN_PATH,
+                vec![(200, r#"{"access_token":"token","expires_in":3600}"#)],
+            ),
+            (
+                TRANSLATE_V2_PATH,
+                vec![(401, r#"{"error":{"code":401,"message":"invalid token"}}"#)],
+            ),
+        ])
+        .await;
+        let directory = tempfile::tempdir().unwrap();
+        let backend = backend(
+            &server,
+            &directory,
+            GoogleTranslateApiVersion::V2,
+            None,
+            None,
+        )
+        .await;
+
+        let translated = backend
+            .translate(texts(&["Hello"]), TranslateOptions::new("ja".to_string()))
+            .await
+            .unwrap();
+        assert!(matches!(
+            translated[0],
+            Err(TranslateError::Api { status: 401, .. })
+        ));
+        assert_eq!(server.requests(TRANSLATE_V2_PATH).len(), 2);
+    }
+}
@@ -0,0 +1,573 @@
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use std::error::Error;
+use url::Url;
+
+const TRANSLATION_SCOPE: &str = "https://www.googleapis.com/auth/cloud-translation";
+const CLOUD_PLATFORM_SCOPE: &str = "https://www.googleapis.com/auth/cloud-platform";
+const DEFAULT_TOKEN_URI: &str = "https://oauth2.googleapis.com/token";
+pub const DEFAULT_METADATA_SERVER_URL: &str = "http://metadata.google.internal/";
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ServiceAccountFile {
+    project_id: String,
+    private_key_id: String,
+    private_key: String,
+    client_email: String,
+    client_id: String,
+    auth_uri: String,
+    token_uri: String,
+    auth_provider_x509_cert_url: String,
+    client_x509_cert_url: String,
+}
+
+// `gcloud auth application-default login` で作られるファイル
+#[derive(Serialize, Deserialize, Clone)]
+pub struct AuthorizedUserFile {
+    client_id: String,
+    client_secret: String,
+    refresh_token: String,
+    token_uri: Option<String>,
+    quota_project_id: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct CredentialSourceFormat {
+    #[serde(rename = "type")]
+    format_type: String,
+    subject_token_field_name: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct CredentialSource {
+    file: Option<String>,
+    url: Option<String>,
+    headers: Option<HashMap<String, String>>,
+    format: Option<CredentialSourceFormat>,
+}
+
+// Workload Identity 連携で使われるファイル
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ExternalAccountFile {
+    audience: String,
+    subject_token_type: String,
+    token_url: String,
+    service_account_impersonation_url: Option<String>,
+    credential_source: CredentialSource,
+    quota_project_id: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct GoogleJwt {
+    iss: String,
+    scope: String,
+    aud: String,
+    exp: i64,
+    iat: i64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CredentialsFileType {
+    #[serde(rename = "type")]
+    credentials_type: Option<String>,
+}
+
+#[derive(Clone)]
+pub enum GoogleCredentials {
+    ServiceAccount(ServiceAccountFile),
+    AuthorizedUser(AuthorizedUserFile),
+    ExternalAccount(ExternalAccountFile),
+    // GCEやCloud Runのメタデータサーバー
+    MetadataServer(Url),
+}
+
+impl GoogleCredentials {
+    // Application Default Credentials と同じ順番で認証情報を探す
+    //   1. GOOGLE_APPLICATION_CREDENTIALS で指定されたファイル
+    //   2. gcloud が作成したファイル
+    //   3. メタデータサーバー
+    pub fn detect(
+        credentials_file: Option<String>,
+        metadata_server_url: Option<String>,
+    ) -> Result<GoogleCredentials, Box<dyn Error + Send + Sync>> {
+        if let Some(credentials_file) = credentials_file {
+            return GoogleCredentials::from_file(&credentials_file);
+        }
+
+        if let Ok(home) = std::env::var("HOME") {
+            let well_known_file = format!(
+                "{}/.config/gcloud/application_default_credentials.json",
+                home
+            );
+            if std::path::Path::new(&well_known_file).exists() {
+                return GoogleCredentials::from_file(&well_known_file);
+            }
+        }
+
+        let metadata_server_url =
+            metadata_server_url.unwrap_or(DEFAULT_METADATA_SERVER_URL.to_string());
+        Ok(GoogleCredentials::MetadataServer(Url::parse(
+            &metadata_server_url,
+        )?))
+    }
+
+    pub fn from_file(path: &str) -> Result<GoogleCredentials, Box<dyn Error + Send + Sync>> {
+        let credentials_json = std::fs::read_to_string(path)?;
+        let file_type = serde_json::from_str::<CredentialsFileType>(&credentials_json)?;
+
+        match file_type.credentials_type.as_deref() {
+            Some("service_account") | None => Ok(GoogleCredentials::ServiceAccount(
+                serde_json::from_str(&credentials_json)?,
+            )),
+            Some("authorized_user") => Ok(GoogleCredentials::AuthorizedUser(serde_json::from_str(
+                &credentials_json,
+            )?)),
+            Some("external_account") => Ok(GoogleCredentials::ExternalAccount(
+                serde_json::from_str(&credentials_json)?,
+            )),
+            Some(credentials_type) => {
+                Err(format!("unsupported credentials type: {}", credentials_type).into())
+            }
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            GoogleCredentials::ServiceAccount(_) => "service_account",
+            GoogleCredentials::AuthorizedUser(_) => "authorized_user",
+            GoogleCredentials::ExternalAccount(_) => "external_account",
+            GoogleCredentials::MetadataServer(_) => "metadata_server",
+        }
+    }
+
+    // 認証情報から分かるプロジェクトID (分からない場合は None)
+    //   メタデータサーバーの場合は実行中のプロジェクトを問い合わせる
+    pub async fn project_id(
+        &self,
+        client: &reqwest::Client,
+    ) -> Result<Option<String>, Box<dyn Error + Send + Sync>> {
+        match self {
+            GoogleCredentials::ServiceAccount(service_account) => {
+                Ok(Some(service_account.project_id.clone()))
+            }
+            GoogleCredentials::AuthorizedUser(authorized_user) => {
+                Ok(authorized_user.quota_project_id.clone())
+            }
+            GoogleCredentials::ExternalAccount(external_account) => {
+                Ok(external_account.quota_project_id.clone())
+            }
+            GoogleCredentials::MetadataServer(base_url) => {
+                fetch_metadata_server_project_id(client, base_url)
+                    .await
+                    .map(Some)
+            }
+        }
+    }
+
+    // アクセストークンとその有効期限 (UNIX時間) を取得する
+    pub async fn fetch_access_token(
+        &self,
+        client: &reqwest::Client,
+    ) -> Result<(String, i64), Box<dyn Error + Send + Sync>> {
+        match self {
+            GoogleCredentials::ServiceAccount(service_account) => {
+                fetch_service_account_token(client, service_account).await
+            }
+            GoogleCredentials::AuthorizedUser(authorized_user) => {
+                fetch_authorized_user_token(client, authorized_user).await
+            }
+            GoogleCredentials::ExternalAccount(external_account) => {
+                fetch_external_account_token(client, external_account).await
+            }
+            GoogleCredentials::MetadataServer(base_url) => {
+                fetch_metadata_server_token(client, base_url).await
+            }
+        }
+    }
+}
+
+fn now_sec() -> Result<i64, Box<dyn Error + Send + Sync>> {
+    Ok(std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)?
+        .as_secs() as i64)
+}
+
+// { "access_token": "...", "expires_in": 3599 } 形式のレスポンスを読む
+async fn parse_token_response(
+    response: reqwest::Response,
+) -> Result<(String, i64), Box<dyn Error + Send + Sync>> {
+    let now = now_sec()?;
+    let status = response.status();
+    let response_body = response.text().await?;
+    if !status.is_success() {
+        return Err(format!("failed to get access token ({}): {}", status, response_body).into());
+    }
+    let parsed_response: serde_json::Value = serde_json::from_str(&response_body)?;
+    let token = match parsed_response["access_token"].as_str() {
+        Some(token) => token.to_string(),
+        None => return Err(format!("failed to get access token: {}", response_body).into()),
+    };
+    let expires_in = parsed_response["expires_in"].as_i64().unwrap_or(3600);
+
+    Ok((token, now + expires_in))
+}
+
+async fn fetch_service_account_token(
+    client: &reqwest::Client,
+    service_account_info: &ServiceAccountFile,
+) -> Result<(String, i64), Box<dyn Error + Send + Sync>> {
+    // 鍵のファイルに書かれている token_uri に送る (エミュレーターなどでは変わる)
+    let token_uri = match service_account_info.token_uri.is_empty() {
+        true => DEFAULT_TOKEN_URI,
+        false => service_account_info.token_uri.as_str(),
+    };
+    let iat = now_sec()?;
+    let exp = iat + 3600;
+    let jwt_payload = GoogleJwt {
+        iss: service_account_info.client_email.clone(),
+        scope: TRANSLATION_SCOPE.to_string(),
+        aud: token_uri.to_string(),
+        exp,
+        iat,
+    };
+    let jwt = jsonwebtoken::encode(
+        &jsonwebtoken::Header::new(jsonwebtoken::Algorithm::RS256),
+        &jwt_payload,
+        &jsonwebtoken::EncodingKey::from_rsa_pem(service_account_info.private_key.as_bytes())?,
+    )?;
+
+    let response = client
+        .post(token_uri)
+        .form(&[
+            ("grant_type", "urn:ietf:params:oauth:grant-type:jwt-bearer"),
+            ("assertion", &jwt),
+        ])
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .send()
+        .await?;
+
+    parse_token_response(response).await
+}
+
+async fn fetch_authorized_user_token(
+    client: &reqwest::Client,
+    authorized_user: &AuthorizedUserFile,
+) -> Result<(String, i64), Box<dyn Error + Send + Sync>> {
+    let token_uri = authorized_user
+        .token_uri
+        .clone()
+        .unwrap_or(DEFAULT_TOKEN_URI.to_string());
+    let response = client
+        .post(token_uri)
+        .form(&[
+            ("grant_type", "refresh_token"),
+            ("client_id", &authorized_user.client_id),
+            ("client_secret", &authorized_user.client_secret),
+            ("refresh_token", &authorized_user.refresh_token),
+        ])
+        .send()
+        .await?;
+
+    parse_token_response(response).await
+}
+
+// 外部のIDプロバイダーが発行したトークンをファイルかURLから読み込む
+async fn read_subject_token(
+    client: &reqwest::Client,
+    credential_source: &CredentialSource,
+) -> Result<String, Box<dyn Error + Send + Sync>> {
+    let raw_token = if let Some(file) = credential_source.file.clone() {
+        std::fs::read_to_string(file)?
+    } else if let Some(url) = credential_source.url.clone() {
+        let mut request = client.get(url);
+        for (name, value) in credential_source.headers.clone().unwrap_or_default() {
+            request = request.header(name, value);
+        }
+        // エラーのレスポンスをトークンとして使わないように、2xx以外はエラーにする
+        request.send().await?.error_for_status()?.text().await?
+    } else {
+        return Err("unsupported credential_source (only file and url are supported)".into());
+    };
+
+    match credential_source.format.clone() {
+        Some(format) if format.format_type == "json" => {
+            let field_name = format
+                .subject_token_field_name
+                .unwrap_or("access_token".to_string());
+            let parsed: serde_json::Value = serde_json::from_str(&raw_token)?;
+            match parsed[field_name.as_str()].as_str() {
+                Some(token) => Ok(token.to_string()),
+                None => Err(format!("subject token field {} was not found", field_name).into()),
+            }
+        }
+        _ => Ok(raw_token.trim().to_string()),
+    }
+}
+
+async fn fetch_external_account_token(
+    client: &reqwest::Client,
+    external_account: &ExternalAccountFile,
+) -> Result<(String, i64), Box<dyn Error + Send + Sync>> {
+    let subject_token = read_subject_token(client, &external_account.credential_source).await?;
+
+    // STSでGoogleのアクセストークンに交換する
+    let response = client
+        .post(&external_account.token_url)
+        .form(&[
+            (
+                "grant_type",
+                "urn:ietf:params:oauth:grant-type:token-exchange",
+            ),
+            ("audience", &external_account.audience),
+            ("scope", CLOUD_PLATFORM_SCOPE),
+            (
+                "requested_token_type",
+                "urn:ietf:params:oauth:token-type:access_token",
+            ),
+            ("subject_token", &subject_token),
+            ("subject_token_type", &external_account.subject_token_type),
+        ])
+        .send()
+        .await?;
+    let (sts_token, sts_expires_at) = parse_token_response(response).await?;
+
+    let impersonation_url = match external_account.service_account_impersonation_url.clone() {
+        Some(impersonation_url) => impersonation_url,
+        None => return Ok((sts_token, sts_expires_at)),
+    };
+
+    // サービスアカウントの権限を借用する場合はさらにトークンを発行してもらう
+    const LIFETIME_SEC: i64 = 3600;
+    let now = now_sec()?;
+    let request_json = json!({
+        "scope": [CLOUD_PLATFORM_SCOPE],
+        "lifetime": format!("{}s", LIFETIME_SEC)
+    });
+    let response = client
+        .post(impersonation_url)
+        .header("Content-Type", "application/json")
+        .header("Authorization", format!("Bearer {}", sts_token))
+        .body(serde_json::to_string(&request_json)?)
+        .send()
+        .await?;
+    let status = response.status();
+    let response_body = response.text().await?;
+    if !status.is_success() {
+        return Err(format!(
+            "failed to impersonate service account ({}): {}",
+            status, response_body
+        )
+        .into());
+    }
+    let parsed_response: serde_json::Value = serde_json::from_str(&response_body)?;
+    match parsed_response["accessToken"].as_str() {
+        Some(token) => Ok((token.to_string(), now + LIFETIME_SEC)),
+        None => Err(format!("failed to impersonate service account: {}", response_body).into()),
+    }
+}
+
+async fn fetch_metadata_server_token(
+    client: &reqwest::Client,
+    base_url: &Url,
+) -> Result<(String, i64), Box<dyn Error + Send + Sync>> {
+    let mut endpoint =
+        base_url.join("computeMetadata/v1/instance/service-accounts/default/token")?;
+    endpoint
+        .query_pairs_mut()
+        .append_pair("scopes", CLOUD_PLATFORM_SCOPE);
+
+    let response = client
+        .get(endpoint)
+        .header("Metadata-Flavor", "Google")
+        .send()
+        .await?;
+
+    parse_token_response(response).await
+}
+
+async fn fetch_metadata_server_project_id(
+    client: &reqwest::Client,
+    base_url: &Url,
+) -> Result<String, Box<dyn Error + Send + Sync>> {
+    let endpoint = base_url.join("computeMetadata/v1/project/project-id")?;
+    let response = client
+        .get(endpoint)
+        .header("Metadata-Flavor", "Google")
+        .send()
+        .await?;
+    let status = response.status();
+    let response_body = response.text().await?;
+    if !status.is_success() {
+        return Err(format!(
+            "failed to get project id from metadata server ({}): {}",
+            status, response_body
+        )
+        .into());
+    }
+
+    Ok(response_body.trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::translate::test_server::TestServer;
+
+    const TOKEN_PATH: &str = "/computeMetadata/v1/instance/service-accounts/default/token";
+    const PROJECT_PATH: &str = "/computeMetadata/v1/project/project-id";
+
+    fn external_account(server: &TestServer, impersonate: bool) -> GoogleCredentials {
+        let mut file = json!({
+            "type": "external_account",
+            "audience": "//iam.googleapis.com/projects/1/locations/global/workloadIdentityPools/pool/providers/provider",
+            "subject_token_type": "urn:ietf:params:oauth:token-type:jwt",
+            "token_url": format!("{}sts", server.url),
+            "credential_source": {
+                "url": format!("{}subject", server.url),
+                "headers": {"X-Test": "1"},
+                "format": {"type": "json", "subject_token_field_name": "id_token"}
+            },
+            "quota_project_id": "quota-project"
+        });
+        if impersonate {
+            file["service_account_impersonation_url"] = json!(format!("{}impersonate", server.url));
+        }
+        GoogleCredentials::ExternalAccount(serde_json::from_value(file).unwrap())
+    }
+
+    #[tokio::test]
+    async fn metadata_server_token_and_project() {
+        let server = TestServer::start(vec![
+            (
+                TOKEN_PATH,
+                vec![(200, r#"{"access_token":"metadata-token","expires_in":100}"#)],
+            ),
+            (PROJECT_PATH, vec![(200, "my-project\n")]),
+        ])
+        .await;
+        // detect は gcloud のファイルがある環境ではそちらを使うので、メタデータサーバーを直接指定する
+        let credentials = GoogleCredentials::MetadataServer(Url::parse(&server.url).unwrap());
+        let client = reqwest::Client::new();
+
+        let (token, expires_at) = credentials.fetch_access_token(&client).await.unwrap();
+        assert_eq!(token, "metadata-token");
+        assert!((expires_at - now_sec().unwrap() - 100).abs() <= 1);
+        let request = &server.requests(TOKEN_PATH)[0];
+        assert_eq!(request.header("Metadata-Flavor"), Some("Google"));
+        assert!(request.target.contains("scopes="));
+
+        assert_eq!(
+            credentials.project_id(&client).await.unwrap(),
+            Some("my-project".to_string())
+        );
+        assert_eq!(
+            server.requests(PROJECT_PATH)[0].header("Metadata-Flavor"),
+            Some("Google")
+        );
+    }
+
+    #[tokio::test]
+    async fn metadata_server_errors_are_reported() {
+        let server = TestServer::start(vec![
+            (TOKEN_PATH, vec![(404, "not defined")]),
+            (PROJECT_PATH, vec![(500, "error")]),
+        ])
+        .await;
+        let credentials = GoogleCredentials::MetadataServer(Url::parse(&server.url).unwrap());
+        let client = reqwest::Client::new();
+
+        assert!(credentials.fetch_access_token(&client).await.is_err());
+        assert!(credentials.project_id(&client).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn external_account_exchanges_the_subject_token() {
+        let server = TestServer::start(vec![
+            ("/subject", vec![(200, r#"{"id_token":"subject-token"}"#)]),
+            (
+                "/sts",
+                vec![(200, r#"{"access_token":"sts-token","expires_in":3599}"#)],
+            ),
+            ("/impersonate", vec![(200, r#"{"accessToken":"sa-token"}"#)]),
+        ])
+        .await;
+        let client = reqwest::Client::new();
+
+        let credentials = external_account(&server, false);
+        let (token, _) = credentials.fetch_access_token(&client).await.unwrap();
+        assert_eq!(token, "sts-token");
+        assert_eq!(server.requests("/subject")[0].header("X-Test"), Some("1"));
+        let sts_body = &server.requests("/sts")[0].body;
+        assert!(
+            sts_body.contains("subject_token=subject-token"),
+            "{}",
+            sts_body
+        );
+        assert_eq!(
+            credentials.project_id(&client).await.unwrap(),
+            Some("quota-project".to_string())
+        );
+
+        // サービスアカウントを借用する場合は STS のトークンで発行してもらう
+        let (token, _) = external_account(&server, true)
+            .fetch_access_token(&client)
+            .await
+            .unwrap();
+        assert_eq!(token, "sa-token");
+        assert_eq!(
+            server.requests("/impersonate")[0].header("Authorization"),
+            Some("Bearer sts-token")
+        );
+    }
+
+    #[tokio::test]
+    async fn error_responses_are_not_used_as_subject_tokens() {
+        let server = TestServer::start(vec![
+            ("/subject", vec![(403, r#"{"id_token":"forbidden"}"#)]),
+            (
+                "/sts",
+                vec![(200, r#"{"access_token":"sts-token","expires_in":3599}"#)],
+            ),
+        ])
+        .await;
+
+        let result = external_account(&server, false)
+            .fetch_access_token(&reqwest::Client::new())
+            .await;
+        assert!(result.is_err());
+        assert!(server.requests("/sts").is_empty());
+    }
+
+    #[tokio::test]
+    async fn subject_token_is_read_from_a_file() {
+        let server = TestServer::start(vec![(
+            "/sts",
+            vec![(200, r#"{"access_token":"sts-token","expires_in":3599}"#)],
+        )])
+        .await;
+        let directory = tempfile::tempdir().unwrap();
+        let token_file = directory.path().join("token");
+        std::fs::write(&token_file, "file-token\n").unwrap();
+        let credentials_file = directory.path().join("credentials.json");
+        let file = json!({
+            "type": "external_account",
+            "audience": "audience",
+            "subject_token_type": "urn:ietf:params:oauth:token-type:jwt",
+            "token_url": format!("{}sts", server.url),
+            "credential_source": {"file": token_file}
+        });
+        std::fs::write(&credentials_file, file.to_string()).unwrap();
+
+        let credentials = GoogleCredentials::from_file(credentials_file.to_str().unwrap()).unwrap();
+        assert_eq!(credentials.kind(), "external_account");
+        let (token, _) = credentials
+            .fetch_access_token(&reqwest::Client::new())
+            .await
+            .unwrap();
+        assert_eq!(token, "sts-token");
+        assert!(server.requests("/sts")[0]
+            .body
+            .contains("subject_token=file-token&"));
+    }
+}
@@ -0,0 +1,370 @@
+// HTMLを翻訳するために、タグと表示されるテキストに分割する
+//   タグや属性、<code> や <pre> の中身はそのまま残し、テキストだけを翻訳APIに送る
+
+// 中身を翻訳しない要素
+const SKIP_ELEMENTS: [&str; 6] = ["code", "pre", "script", "style", "kbd", "samp"];
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum HtmlPart {
+    // タグやコメントなど、そのまま出力する部分
+    Markup(String),
+    // 表示されるテキスト (エンティティはエスケープされたまま)
+    Text(String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct HtmlDocument {
+    parts: Vec<HtmlPart>,
+}
+
+impl HtmlDocument {
+    pub fn parse(html: &str) -> HtmlDocument {
+        let mut parts: Vec<HtmlPart> = Vec::new();
+        let mut text = String::new();
+        let mut rest = html;
+
+        while !rest.is_empty() {
+            let markup_len = match rest.starts_with('<') {
+                true => markup_length(rest),
+                false => None,
+            };
+            match markup_len {
+                Some(len) => {
+                    if !text.is_empty() {
+                        parts.push(HtmlPart::Text(std::mem::take(&mut text)));
+                    }
+                    parts.push(HtmlPart::Markup(rest[..len].to_string()));
+                    rest = &rest[len..];
+                }
+                None => {
+                    let ch = rest.chars().next().unwrap();
+                    text.push(ch);
+                    rest = &rest[ch.len_utf8()..];
+                }
+            }
+        }
+        if !text.is_empty() {
+            parts.push(HtmlPart::Text(text));
+        }
+
+        HtmlDocument { parts }
+    }
+
+    // 翻訳APIに送るテキストの一覧
+    pub fn segments(&self) -> Vec<String> {
+        self.parts
+            .iter()
+            .filter_map(|part| match part {
+                HtmlPart::Text(text) => segment_of(text),
+                HtmlPart::Markup(_) => None,
+            })
+            .collect()
+    }
+
+    // テキスト部分を翻訳結果に差し替えてHTMLに戻す
+    //   翻訳結果が見つからないテキストは元のまま残す
+    pub fn render<F>(&self, translate: F) -> String
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        self.parts
+            .iter()
+            .map(|part| match part {
+                HtmlPart::Markup(markup) => markup.clone(),
+                HtmlPart::Text(text) => {
+                    let translated = segment_of(text).and_then(|segment| translate(&segment));
+                    match translated {
+                        Some(translated) => {
+                            // 前後の空白は元のテキストのものを使う
+                            let leading = &text[..text.len() - text.trim_start().len()];
+                            let trailing = &text[text.trim_end().len()..];
+                            format!("{}{}{}", leading, escape_text(translated.trim()), trailing)
+                        }
+                        None => text.clone(),
+                    }
+                }
+            })
+            .collect()
+    }
+}
+
+// 空白や記号だけのテキストは翻訳しない
+fn segment_of(text: &str) -> Option<String> {
+    let segment = decode_entities(text.trim());
+    if !segment.chars().any(char::is_alphabetic) {
+        return None;
+    }
+    Some(segment)
+}
+
+// `<` から始まる文字列がタグなどであればその長さを返す
+//   翻訳しない要素の場合は閉じタグまでをまとめて返す
+fn markup_length(html: &str) -> Option<usize> {
+    if html.starts_with("<!--") {
+        return Some(find_end(html, "-->").unwrap_or(html.len()));
+    }
+    if html.starts_with("<![CDATA[") {
+        return Some(find_end(html, "]]>").unwrap_or(html.len()));
+    }
+
+    let next = html[1..].chars().next()?;
+    if !(next.is_ascii_alphabetic() || next == '/' || next == '!' || next == '?') {
+        return None;
+    }
+    let tag_len = tag_length(html)?;
+
+    let tag = &html[..tag_len];
+    let name = tag_name(tag);
+    let is_self_closing = tag.ends_with("/>");
+    if next == '/' || is_self_closing || !SKIP_ELEMENTS.contains(&name.as_str()) {
+        return Some(tag_len);
+    }
+
+    // 同じ要素の入れ子も考慮して閉じタグを探す
+    let mut depth = 1;
+    let mut position = tag_len;
+    while position < html.len() {
+        let next_tag = match html[position..].find('<') {
+            Some(offset) => position + offset,
+            None => break,
+        };
+        let len = match tag_length(&html[next_tag..]) {
+            Some(len) => len,
+            None => {
+                position = next_tag + 1;
+                continue;
+            }
+        };
+        let inner_tag = &html[next_tag..next_tag + len];
+        if tag_name(inner_tag) == name {
+            if inner_tag.starts_with("</") {
+                depth -= 1;
+            } else if !inner_tag.ends_with("/>") {
+                depth += 1;
+            }
+        }
+        position = next_tag + len;
+        if depth == 0 {
+            return Some(position);
+        }
+    }
+
+    Some(html.len())
+}
+
+// 属性値の中の `>` を考慮してタグの終わりを探す
+fn tag_length(html: &str) -> Option<usize> {
+    let mut quote: Option<char> = None;
+    for (index, ch) in html.char_indices().skip(1) {
+        match quote {
+            Some(q) if ch == q => quote = None,
+            Some(_) => {}
+            None if ch == '"' || ch == '\'' => quote = Some(ch),
+            None if ch == '>' => return Some(index + 1),
+            None => {}
+        }
+    }
+    None
+}
+
+fn find_end(html: &str, end: &str) -> Option<usize> {
+    html.find(end).map(|index| index + end.len())
+}
+
+fn tag_name(tag: &str) -> String {
+    tag.trim_start_matches('<')
+        .trim_start_matches('/')
+        .chars()
+        .take_while(|ch| ch.is_ascii_alphanumeric())
+        .collect::<String>()
+        .to_ascii_lowercase()
+}
+
+// HTMLのエンティティをデコードする
+//   知らない名前のエンティティはそのまま残す
+pub fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(index) = rest.find('&') {
+        decoded.push_str(&rest[..index]);
+        rest = &rest[index..];
+
+        let end = match rest.find(';') {
+            Some(end) if end <= 10 => end,
+            _ => {
+                decoded.push('&');
+                rest = &rest[1..];
+                continue;
+            }
+        };
+        let entity = &rest[1..end];
+        let ch = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some('\u{a0}'),
+            _ if entity.starts_with("#x") || entity.starts_with("#X") => {
+                u32::from_str_radix(&entity[2..], 16)
+                    .ok()
+                    .and_then(char::from_u32)
+            }
+            _ if entity.starts_with('#') => {
+                entity[1..].parse::<u32>().ok().and_then(char::from_u32)
+            }
+            _ => None,
+        };
+        match ch {
+            Some(ch) => {
+                decoded.push(ch);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+
+    decoded
+}
+
+// タイトルなどのHTMLをプレーンテキストにする (タグを除いてエンティティをデコードする)
+pub fn to_plain_text(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut rest = html;
+
+    while let Some(index) = rest.find('<') {
+        text.push_str(&decode_entities(&rest[..index]));
+        rest = &rest[index..];
+
+        if let Some(cdata) = rest.strip_prefix("<![CDATA[") {
+            let end = cdata.find("]]>").unwrap_or(cdata.len());
+            text.push_str(&cdata[..end]);
+            rest = cdata.get(end + 3..).unwrap_or("");
+            continue;
+        }
+        if rest.starts_with("<!--") {
+            rest = &rest[find_end(rest, "-->").unwrap_or(rest.len())..];
+            continue;
+        }
+        let is_tag = rest[1..]
+            .chars()
+            .next()
+            .map(|next| next.is_ascii_alphabetic() || next == '/' || next == '!' || next == '?')
+            .unwrap_or(false);
+        match tag_length(rest) {
+            Some(len) if is_tag => rest = &rest[len..],
+            _ => {
+                text.push('<');
+                rest = &rest[1..];
+            }
+        }
+    }
+    text.push_str(&decode_entities(rest));
+
+    text
+}
+
+// HTMLのテキストとして埋め込めるようにエスケープする
+pub fn escape_text(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // テキストを大文字にする翻訳の代わり
+    fn render_upper(html: &str) -> String {
+        HtmlDocument::parse(html).render(|segment| Some(segment.to_uppercase()))
+    }
+
+    #[test]
+    fn nested_code_and_pre_are_kept_verbatim() {
+        let html = "<pre>outer <pre>inner</pre> <code>let x = a < b;</code> end</pre><p>Hello</p>";
+        let document = HtmlDocument::parse(html);
+
+        assert_eq!(document.segments(), vec!["Hello"]);
+        assert_eq!(
+            render_upper(html),
+            "<pre>outer <pre>inner</pre> <code>let x = a < b;</code> end</pre><p>HELLO</p>"
+        );
+        assert_eq!(
+            render_upper("<p>Run <code>cargo <b>build</b></code> now</p>"),
+            "<p>RUN <code>cargo <b>build</b></code> NOW</p>"
+        );
+    }
+
+    #[test]
+    fn quoted_attributes_may_contain_angle_brackets() {
+        let html = r#"<a title="a > b" href='https://example.com/?a=1&b=2'>Read more</a>"#;
+
+        assert_eq!(HtmlDocument::parse(html).segments(), vec!["Read more"]);
+        assert_eq!(
+            render_upper(html),
+            r#"<a title="a > b" href='https://example.com/?a=1&b=2'>READ MORE</a>"#
+        );
+    }
+
+    #[test]
+    fn comments_and_cdata_are_not_translated() {
+        let html = "<!-- note <b>x</b> --><![CDATA[raw <text>]]><p>Hi</p>";
+
+        assert_eq!(HtmlDocument::parse(html).segments(), vec!["Hi"]);
+        assert_eq!(
+            render_upper(html),
+            "<!-- note <b>x</b> --><![CDATA[raw <text>]]><p>HI</p>"
+        );
+        // プレーンテキストにする場合、コメントは除いて CDATA の中身は残す
+        assert_eq!(to_plain_text(html), "raw <text>Hi");
+    }
+
+    #[test]
+    fn unknown_entities_are_kept() {
+        assert_eq!(
+            decode_entities("&foo; &amp; &#x41;&#66; &lt;b&gt; & alone &verylongentityname;"),
+            "&foo; & AB <b> & alone &verylongentityname;"
+        );
+        assert_eq!(decode_entities("&#xZZ; &#99999999;"), "&#xZZ; &#99999999;");
+    }
+
+    #[test]
+    fn render_keeps_surrounding_whitespace_and_escapes() {
+        let html = "<p>\n  Fish &amp; chips  </p>\n<p> </p>";
+        let document = HtmlDocument::parse(html);
+
+        assert_eq!(document.segments(), vec!["Fish & chips"]);
+        assert_eq!(
+            document.render(|_| Some(" <魚> & チップス ".to_string())),
+            "<p>\n  &lt;魚&gt; &amp; チップス  </p>\n<p> </p>"
+        );
+        // 翻訳が見つからないテキストは元のまま残す
+        assert_eq!(document.render(|_| None), html);
+    }
+
+    #[test]
+    fn plain_text_keeps_malformed_angle_brackets() {
+        assert_eq!(
+            to_plain_text("a < b <3 and <b>bold</b>"),
+            "a < b <3 and bold"
+        );
+        assert_eq!(to_plain_text("x <"), "x <");
+        assert_eq!(to_plain_text("<b unclosed"), "<b unclosed");
+        assert_eq!(to_plain_text("Q&amp;A <i>today</i>"), "Q&A today");
+    }
+
+    #[test]
+    fn inline_elements_split_segments() {
+        // インライン要素の前後は別のテキストとして翻訳する
+        assert_eq!(
+            HtmlDocument::parse("Click <a href=\"/\">here</a> to read").segments(),
+            vec!["Click", "here", "to read"]
+        );
+    }
+}
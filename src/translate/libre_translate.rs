@@ -0,0 +1,155 @@
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use url::Url;
+
+use super::backend::{TranslateFuture, TranslateOptions, TranslatedItem, TranslationBackend};
+use super::batch::{self, BatchLimits};
+use super::error::{self, TranslateError};
+use super::retry::RequestPolicy;
+
+#[derive(Serialize, Deserialize)]
+#[allow(non_snake_case)]
+struct Response {
+    translatedText: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SupportedLanguage {
+    code: String,
+}
+
+pub struct LibreTranslateBackendOptions {
+    pub endpoint_url: String,
+    pub api_key: Option<String>,
+    pub batch_limits: BatchLimits,
+    pub request_policy: RequestPolicy,
+}
+
+#[derive(Clone)]
+pub struct LibreTranslateBackend {
+    client: reqwest::Client,
+    base_url: Url,
+    api_key: Option<String>,
+    batch_limits: BatchLimits,
+    request_policy: RequestPolicy,
+}
+
+impl TranslationBackend for LibreTranslateBackend {
+    fn translate(
+        &self,
+        target_strs: Vec<String>,
+        options: TranslateOptions,
+    ) -> TranslateFuture<Vec<TranslatedItem>> {
+        let backend = self.clone();
+        Box::pin(async move { backend.translate_batches(target_strs, options).await })
+    }
+
+    fn supported_languages(&self) -> TranslateFuture<Vec<String>> {
+        let backend = self.clone();
+        Box::pin(async move { backend.fetch_supported_languages().await })
+    }
+
+    fn name(&self) -> String {
+        "libretranslate".to_string()
+    }
+
+    fn version(&self) -> String {
+        "v1".to_string()
+    }
+
+    fn clone_box(&self) -> Box<dyn TranslationBackend> {
+        Box::new(self.clone())
+    }
+}
+
+impl LibreTranslateBackend {
+    // LibreTranslateの文字数制限はサーバーの設定 (--char-limit) による
+    pub const DEFAULT_BATCH_LIMITS: BatchLimits = BatchLimits {
+        max_segments: 100,
+        max_chars: 5000,
+        concurrency: 4,
+    };
+
+    pub fn new(options: LibreTranslateBackendOptions) -> Self {
+        let base_url = Url::parse(&options.endpoint_url).unwrap();
+
+        LibreTranslateBackend {
+            client: reqwest::Client::new(),
+            base_url,
+            api_key: options.api_key,
+            batch_limits: options.batch_limits,
+            request_policy: options.request_policy,
+        }
+    }
+
+    // LibreTranslateは "ja-JP" のような地域付きのコードを受け付けないので言語部分だけにする
+    fn to_language_code(to: &str) -> String {
+        to.split(['-', '_']).next().unwrap_or(to).to_lowercase()
+    }
+
+    async fn translate_batches(
+        &self,
+        target_strs: Vec<String>,
+        options: TranslateOptions,
+    ) -> Result<Vec<TranslatedItem>, TranslateError> {
+        let format = match options.mime_type.as_deref() {
+            Some("text/html") => "html",
+            _ => "text",
+        };
+        let to = options.to;
+
+        let backend = self.clone();
+        let results = batch::dispatch(target_strs, self.batch_limits, move |batch| {
+            let backend = backend.clone();
+            let to = to.clone();
+            async move { backend.translate_batch(batch, to, format).await }
+        })
+        .await;
+        Ok(results)
+    }
+
+    async fn translate_batch(
+        &self,
+        batch: Vec<String>,
+        to: String,
+        format: &str,
+    ) -> Result<Vec<TranslatedItem>, TranslateError> {
+        let endpoint = self.base_url.join("translate")?;
+        let target = LibreTranslateBackend::to_language_code(&to);
+
+        let mut request_json = json!({
+            "q": batch,
+            "source": "auto",
+            "target": target,
+            "format": format
+        });
+        if let Some(api_key) = self.api_key.clone() {
+            request_json["api_key"] = json!(api_key);
+        }
+
+        let chars = batch.iter().map(|text| text.chars().count()).sum();
+        let request = self
+            .client
+            .post(endpoint)
+            .header("Content-Type", "application/json")
+            .body(serde_json::to_string(&request_json)?);
+        let response = self.request_policy.send(request, chars).await?;
+        let response_body = error::read_response(&self.name(), response).await?;
+        let parsed_response: Response = error::parse_response(&self.name(), &response_body)?;
+
+        error::pair_translations(&self.name(), &batch, parsed_response.translatedText)
+    }
+
+    async fn fetch_supported_languages(&self) -> Result<Vec<String>, TranslateError> {
+        let endpoint = self.base_url.join("languages")?;
+        let response = self.client.get(endpoint).send().await?;
+        let response_body = error::read_response(&self.name(), response).await?;
+        let languages: Vec<SupportedLanguage> =
+            error::parse_response(&self.name(), &response_body)?;
+
+        Ok(languages
+            .into_iter()
+            .map(|language| language.code)
+            .collect())
+    }
+}
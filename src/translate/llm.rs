@@ -0,0 +1,316 @@
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use url::Url;
+
+use super::backend::{TranslateFuture, TranslateOptions, TranslatedItem, TranslationBackend};
+use super::batch::{self, BatchLimits};
+use super::error::{self, TranslateError};
+use super::retry::RequestPolicy;
+
+pub const DEFAULT_PROMPT_TEMPLATE: &str = "You are a professional translator. \
+Translate every string in the JSON array given by the user into the language identified by the BCP 47 tag \"{to}\". \
+Keep product names, company names, stock tickers, code identifiers, URLs and version numbers exactly as they are. \
+Respond only with a JSON object of the form {\"translations\": [\"...\"]} that contains exactly {count} strings, \
+one translation per input string, in the same order as the input.";
+
+#[derive(Serialize, Deserialize)]
+struct ChatMessage {
+    role: String,
+    content: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ChatChoice {
+    message: ChatMessage,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ChatResponse {
+    choices: Vec<ChatChoice>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct TranslatedContent {
+    translations: Vec<String>,
+}
+
+pub struct LlmBackendOptions {
+    // 例: http://localhost:11434/v1/
+    pub endpoint_url: String,
+    pub api_key: Option<String>,
+    pub model: String,
+    pub temperature: f32,
+    // {to} と {count} が翻訳先の言語と件数に置き換えられる
+    pub prompt_template: String,
+    pub batch_limits: BatchLimits,
+    pub request_policy: RequestPolicy,
+}
+
+#[derive(Clone)]
+pub struct LlmBackend {
+    client: reqwest::Client,
+    base_url: Url,
+    api_key: Option<String>,
+    model: String,
+    temperature: f32,
+    prompt_template: String,
+    batch_limits: BatchLimits,
+    request_policy: RequestPolicy,
+}
+
+impl TranslationBackend for LlmBackend {
+    fn translate(
+        &self,
+        target_strs: Vec<String>,
+        options: TranslateOptions,
+    ) -> TranslateFuture<Vec<TranslatedItem>> {
+        let backend = self.clone();
+        Box::pin(async move { backend.translate_batches(target_strs, options.to).await })
+    }
+
+    fn supported_languages(&self) -> TranslateFuture<Vec<String>> {
+        // LLMは任意の言語を扱えるので一覧は返さない
+        Box::pin(async move { Ok(Vec::new()) })
+    }
+
+    fn name(&self) -> String {
+        "llm".to_string()
+    }
+
+    // プロンプトや temperature を変えると翻訳結果が変わるので、そのハッシュもキャッシュのキーに含める
+    fn version(&self) -> String {
+        let settings = format!("{}\n{}", self.prompt_template, self.temperature);
+        let hash = format!("{:x}", Sha256::digest(settings.as_bytes()));
+        format!("{}/prompt={}", self.model, &hash[..16])
+    }
+
+    fn clone_box(&self) -> Box<dyn TranslationBackend> {
+        Box::new(self.clone())
+    }
+}
+
+impl LlmBackend {
+    // 1回のプロンプトに入れすぎると件数がずれやすいので控えめにする
+    pub const DEFAULT_BATCH_LIMITS: BatchLimits = BatchLimits {
+        max_segments: 50,
+        max_chars: 8000,
+        concurrency: 2,
+    };
+
+    pub fn new(options: LlmBackendOptions) -> Self {
+        let base_url = Url::parse(&options.endpoint_url).unwrap();
+
+        LlmBackend {
+            client: reqwest::Client::new(),
+            base_url,
+            api_key: options.api_key,
+            model: options.model,
+            temperature: options.temperature,
+            prompt_template: options.prompt_template,
+            batch_limits: options.batch_limits,
+            request_policy: options.request_policy,
+        }
+    }
+
+    fn build_prompt(&self, to: &str, count: usize) -> String {
+        self.prompt_template
+            .replace("{to}", to)
+            .replace("{count}", &count.to_string())
+    }
+
+    // モデルによってはコードブロックで囲んで返してくるので中身だけを取り出す
+    fn parse_content(&self, content: &str) -> Result<Vec<String>, TranslateError> {
+        let content = content.trim();
+        let content = match content.strip_prefix("```") {
+            Some(fenced) => {
+                let fenced = fenced.trim_start_matches("json");
+                fenced.strip_suffix("```").unwrap_or(fenced).trim()
+            }
+            None => content,
+        };
+
+        if let Ok(parsed) = serde_json::from_str::<TranslatedContent>(content) {
+            return Ok(parsed.translations);
+        }
+        error::parse_response(&self.name(), content)
+    }
+
+    async fn translate_batches(
+        &self,
+        target_strs: Vec<String>,
+        to: String,
+    ) -> Result<Vec<TranslatedItem>, TranslateError> {
+        let backend = self.clone();
+        let results = batch::dispatch(target_strs, self.batch_limits, move |batch| {
+            let backend = backend.clone();
+            let to = to.clone();
+            async move { backend.translate_batch(batch, to).await }
+        })
+        .await;
+        Ok(results)
+    }
+
+    async fn translate_batch(
+        &self,
+        batch: Vec<String>,
+        to: String,
+    ) -> Result<Vec<TranslatedItem>, TranslateError> {
+        let endpoint = self.base_url.join("chat/completions")?;
+
+        let request_json = json!({
+            "model": self.model,
+            "temperature": self.temperature,
+            "response_format": { "type": "json_object" },
+            "messages": [
+                { "role": "system", "content": self.build_prompt(&to, batch.len()) },
+                { "role": "user", "content": serde_json::to_string(&batch)? }
+            ]
+        });
+
+        let mut request = self
+            .client
+            .post(endpoint)
+            .header("Content-Type", "application/json")
+            .body(serde_json::to_string(&request_json)?);
+        if let Some(api_key) = self.api_key.clone() {
+            request = request.header("Authorization", format!("Bearer {}", api_key));
+        }
+
+        let chars = batch.iter().map(|text| text.chars().count()).sum();
+        let response = self.request_policy.send(request, chars).await?;
+        let response_body = error::read_response(&self.name(), response).await?;
+        let parsed_response: ChatResponse = error::parse_response(&self.name(), &response_body)?;
+        let content = match parsed_response.choices.first() {
+            Some(choice) => choice.message.content.clone(),
+            None => {
+                return Err(TranslateError::InvalidResponse {
+                    backend: self.name(),
+                    message: "no choices".to_string(),
+                })
+            }
+        };
+        let translated = self.parse_content(&content)?;
+
+        // 件数が合わない場合はどのタイトルに対応するか分からないのでエラーにする
+        error::pair_translations(&self.name(), &batch, translated)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    fn backend(endpoint_url: &str, prompt_template: &str, temperature: f32) -> LlmBackend {
+        LlmBackend::new(LlmBackendOptions {
+            endpoint_url: endpoint_url.to_string(),
+            api_key: None,
+            model: "test-model".to_string(),
+            temperature,
+            prompt_template: prompt_template.to_string(),
+            batch_limits: LlmBackend::DEFAULT_BATCH_LIMITS,
+            request_policy: RequestPolicy::default(),
+        })
+    }
+
+    // 1回だけ body を返すサーバーを立てて、そのURLを返す
+    async fn serve(body: &'static str) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/v1/", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buffer = [0u8; 8192];
+            let _ = stream.read(&mut buffer).await;
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            stream.write_all(response.as_bytes()).await.unwrap();
+            stream.shutdown().await.unwrap();
+        });
+        url
+    }
+
+    fn texts(texts: &[&str]) -> Vec<String> {
+        texts.iter().map(|text| text.to_string()).collect()
+    }
+
+    #[test]
+    fn content_is_parsed_with_or_without_a_code_block() {
+        let backend = backend("http://localhost/v1/", DEFAULT_PROMPT_TEMPLATE, 0.0);
+        let expected = texts(&["こんにちは", "世界"]);
+
+        assert_eq!(
+            backend
+                .parse_content(r#"{"translations": ["こんにちは", "世界"]}"#)
+                .unwrap(),
+            expected
+        );
+        assert_eq!(
+            backend
+                .parse_content("```json\n{\"translations\": [\"こんにちは\", \"世界\"]}\n```")
+                .unwrap(),
+            expected
+        );
+        assert_eq!(
+            backend
+                .parse_content("  ```\n{\"translations\": [\"こんにちは\", \"世界\"]}```  ")
+                .unwrap(),
+            expected
+        );
+        assert!(backend.parse_content("こんにちは\n世界").is_err());
+    }
+
+    #[test]
+    fn version_changes_with_the_prompt_and_temperature() {
+        let base = backend("http://localhost/v1/", DEFAULT_PROMPT_TEMPLATE, 0.0);
+        assert!(base.version().starts_with("test-model/prompt="));
+        assert_eq!(
+            base.version(),
+            backend("http://localhost/v1/", DEFAULT_PROMPT_TEMPLATE, 0.0).version()
+        );
+        assert_ne!(
+            base.version(),
+            backend("http://localhost/v1/", "Translate into {to}.", 0.0).version()
+        );
+        assert_ne!(
+            base.version(),
+            backend("http://localhost/v1/", DEFAULT_PROMPT_TEMPLATE, 0.7).version()
+        );
+    }
+
+    #[tokio::test]
+    async fn translations_are_paired_with_the_input() {
+        let url = serve(
+            r#"{"choices":[{"message":{"role":"assistant","content":"{\"translations\": [\"こんにちは\", \"世界\"]}"}}]}"#,
+        )
+        .await;
+        let translated = backend(&url, DEFAULT_PROMPT_TEMPLATE, 0.0)
+            .translate_batch(texts(&["Hello", "World"]), "ja".to_string())
+            .await
+            .unwrap();
+
+        assert_eq!(translated[0].as_ref().unwrap().translated, "こんにちは");
+        assert_eq!(translated[1].as_ref().unwrap().raw_text, "World");
+    }
+
+    #[tokio::test]
+    async fn a_different_number_of_translations_fails_the_batch() {
+        let url = serve(
+            r#"{"choices":[{"message":{"role":"assistant","content":"{\"translations\": [\"こんにちは 世界\"]}"}}]}"#,
+        )
+        .await;
+        let result = backend(&url, DEFAULT_PROMPT_TEMPLATE, 0.0)
+            .translate_batch(texts(&["Hello", "World"]), "ja".to_string())
+            .await;
+
+        match result {
+            Err(TranslateError::CountMismatch {
+                expected, actual, ..
+            }) => assert_eq!((expected, actual), (2, 1)),
+            _ => panic!("expected CountMismatch"),
+        }
+    }
+}
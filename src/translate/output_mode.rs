@@ -0,0 +1,161 @@
+use std::str::FromStr;
+
+pub const DEFAULT_BILINGUAL_TEMPLATE: &str = "{translated} ({original})";
+pub const DEFAULT_ORIGINAL_FIRST_TEMPLATE: &str = "{original} ({translated})";
+
+// 翻訳後のタイトルの見せ方
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OutputMode {
+    // 翻訳後のタイトルだけ
+    Translated,
+    // 翻訳後のタイトルの後ろに元のタイトル
+    Bilingual,
+    // 元のタイトルの後ろに翻訳後のタイトル
+    OriginalFirst,
+    // 翻訳せずにラテン文字に書き換えたタイトル (日本語の漢字は書き換えられずに残る)
+    Romanized,
+}
+
+impl FromStr for OutputMode {
+    type Err = String;
+
+    fn from_str(mode: &str) -> Result<Self, Self::Err> {
+        match mode {
+            "translated" => Ok(OutputMode::Translated),
+            "bilingual" => Ok(OutputMode::Bilingual),
+            "original-first" => Ok(OutputMode::OriginalFirst),
+            "romanized" => Ok(OutputMode::Romanized),
+            _ => Err(format!("unknown mode: {}", mode)),
+        }
+    }
+}
+
+// {translated} と {original} を置き換えてタイトルを作る
+#[derive(Clone)]
+pub struct TitleTemplates {
+    pub bilingual: String,
+    pub original_first: String,
+}
+
+impl Default for TitleTemplates {
+    fn default() -> Self {
+        TitleTemplates {
+            bilingual: DEFAULT_BILINGUAL_TEMPLATE.to_string(),
+            original_first: DEFAULT_ORIGINAL_FIRST_TEMPLATE.to_string(),
+        }
+    }
+}
+
+impl TitleTemplates {
+    // template が指定された場合はモードのテンプレートより優先する
+    pub fn format(
+        &self,
+        mode: OutputMode,
+        template: Option<&str>,
+        original: &str,
+        translated: &str,
+    ) -> String {
+        let template = match mode {
+            OutputMode::Translated => return translated.to_string(),
+            // ラテン文字にしたタイトルは、テンプレートが指定された場合だけ元のタイトルと並べる
+            OutputMode::Romanized => match template {
+                Some(template) if original.trim() != translated.trim() => template,
+                _ => return translated.to_string(),
+            },
+            // 翻訳しても変わらなかった場合は同じものを2回並べない
+            _ if original.trim() == translated.trim() => return translated.to_string(),
+            OutputMode::Bilingual => template.unwrap_or(&self.bilingual),
+            OutputMode::OriginalFirst => template.unwrap_or(&self.original_first),
+        };
+
+        template
+            .replace("{translated}", translated.trim())
+            .replace("{original}", original.trim())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn modes_are_parsed_by_name() {
+        assert_eq!("translated".parse(), Ok(OutputMode::Translated));
+        assert_eq!("bilingual".parse(), Ok(OutputMode::Bilingual));
+        assert_eq!("original-first".parse(), Ok(OutputMode::OriginalFirst));
+        assert_eq!("romanized".parse(), Ok(OutputMode::Romanized));
+
+        assert_eq!(
+            "Bilingual".parse::<OutputMode>(),
+            Err("unknown mode: Bilingual".to_string())
+        );
+        assert!("original_first".parse::<OutputMode>().is_err());
+        assert!("".parse::<OutputMode>().is_err());
+    }
+
+    #[test]
+    fn each_mode_uses_its_default_template() {
+        let templates = TitleTemplates::default();
+        let format = |mode: OutputMode| templates.format(mode, None, " Hello ", "こんにちは");
+
+        assert_eq!(format(OutputMode::Translated), "こんにちは");
+        assert_eq!(format(OutputMode::Bilingual), "こんにちは (Hello)");
+        assert_eq!(format(OutputMode::OriginalFirst), "Hello (こんにちは)");
+        // ラテン文字にしたものはテンプレートがなければそれだけを返す
+        assert_eq!(format(OutputMode::Romanized), "こんにちは");
+    }
+
+    #[test]
+    fn a_custom_template_overrides_the_mode_default() {
+        let templates = TitleTemplates {
+            bilingual: "{translated} / {original}".to_string(),
+            original_first: "{original} / {translated}".to_string(),
+        };
+        let template = Some("[{original}] {translated}");
+
+        assert_eq!(
+            templates.format(OutputMode::Bilingual, None, "Hello", "こんにちは"),
+            "こんにちは / Hello"
+        );
+        assert_eq!(
+            templates.format(OutputMode::Bilingual, template, "Hello", "こんにちは"),
+            "[Hello] こんにちは"
+        );
+        assert_eq!(
+            templates.format(OutputMode::OriginalFirst, template, "Hello", "こんにちは"),
+            "[Hello] こんにちは"
+        );
+        assert_eq!(
+            templates.format(OutputMode::Romanized, template, "Привет", "Privet"),
+            "[Привет] Privet"
+        );
+        // translated では翻訳後のタイトルだけを返す
+        assert_eq!(
+            templates.format(OutputMode::Translated, template, "Hello", "こんにちは"),
+            "こんにちは"
+        );
+    }
+
+    #[test]
+    fn identical_titles_are_not_repeated() {
+        let templates = TitleTemplates::default();
+        for mode in [
+            OutputMode::Translated,
+            OutputMode::Bilingual,
+            OutputMode::OriginalFirst,
+            OutputMode::Romanized,
+        ] {
+            assert_eq!(
+                templates.format(
+                    mode,
+                    Some("{original} | {translated}"),
+                    "Rust 1.80 ",
+                    "Rust 1.80"
+                ),
+                "Rust 1.80",
+                "{:?}",
+                mode
+            );
+        }
+    }
+}
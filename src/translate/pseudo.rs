@@ -0,0 +1,279 @@
+use regex::{Captures, Regex};
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::backend::{TranslateFuture, TranslateOptions, TranslatedItem, TranslationBackend};
+use super::batch::{self, BatchLimits};
+use super::error::{self, TranslateError};
+
+// 変換の方法
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PseudoMode {
+    // "[ja-JP] 元のテキスト" のように翻訳先の言語で囲む
+    Brackets,
+    // 単語ごとに文字の順番を逆にする (プレースホルダーの [[0]] などは残す)
+    Reverse,
+    // 辞書にある単語だけを置き換える
+    Dictionary,
+}
+
+impl FromStr for PseudoMode {
+    type Err = String;
+
+    fn from_str(mode: &str) -> Result<Self, Self::Err> {
+        match mode {
+            "brackets" => Ok(PseudoMode::Brackets),
+            "reverse" => Ok(PseudoMode::Reverse),
+            "dictionary" => Ok(PseudoMode::Dictionary),
+            _ => Err(format!("unknown pseudo mode: {}", mode)),
+        }
+    }
+}
+
+pub struct PseudoBackendOptions {
+    pub mode: PseudoMode,
+    // 小文字の単語 -> 置き換え後の単語
+    pub dictionary: HashMap<String, String>,
+    // 1リクエストごとに待つ時間
+    pub latency: Duration,
+    // 失敗させるリクエストの割合 (0.0 - 1.0)
+    pub failure_rate: f64,
+    pub batch_limits: BatchLimits,
+}
+
+// 外部のAPIを呼ばずに決まった変換をするバックエンド
+//   認証情報なしで /rss の動作を確認したり、遅延や失敗を再現したりするために使う
+#[derive(Clone)]
+pub struct PseudoBackend {
+    mode: PseudoMode,
+    dictionary: Arc<HashMap<String, String>>,
+    word_pattern: Regex,
+    latency: Duration,
+    failure_rate: f64,
+    // 失敗させるかどうかを決めるためのリクエストの通し番号
+    request_count: Arc<AtomicU64>,
+    batch_limits: BatchLimits,
+}
+
+impl TranslationBackend for PseudoBackend {
+    fn translate(
+        &self,
+        target_strs: Vec<String>,
+        options: TranslateOptions,
+    ) -> TranslateFuture<Vec<TranslatedItem>> {
+        let backend = self.clone();
+        Box::pin(async move { backend.translate_batches(target_strs, options.to).await })
+    }
+
+    fn supported_languages(&self) -> TranslateFuture<Vec<String>> {
+        // どの言語でも変換できるので一覧は返さない
+        Box::pin(async move { Ok(Vec::new()) })
+    }
+
+    fn name(&self) -> String {
+        "pseudo".to_string()
+    }
+
+    fn version(&self) -> String {
+        match self.mode {
+            PseudoMode::Brackets => "brackets".to_string(),
+            PseudoMode::Reverse => "reverse".to_string(),
+            PseudoMode::Dictionary => "dictionary".to_string(),
+        }
+    }
+
+    fn clone_box(&self) -> Box<dyn TranslationBackend> {
+        Box::new(self.clone())
+    }
+}
+
+impl PseudoBackend {
+    // 実際の制限はないが、バッチの分割も確認できるように他のバックエンドと同程度にしておく
+    pub const DEFAULT_BATCH_LIMITS: BatchLimits = BatchLimits {
+        max_segments: 50,
+        max_chars: 5000,
+        concurrency: 4,
+    };
+
+    pub fn new(options: PseudoBackendOptions) -> Self {
+        let dictionary = options
+            .dictionary
+            .into_iter()
+            .map(|(word, translated)| (word.to_lowercase(), translated))
+            .collect();
+
+        PseudoBackend {
+            mode: options.mode,
+            dictionary: Arc::new(dictionary),
+            word_pattern: Regex::new(r"[\p{L}']+").unwrap(),
+            latency: options.latency,
+            failure_rate: options.failure_rate.clamp(0.0, 1.0),
+            request_count: Arc::new(AtomicU64::new(0)),
+            batch_limits: options.batch_limits,
+        }
+    }
+
+    fn transform(&self, text: &str, to: &str) -> String {
+        match self.mode {
+            PseudoMode::Brackets => format!("[{}] {}", to, text),
+            PseudoMode::Reverse => self
+                .word_pattern
+                .replace_all(text, |captures: &Captures| {
+                    captures[0].chars().rev().collect::<String>()
+                })
+                .to_string(),
+            PseudoMode::Dictionary => self
+                .word_pattern
+                .replace_all(text, |captures: &Captures| {
+                    let word = &captures[0];
+                    match self.dictionary.get(&word.to_lowercase()) {
+                        Some(translated) => translated.clone(),
+                        None => word.to_string(),
+                    }
+                })
+                .to_string(),
+        }
+    }
+
+    // 乱数を使わずに failure_rate の割合で失敗させる
+    //   n番目のリクエストは floor((n + 1) * rate) が floor(n * rate) より大きくなった場合に失敗する
+    fn should_fail(&self) -> bool {
+        if self.failure_rate <= 0.0 {
+            return false;
+        }
+        let count = self.request_count.fetch_add(1, Ordering::SeqCst) as f64;
+        ((count + 1.0) * self.failure_rate).floor() > (count * self.failure_rate).floor()
+    }
+
+    async fn translate_batches(
+        &self,
+        target_strs: Vec<String>,
+        to: String,
+    ) -> Result<Vec<TranslatedItem>, TranslateError> {
+        let backend = self.clone();
+        let results = batch::dispatch(target_strs, self.batch_limits, move |batch| {
+            let backend = backend.clone();
+            let to = to.clone();
+            async move { backend.translate_batch(batch, to).await }
+        })
+        .await;
+        Ok(results)
+    }
+
+    async fn translate_batch(
+        &self,
+        batch: Vec<String>,
+        to: String,
+    ) -> Result<Vec<TranslatedItem>, TranslateError> {
+        if !self.latency.is_zero() {
+            tokio::time::sleep(self.latency).await;
+        }
+        if self.should_fail() {
+            return Err(TranslateError::Api {
+                backend: self.name(),
+                status: 503,
+                code: Some("SIMULATED_FAILURE".to_string()),
+                message: "simulated failure".to_string(),
+            });
+        }
+
+        let translated = batch
+            .iter()
+            .map(|text| self.transform(text, &to))
+            .collect();
+        error::pair_translations(&self.name(), &batch, translated)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn backend(mode: PseudoMode, failure_rate: f64, max_segments: usize) -> PseudoBackend {
+        PseudoBackend::new(PseudoBackendOptions {
+            mode,
+            dictionary: HashMap::from([
+                ("Hello".to_string(), "こんにちは".to_string()),
+                ("world".to_string(), "世界".to_string()),
+            ]),
+            latency: Duration::ZERO,
+            failure_rate,
+            batch_limits: BatchLimits {
+                max_segments,
+                max_chars: 5000,
+                concurrency: 1,
+            },
+        })
+    }
+
+    #[test]
+    fn brackets_wrap_the_text_with_the_target_language() {
+        let backend = backend(PseudoMode::Brackets, 0.0, 50);
+        assert_eq!(
+            backend.transform("Hello [[0]]", "ja-JP"),
+            "[ja-JP] Hello [[0]]"
+        );
+    }
+
+    #[test]
+    fn reverse_keeps_placeholders() {
+        let backend = backend(PseudoMode::Reverse, 0.0, 50);
+        assert_eq!(
+            backend.transform("Rust's [[0]] release [[12]]!", "ja"),
+            "s'tsuR [[0]] esaeler [[12]]!"
+        );
+    }
+
+    #[test]
+    fn dictionary_replaces_known_words_ignoring_case() {
+        let backend = backend(PseudoMode::Dictionary, 0.0, 50);
+        assert_eq!(
+            backend.transform("hello WORLD, new [[0]]", "ja"),
+            "こんにちは 世界, new [[0]]"
+        );
+    }
+
+    #[test]
+    fn failures_follow_the_rate_deterministically() {
+        let sequence = |rate: f64| {
+            let backend = backend(PseudoMode::Brackets, rate, 50);
+            (0..8).map(|_| backend.should_fail()).collect::<Vec<bool>>()
+        };
+        assert_eq!(sequence(0.0), vec![false; 8]);
+        assert_eq!(
+            sequence(0.5),
+            vec![false, true, false, true, false, true, false, true]
+        );
+        assert_eq!(
+            sequence(0.25),
+            vec![false, false, false, true, false, false, false, true]
+        );
+        assert_eq!(sequence(1.0), vec![true; 8]);
+    }
+
+    #[tokio::test]
+    async fn failed_batches_fail_only_their_own_texts() {
+        let backend = backend(PseudoMode::Brackets, 0.5, 1);
+        let options = TranslateOptions {
+            to: "ja".to_string(),
+            formality: None,
+            glossary_id: None,
+            model: None,
+            mime_type: None,
+        };
+        let results = backend
+            .translate(vec!["a".to_string(), "b".to_string()], options)
+            .await
+            .unwrap();
+
+        // 1件ずつ順番に送るので、2番目のリクエストだけが失敗する
+        assert_eq!(results[0].as_ref().unwrap().translated, "[ja] a");
+        assert!(matches!(
+            results[1],
+            Err(TranslateError::Api { status: 503, .. })
+        ));
+    }
+}
@@ -0,0 +1,159 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+// トークンバケット
+struct Bucket {
+    capacity: f64,
+    // 1秒あたりに回復する量
+    refill_per_sec: f64,
+    tokens: f64,
+}
+
+impl Bucket {
+    fn new(capacity: f64, refill_per_sec: f64) -> Self {
+        Bucket {
+            capacity,
+            refill_per_sec,
+            tokens: capacity,
+        }
+    }
+
+    fn refill(&mut self, elapsed: Duration) {
+        self.tokens =
+            (self.tokens + elapsed.as_secs_f64() * self.refill_per_sec).min(self.capacity);
+    }
+
+    // 必要な量が貯まるまでの時間
+    //   容量を超える量を要求された場合は満タンになれば通す
+    fn wait_time(&self, amount: f64) -> Duration {
+        let amount = amount.min(self.capacity);
+        if self.tokens >= amount {
+            return Duration::ZERO;
+        }
+        Duration::from_secs_f64((amount - self.tokens) / self.refill_per_sec)
+    }
+
+    fn take(&mut self, amount: f64) {
+        self.tokens -= amount.min(self.capacity);
+    }
+}
+
+struct RateLimiterState {
+    requests: Option<Bucket>,
+    chars: Option<Bucket>,
+    last_refill: Instant,
+}
+
+// 翻訳APIのクォータを超えないようにクライアント側で流量を制限する
+//   cloneしても同じバケットを共有するので、プロセス内のすべてのリクエストで共通になる
+#[derive(Clone)]
+pub struct RateLimiter {
+    state: Arc<Mutex<RateLimiterState>>,
+}
+
+impl RateLimiter {
+    // 0を指定した場合はその制限をしない
+    pub fn new(requests_per_sec: f64, chars_per_minute: f64) -> Self {
+        let requests = match requests_per_sec > 0.0 {
+            true => Some(Bucket::new(requests_per_sec.max(1.0), requests_per_sec)),
+            false => None,
+        };
+        let chars = match chars_per_minute > 0.0 {
+            true => Some(Bucket::new(chars_per_minute, chars_per_minute / 60.0)),
+            false => None,
+        };
+
+        RateLimiter {
+            state: Arc::new(Mutex::new(RateLimiterState {
+                requests,
+                chars,
+                last_refill: Instant::now(),
+            })),
+        }
+    }
+
+    pub fn unlimited() -> Self {
+        RateLimiter::new(0.0, 0.0)
+    }
+
+    // 1リクエスト分と文字数分のトークンが貯まるまで待つ
+    pub async fn acquire(&self, chars: usize) {
+        loop {
+            let wait_time = {
+                let mut state = self.state.lock().await;
+                let now = Instant::now();
+                let elapsed = now.duration_since(state.last_refill);
+                state.last_refill = now;
+
+                let mut wait_time = Duration::ZERO;
+                if let Some(requests) = state.requests.as_mut() {
+                    requests.refill(elapsed);
+                    wait_time = wait_time.max(requests.wait_time(1.0));
+                }
+                if let Some(char_bucket) = state.chars.as_mut() {
+                    char_bucket.refill(elapsed);
+                    wait_time = wait_time.max(char_bucket.wait_time(chars as f64));
+                }
+
+                if wait_time.is_zero() {
+                    if let Some(requests) = state.requests.as_mut() {
+                        requests.take(1.0);
+                    }
+                    if let Some(char_bucket) = state.chars.as_mut() {
+                        char_bucket.take(chars as f64);
+                    }
+                }
+                wait_time
+            };
+
+            if wait_time.is_zero() {
+                return;
+            }
+            tokio::time::sleep(wait_time).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bucket_refills_up_to_capacity() {
+        let mut bucket = Bucket::new(10.0, 2.0);
+        bucket.take(10.0);
+        assert_eq!(bucket.tokens, 0.0);
+
+        bucket.refill(Duration::from_secs(2));
+        assert_eq!(bucket.tokens, 4.0);
+
+        bucket.refill(Duration::from_secs(60));
+        assert_eq!(bucket.tokens, 10.0);
+    }
+
+    #[test]
+    fn wait_time_covers_the_missing_tokens() {
+        let mut bucket = Bucket::new(10.0, 2.0);
+        assert_eq!(bucket.wait_time(10.0), Duration::ZERO);
+
+        bucket.take(10.0);
+        assert_eq!(bucket.wait_time(4.0), Duration::from_secs(2));
+        // 容量を超える量は満タンになるまで待てば通す
+        assert_eq!(bucket.wait_time(100.0), Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn acquire_waits_once_the_bucket_is_empty() {
+        let rate_limiter = RateLimiter::new(10.0, 0.0);
+        let started = Instant::now();
+        for _ in 0..10 {
+            rate_limiter.acquire(0).await;
+        }
+        assert!(started.elapsed() < Duration::from_millis(50));
+
+        // 11件目は1件分 (100ms) 回復するまで待つ
+        rate_limiter.acquire(0).await;
+        assert!(started.elapsed() >= Duration::from_millis(90));
+    }
+}
@@ -0,0 +1,235 @@
+use std::hash::BuildHasher;
+use std::time::Duration;
+
+use super::error::TranslateError;
+use super::rate_limit::RateLimiter;
+
+#[derive(Clone, Copy, Debug)]
+pub struct RetryPolicy {
+    // 最初の1回を除いた再試行の回数
+    pub max_retries: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    // 指数的に伸ばした待ち時間の範囲でランダムに待つ (full jitter)
+    fn backoff(&self, attempt: u32) -> Duration {
+        let exponential = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_delay);
+        let random =
+            std::collections::hash_map::RandomState::new().hash_one(std::time::SystemTime::now());
+        let jitter_ms = random % (exponential.as_millis() as u64 + 1);
+        Duration::from_millis(jitter_ms)
+    }
+
+    // 再試行するまでに待つ時間
+    //   Retry-After が上限より長い場合は待たずに RateLimited を返す
+    fn delay(
+        &self,
+        attempt: u32,
+        retry_after: Option<Duration>,
+    ) -> Result<Duration, TranslateError> {
+        let backoff = self.backoff(attempt);
+        match retry_after {
+            Some(retry_after) if retry_after > self.max_delay => {
+                Err(TranslateError::RateLimited { retry_after })
+            }
+            Some(retry_after) => Ok(retry_after.max(backoff)),
+            None => Ok(backoff),
+        }
+    }
+}
+
+// Retry-After ヘッダーを読む (秒数とHTTP日付の両方に対応)
+pub fn parse_retry_after(response: &reqwest::Response) -> Option<Duration> {
+    let value = response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let retry_at = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    let seconds = (retry_at.timestamp() - chrono::Utc::now().timestamp()).max(0);
+    Some(Duration::from_secs(seconds as u64))
+}
+
+fn is_retryable_status(status: reqwest::StatusCode) -> bool {
+    status == reqwest::StatusCode::TOO_MANY_REQUESTS
+        || status == reqwest::StatusCode::REQUEST_TIMEOUT
+        || status.is_server_error()
+}
+
+// 翻訳APIへのリクエストの送り方 (流量制限と再試行)
+#[derive(Clone)]
+pub struct RequestPolicy {
+    pub retry: RetryPolicy,
+    pub rate_limiter: RateLimiter,
+}
+
+impl Default for RequestPolicy {
+    fn default() -> Self {
+        RequestPolicy {
+            retry: RetryPolicy::default(),
+            rate_limiter: RateLimiter::unlimited(),
+        }
+    }
+}
+
+impl RequestPolicy {
+    // 429や5xx、接続エラーの場合は待ってから再送する
+    //   再試行しきった場合は最後のレスポンスをそのまま返す
+    pub async fn send(
+        &self,
+        request: reqwest::RequestBuilder,
+        chars: usize,
+    ) -> Result<reqwest::Response, TranslateError> {
+        let mut attempt = 0;
+        loop {
+            let current_request = request.try_clone().ok_or("request body cannot be cloned")?;
+
+            self.rate_limiter.acquire(chars).await;
+            let result = current_request.send().await;
+
+            let can_retry = attempt < self.retry.max_retries;
+            let delay = match result {
+                Ok(response) => {
+                    if !can_retry || !is_retryable_status(response.status()) {
+                        return Ok(response);
+                    }
+                    self.retry.delay(attempt, parse_retry_after(&response))?
+                }
+                Err(e) => {
+                    if !can_retry || !(e.is_timeout() || e.is_connect()) {
+                        return Err(TranslateError::Http(e));
+                    }
+                    self.retry.backoff(attempt)
+                }
+            };
+
+            attempt += 1;
+            println!(
+                "translation request failed, retrying in {}ms ({}/{})",
+                delay.as_millis(),
+                attempt,
+                self.retry.max_retries
+            );
+            tokio::time::sleep(delay).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    fn policy(max_retries: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_retries,
+            base_delay: Duration::from_millis(10),
+            max_delay: Duration::from_millis(100),
+        }
+    }
+
+    // 接続ごとに responses を順番に返すサーバーを立てて、URLと受け付けた回数を返す
+    async fn serve(responses: Vec<&'static str>) -> (String, Arc<AtomicUsize>) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let count = Arc::new(AtomicUsize::new(0));
+        let served = count.clone();
+        tokio::spawn(async move {
+            for response in responses {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buffer = [0u8; 4096];
+                let _ = stream.read(&mut buffer).await;
+                served.fetch_add(1, Ordering::SeqCst);
+                stream.write_all(response.as_bytes()).await.unwrap();
+                stream.shutdown().await.unwrap();
+            }
+        });
+        (url, count)
+    }
+
+    #[test]
+    fn backoff_stays_within_the_exponential_bound() {
+        let policy = policy(10);
+        for attempt in 0..10 {
+            let bound = (policy.base_delay * 2u32.pow(attempt)).min(policy.max_delay);
+            for _ in 0..20 {
+                assert!(policy.backoff(attempt) <= bound);
+            }
+        }
+        // 大きな回数でもあふれずに上限で止まる
+        assert!(policy.backoff(u32::MAX) <= policy.max_delay);
+    }
+
+    #[test]
+    fn retry_after_is_honoured_up_to_max_delay() {
+        let policy = policy(3);
+        let delay = policy.delay(0, Some(Duration::from_millis(80))).unwrap();
+        assert!(delay >= Duration::from_millis(80));
+
+        match policy.delay(0, Some(Duration::from_secs(120))) {
+            Err(TranslateError::RateLimited { retry_after }) => {
+                assert_eq!(retry_after, Duration::from_secs(120))
+            }
+            _ => panic!("expected RateLimited"),
+        }
+    }
+
+    #[tokio::test]
+    async fn long_retry_after_is_not_waited_for() {
+        let (url, count) = serve(vec![
+            "HTTP/1.1 429 Too Many Requests\r\nRetry-After: 120\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        ])
+        .await;
+        let request_policy = RequestPolicy {
+            retry: policy(3),
+            rate_limiter: RateLimiter::unlimited(),
+        };
+
+        let result = request_policy
+            .send(reqwest::Client::new().get(url), 0)
+            .await;
+        assert!(matches!(result, Err(TranslateError::RateLimited { .. })));
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn server_errors_are_retried() {
+        let (url, count) = serve(vec![
+            "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            "HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok",
+        ])
+        .await;
+        let request_policy = RequestPolicy {
+            retry: policy(3),
+            rate_limiter: RateLimiter::unlimited(),
+        };
+
+        let response = request_policy
+            .send(reqwest::Client::new().get(url), 0)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,294 @@
+use regex::{NoExpand, Regex};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::error::Error;
+
+use super::term_protection::language_matches;
+
+// 書き換えを行うタイミング
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum RewriteStage {
+    // 翻訳APIに送る前の原文を書き換える
+    Pre,
+    // 翻訳後のテキストを書き換える
+    #[default]
+    Post,
+}
+
+// 置き換えのルール
+#[derive(Deserialize, Clone, Default)]
+pub struct RewriteRule {
+    pub pattern: String,
+    // 正規表現の場合は $1 などでキャプチャを参照できる
+    pub replacement: String,
+    // trueの場合は pattern を正規表現として扱う (デフォルトは文字列の一致)
+    #[serde(default)]
+    pub regex: bool,
+    #[serde(default)]
+    pub ignore_case: bool,
+    #[serde(default)]
+    pub stage: RewriteStage,
+    // 翻訳先の言語 (未指定の場合はすべての言語)
+    pub to: Option<String>,
+}
+
+#[derive(Deserialize, Clone, Default)]
+pub struct RewriteConfig {
+    // すべてのフィードに適用するルール
+    #[serde(default)]
+    pub rules: Vec<RewriteRule>,
+    // フィードのURL (前方一致) ごとのルール
+    #[serde(default)]
+    pub feeds: HashMap<String, Vec<RewriteRule>>,
+}
+
+impl RewriteConfig {
+    pub fn load(path: &str) -> Result<RewriteConfig, Box<dyn Error + Send + Sync>> {
+        let config_json = std::fs::read_to_string(path)?;
+        let config: RewriteConfig = serde_json::from_str(&config_json)?;
+        // 正規表現の誤りは起動時に分かるようにする
+        for rule in config.rules.iter().chain(config.feeds.values().flatten()) {
+            rule_regex(rule)?;
+        }
+        Ok(config)
+    }
+
+    // グローバルなルールの後にフィードのルールを適用する
+    pub fn rewriter_for(&self, feed_url: &str, to: &str) -> Rewriter {
+        let mut prefixes: Vec<&String> = self
+            .feeds
+            .keys()
+            .filter(|url_prefix| feed_url.starts_with(url_prefix.as_str()))
+            .collect();
+        // 短いURLから順に適用して、より具体的なフィードのルールを後にする
+        prefixes.sort_by_key(|url_prefix| url_prefix.len());
+
+        let rules = self
+            .rules
+            .iter()
+            .chain(
+                prefixes
+                    .into_iter()
+                    .flat_map(|url_prefix| &self.feeds[url_prefix]),
+            )
+            .filter(|rule| match rule.to.as_deref() {
+                Some(rule_to) => language_matches(rule_to, to),
+                None => true,
+            });
+
+        let mut rewriter = Rewriter::default();
+        for rule in rules {
+            let regex = match rule_regex(rule) {
+                Ok(regex) => regex,
+                Err(e) => {
+                    println!("Error (invalid rewrite rule {:?}): {}", rule.pattern, e);
+                    continue;
+                }
+            };
+            let compiled = CompiledRule {
+                regex,
+                replacement: rule.replacement.clone(),
+                expand: rule.regex,
+            };
+            match rule.stage {
+                RewriteStage::Pre => rewriter.pre.push(compiled),
+                RewriteStage::Post => rewriter.post.push(compiled),
+            }
+        }
+
+        rewriter
+    }
+}
+
+fn rule_regex(rule: &RewriteRule) -> Result<Regex, regex::Error> {
+    let pattern = match rule.regex {
+        true => rule.pattern.clone(),
+        false => regex::escape(&rule.pattern),
+    };
+    let pattern = match rule.ignore_case {
+        true => format!("(?i){}", pattern),
+        false => pattern,
+    };
+    Regex::new(&pattern)
+}
+
+struct CompiledRule {
+    regex: Regex,
+    replacement: String,
+    // 置き換え後の文字列の $1 などを展開するかどうか
+    expand: bool,
+}
+
+impl CompiledRule {
+    fn apply(&self, text: &str) -> String {
+        match self.expand {
+            true => self
+                .regex
+                .replace_all(text, self.replacement.as_str())
+                .to_string(),
+            false => self
+                .regex
+                .replace_all(text, NoExpand(&self.replacement))
+                .to_string(),
+        }
+    }
+}
+
+// フィードと翻訳先の言語に合わせて選んだルール
+#[derive(Default)]
+pub struct Rewriter {
+    pre: Vec<CompiledRule>,
+    post: Vec<CompiledRule>,
+}
+
+impl Rewriter {
+    fn apply(rules: &[CompiledRule], text: &str) -> String {
+        rules
+            .iter()
+            .fold(text.to_string(), |text, rule| rule.apply(&text))
+    }
+
+    // 翻訳前に適用するルールを並べたもの (翻訳結果が変わるのでキャッシュのキーに含める、ルールがない場合は空)
+    pub fn source_fingerprint(&self) -> String {
+        self.pre
+            .iter()
+            .map(|rule| {
+                format!(
+                    "{}={:?}:{}",
+                    rule.regex.as_str(),
+                    rule.replacement,
+                    rule.expand
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    // 翻訳APIに送る前の原文に適用する
+    pub fn rewrite_source(&self, text: &str) -> String {
+        Rewriter::apply(&self.pre, text)
+    }
+
+    // 翻訳後のテキストに適用する
+    pub fn rewrite_translated(&self, text: &str) -> String {
+        Rewriter::apply(&self.post, text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(json: &str) -> RewriteConfig {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn literal_rules_do_not_expand_captures() {
+        let rewriter = config(r#"{"rules": [{"pattern": "a.b", "replacement": "$1"}]}"#)
+            .rewriter_for("https://example.com/feed", "ja-JP");
+        assert_eq!(rewriter.rewrite_translated("a.b axb"), "$1 axb");
+    }
+
+    #[test]
+    fn regex_rules_strip_trailing_period() {
+        let rewriter = config(
+            r#"{"rules": [{"pattern": "。$", "replacement": "", "regex": true, "to": "ja"}]}"#,
+        )
+        .rewriter_for("https://example.com/feed", "ja-JP");
+        assert_eq!(
+            rewriter.rewrite_translated("新しいバージョンが公開されました。"),
+            "新しいバージョンが公開されました"
+        );
+        assert_eq!(rewriter.rewrite_translated("途中。の句点"), "途中。の句点");
+    }
+
+    #[test]
+    fn regex_rules_expand_captures() {
+        let rewriter = config(
+            r#"{"rules": [{"pattern": "（(.+?)）", "replacement": "($1)", "regex": true}]}"#,
+        )
+        .rewriter_for("https://example.com/feed", "ja-JP");
+        assert_eq!(
+            rewriter.rewrite_translated("リリース（安定版）"),
+            "リリース(安定版)"
+        );
+    }
+
+    #[test]
+    fn rules_are_scoped_by_language() {
+        let rewriter = config(
+            r#"{"rules": [
+                {"pattern": "！", "replacement": "!", "to": "ja"},
+                {"pattern": "!", "replacement": "！", "to": "zh-CN"}
+            ]}"#,
+        )
+        .rewriter_for("https://example.com/feed", "ja-JP");
+        assert_eq!(rewriter.rewrite_translated("速報！ new!"), "速報! new!");
+    }
+
+    #[test]
+    fn feed_rules_apply_after_global_rules() {
+        let config = config(
+            r#"{
+                "rules": [{"pattern": "プルリク", "replacement": "PR"}],
+                "feeds": {
+                    "https://example.com/": [{"pattern": "PR", "replacement": "プルリクエスト"}],
+                    "https://example.com/blog/": [{"pattern": "プルリクエスト", "replacement": "Pull Request"}]
+                }
+            }"#,
+        );
+        assert_eq!(
+            config
+                .rewriter_for("https://example.com/blog/feed", "ja-JP")
+                .rewrite_translated("プルリクを送る"),
+            "Pull Requestを送る"
+        );
+        assert_eq!(
+            config
+                .rewriter_for("https://example.com/news/feed", "ja-JP")
+                .rewrite_translated("プルリクを送る"),
+            "プルリクエストを送る"
+        );
+        assert_eq!(
+            config
+                .rewriter_for("https://other.example.com/feed", "ja-JP")
+                .rewrite_translated("プルリクを送る"),
+            "PRを送る"
+        );
+    }
+
+    #[test]
+    fn pre_rules_only_rewrite_source() {
+        let rewriter = config(
+            r#"{"rules": [{"pattern": "k8s", "replacement": "Kubernetes", "ignore_case": true, "stage": "pre"}]}"#,
+        )
+        .rewriter_for("https://example.com/feed", "ja-JP");
+        assert_eq!(
+            rewriter.rewrite_source("K8s 1.30 released"),
+            "Kubernetes 1.30 released"
+        );
+        assert_eq!(rewriter.rewrite_translated("k8s"), "k8s");
+        assert_ne!(rewriter.source_fingerprint(), "");
+    }
+
+    #[test]
+    fn post_rules_do_not_change_the_source_fingerprint() {
+        let rewriter = config(r#"{"rules": [{"pattern": "!", "replacement": "！"}]}"#)
+            .rewriter_for("https://example.com/feed", "ja-JP");
+        assert_eq!(rewriter.source_fingerprint(), "");
+    }
+
+    #[test]
+    fn invalid_regex_is_rejected_on_load() {
+        let path = std::env::temp_dir().join("rewrite_rules_invalid.json");
+        std::fs::write(
+            &path,
+            r#"{"rules": [{"pattern": "(", "replacement": "", "regex": true}]}"#,
+        )
+        .unwrap();
+        assert!(RewriteConfig::load(path.to_str().unwrap()).is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+}
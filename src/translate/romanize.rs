@@ -0,0 +1,322 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use super::backend::{TranslateFuture, TranslateOptions, TranslatedItem, TranslationBackend};
+use super::error;
+
+// ひらがなのヘボン式のローマ字 (カタカナはひらがなにしてから変換する)
+#[rustfmt::skip]
+const KANA_TABLE: &[(&str, &str)] = &[
+    ("あ", "a"), ("い", "i"), ("う", "u"), ("え", "e"), ("お", "o"),
+    ("か", "ka"), ("き", "ki"), ("く", "ku"), ("け", "ke"), ("こ", "ko"),
+    ("さ", "sa"), ("し", "shi"), ("す", "su"), ("せ", "se"), ("そ", "so"),
+    ("た", "ta"), ("ち", "chi"), ("つ", "tsu"), ("て", "te"), ("と", "to"),
+    ("な", "na"), ("に", "ni"), ("ぬ", "nu"), ("ね", "ne"), ("の", "no"),
+    ("は", "ha"), ("ひ", "hi"), ("ふ", "fu"), ("へ", "he"), ("ほ", "ho"),
+    ("ま", "ma"), ("み", "mi"), ("む", "mu"), ("め", "me"), ("も", "mo"),
+    ("や", "ya"), ("ゆ", "yu"), ("よ", "yo"),
+    ("ら", "ra"), ("り", "ri"), ("る", "ru"), ("れ", "re"), ("ろ", "ro"),
+    ("わ", "wa"), ("ゐ", "i"), ("ゑ", "e"), ("を", "o"), ("ん", "n"),
+    ("が", "ga"), ("ぎ", "gi"), ("ぐ", "gu"), ("げ", "ge"), ("ご", "go"),
+    ("ざ", "za"), ("じ", "ji"), ("ず", "zu"), ("ぜ", "ze"), ("ぞ", "zo"),
+    ("だ", "da"), ("ぢ", "ji"), ("づ", "zu"), ("で", "de"), ("ど", "do"),
+    ("ば", "ba"), ("び", "bi"), ("ぶ", "bu"), ("べ", "be"), ("ぼ", "bo"),
+    ("ぱ", "pa"), ("ぴ", "pi"), ("ぷ", "pu"), ("ぺ", "pe"), ("ぽ", "po"),
+    ("ゔ", "vu"),
+    ("ぁ", "a"), ("ぃ", "i"), ("ぅ", "u"), ("ぇ", "e"), ("ぉ", "o"),
+    ("ゃ", "ya"), ("ゅ", "yu"), ("ょ", "yo"), ("ゎ", "wa"),
+    ("きゃ", "kya"), ("きゅ", "kyu"), ("きょ", "kyo"),
+    ("しゃ", "sha"), ("しゅ", "shu"), ("しぇ", "she"), ("しょ", "sho"),
+    ("ちゃ", "cha"), ("ちゅ", "chu"), ("ちぇ", "che"), ("ちょ", "cho"),
+    ("にゃ", "nya"), ("にゅ", "nyu"), ("にょ", "nyo"),
+    ("ひゃ", "hya"), ("ひゅ", "hyu"), ("ひょ", "hyo"),
+    ("みゃ", "mya"), ("みゅ", "myu"), ("みょ", "myo"),
+    ("りゃ", "rya"), ("りゅ", "ryu"), ("りょ", "ryo"),
+    ("ぎゃ", "gya"), ("ぎゅ", "gyu"), ("ぎょ", "gyo"),
+    ("じゃ", "ja"), ("じゅ", "ju"), ("じぇ", "je"), ("じょ", "jo"),
+    ("ぢゃ", "ja"), ("ぢゅ", "ju"), ("ぢょ", "jo"),
+    ("びゃ", "bya"), ("びゅ", "byu"), ("びょ", "byo"),
+    ("ぴゃ", "pya"), ("ぴゅ", "pyu"), ("ぴょ", "pyo"),
+    // 外来語の表記
+    ("ふぁ", "fa"), ("ふぃ", "fi"), ("ふぇ", "fe"), ("ふぉ", "fo"),
+    ("てぃ", "ti"), ("でぃ", "di"), ("とぅ", "tu"), ("どぅ", "du"),
+    ("うぃ", "wi"), ("うぇ", "we"), ("うぉ", "wo"),
+    ("ゔぁ", "va"), ("ゔぃ", "vi"), ("ゔぇ", "ve"), ("ゔぉ", "vo"),
+    ("つぁ", "tsa"), ("つぃ", "tsi"), ("つぇ", "tse"), ("つぉ", "tso"),
+];
+
+fn is_kana(ch: char) -> bool {
+    matches!(ch, '\u{3041}'..='\u{3096}' | '\u{30A1}'..='\u{30FA}' | 'ー')
+}
+
+// 日本語の文の中の文字の種類
+//   単語の区切りは辞書がないと分からないので、文字の種類が変わるところで区切る
+#[derive(Clone, Copy, PartialEq)]
+enum JapaneseScript {
+    Hiragana,
+    Katakana,
+    Han,
+}
+
+fn japanese_script(ch: char) -> Option<JapaneseScript> {
+    match ch {
+        '\u{3041}'..='\u{3096}' => Some(JapaneseScript::Hiragana),
+        '\u{30A1}'..='\u{30FA}' | 'ー' => Some(JapaneseScript::Katakana),
+        _ if is_han(ch) => Some(JapaneseScript::Han),
+        _ => None,
+    }
+}
+
+fn is_han(ch: char) -> bool {
+    matches!(ch, '\u{3400}'..='\u{4DBF}' | '\u{4E00}'..='\u{9FFF}' | '\u{F900}'..='\u{FAFF}' | '々')
+}
+
+// カタカナをひらがなにする
+fn to_hiragana(ch: char) -> char {
+    match ch {
+        '\u{30A1}'..='\u{30F6}' => char::from_u32(ch as u32 - 0x60).unwrap_or(ch),
+        _ => ch,
+    }
+}
+
+// 翻訳する代わりにラテン文字に書き換えるバックエンド
+//   日本語のかなはヘボン式、それ以外の文字 (キリル文字、中国語のピンイン、ハングルなど) は any_ascii で変換する
+//   漢字の読みは辞書がないと決められないので、日本語は完全にはローマ字にならない
+//   (かなを含む日本語の文では漢字をそのまま残し、漢字だけのタイトルは中国語として扱う)
+#[derive(Clone)]
+pub struct RomanizeBackend {
+    kana_table: Arc<HashMap<&'static str, &'static str>>,
+}
+
+impl TranslationBackend for RomanizeBackend {
+    fn translate(
+        &self,
+        target_strs: Vec<String>,
+        _options: TranslateOptions,
+    ) -> TranslateFuture<Vec<TranslatedItem>> {
+        let backend = self.clone();
+        Box::pin(async move {
+            let romanized = target_strs
+                .iter()
+                .map(|text| backend.romanize(text))
+                .collect();
+            error::pair_translations(&backend.name(), &target_strs, romanized)
+        })
+    }
+
+    fn supported_languages(&self) -> TranslateFuture<Vec<String>> {
+        // 翻訳先の言語は使わないので一覧は返さない
+        Box::pin(async move { Ok(Vec::new()) })
+    }
+
+    fn name(&self) -> String {
+        "romanize".to_string()
+    }
+
+    fn version(&self) -> String {
+        "hepburn+any_ascii".to_string()
+    }
+
+    fn clone_box(&self) -> Box<dyn TranslationBackend> {
+        Box::new(self.clone())
+    }
+}
+
+impl Default for RomanizeBackend {
+    fn default() -> Self {
+        RomanizeBackend::new()
+    }
+}
+
+impl RomanizeBackend {
+    pub fn new() -> Self {
+        RomanizeBackend {
+            kana_table: Arc::new(KANA_TABLE.iter().copied().collect()),
+        }
+    }
+
+    pub fn romanize(&self, text: &str) -> String {
+        let is_japanese = text.chars().any(|ch| is_kana(ch) && ch != 'ー');
+
+        let mut romanized = String::with_capacity(text.len());
+        let chars: Vec<char> = text.chars().collect();
+        let mut index = 0;
+        while index < chars.len() {
+            let ch = chars[index];
+            if let Some(script) = japanese_script(ch).filter(|_| is_japanese) {
+                // 同じ種類の文字のまとまりごとに変換し、前後とは空白で区切る
+                let start = index;
+                index += 1;
+                while index < chars.len() {
+                    match japanese_script(chars[index]) {
+                        Some(next) if next == script => {}
+                        // ひらがなの後の長音はひらがなのまとまりに含める
+                        Some(JapaneseScript::Katakana)
+                            if chars[index] == 'ー' && script == JapaneseScript::Hiragana => {}
+                        _ => break,
+                    }
+                    index += 1;
+                }
+                let run: String = chars[start..index].iter().collect();
+                let converted = match script {
+                    JapaneseScript::Han => run,
+                    _ => self.romanize_kana(&run),
+                };
+                push_word(&mut romanized, &converted);
+                continue;
+            }
+            if is_japanese && ch.is_ascii() {
+                romanized.push(ch);
+            } else {
+                let converted = any_ascii::any_ascii_char(ch);
+                // 中国語は1文字ずつ音節になるので空白で区切る
+                if is_han(ch) {
+                    push_word(&mut romanized, converted);
+                } else {
+                    romanized.push_str(converted);
+                }
+            }
+            index += 1;
+        }
+
+        // 区切りのために入れた空白が句読点の前に来ないようにする
+        let mut cleaned = String::with_capacity(romanized.len());
+        for word in romanized.split(' ').filter(|word| !word.is_empty()) {
+            let attaches = word.starts_with([',', '.', '!', '?', ':', ';', ')', ']']);
+            if !cleaned.is_empty() && !attaches && !cleaned.ends_with(['(', '[', '"']) {
+                cleaned.push(' ');
+            }
+            cleaned.push_str(word);
+        }
+        cleaned
+    }
+
+    fn romanize_kana(&self, kana: &str) -> String {
+        let chars: Vec<char> = kana.chars().map(to_hiragana).collect();
+        let mut romanized = String::new();
+        // 直後の子音を重ねる (促音)
+        let mut geminate = false;
+        let mut index = 0;
+        while index < chars.len() {
+            let ch = chars[index];
+            match ch {
+                'っ' => {
+                    geminate = true;
+                    index += 1;
+                    continue;
+                }
+                // 長音は直前の母音を重ねる
+                'ー' => {
+                    if let Some(vowel) = romanized.chars().last().filter(|ch| "aiueo".contains(*ch))
+                    {
+                        romanized.push(vowel);
+                    }
+                    index += 1;
+                    continue;
+                }
+                _ => {}
+            }
+
+            let pair: String = chars[index..(index + 2).min(chars.len())].iter().collect();
+            let (syllable, length) = match self.kana_table.get(pair.as_str()) {
+                Some(syllable) if pair.chars().count() == 2 => (*syllable, 2),
+                _ => match self.kana_table.get(ch.to_string().as_str()) {
+                    Some(syllable) => (*syllable, 1),
+                    None => {
+                        romanized.push_str(any_ascii::any_ascii_char(ch));
+                        index += 1;
+                        continue;
+                    }
+                },
+            };
+
+            if geminate {
+                match syllable {
+                    _ if syllable.starts_with("ch") => romanized.push('t'),
+                    _ if syllable.starts_with(|ch: char| "aiueon".contains(ch)) => {}
+                    _ => romanized.push_str(&syllable[..1]),
+                }
+                geminate = false;
+            }
+            // ん の後に母音や y が続く場合は区切りを入れる (例: kan'i)
+            if romanized.ends_with('n')
+                && index > 0
+                && chars[index - 1] == 'ん'
+                && syllable.starts_with(|ch: char| "aiueoy".contains(ch))
+            {
+                romanized.push('\'');
+            }
+            romanized.push_str(syllable);
+            index += length;
+        }
+
+        romanized
+    }
+}
+
+fn push_word(romanized: &mut String, word: &str) {
+    if !romanized.is_empty() && !romanized.ends_with(' ') {
+        romanized.push(' ');
+    }
+    romanized.push_str(word);
+    romanized.push(' ');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_romanized(cases: &[(&str, &str)]) {
+        let backend = RomanizeBackend::new();
+        for (text, expected) in cases {
+            assert_eq!(backend.romanize(text), *expected, "{}", text);
+        }
+    }
+
+    #[test]
+    fn cyrillic() {
+        assert_romanized(&[
+            ("Москва — столица России", "Moskva - stolitsa Rossii"),
+            ("Привет, мир!", "Privet, mir!"),
+        ]);
+    }
+
+    #[test]
+    fn chinese_is_split_into_syllables() {
+        assert_romanized(&[
+            ("北京欢迎你", "Bei Jing Huan Ying Ni"),
+            ("你好，世界", "Ni Hao, Shi Jie"),
+        ]);
+    }
+
+    #[test]
+    fn kana_follows_hepburn() {
+        assert_romanized(&[
+            // 促音
+            ("きっと", "kitto"),
+            ("がっこう", "gakkou"),
+            ("ちょっと", "chotto"),
+            ("マッチ", "matchi"),
+            // 長音
+            ("コーヒー", "koohii"),
+            ("らーめん", "raamen"),
+            // 拗音
+            ("きゃりーぱみゅぱみゅ", "kyariipamyupamyu"),
+            ("ジャンプ！", "janpu!"),
+            // ん の後の母音
+            ("しんいち", "shin'ichi"),
+            ("「こんにちは」", "[konnichiha]"),
+        ]);
+    }
+
+    #[test]
+    fn kanji_in_japanese_text_is_left_as_is() {
+        assert_romanized(&[
+            ("Rustのリリース 1.76", "Rust no ririisu 1.76"),
+            ("東京タワー", "東京 tawaa"),
+            ("今日は良い天気ですね", "今日 ha 良 i 天気 desune"),
+            // かながない場合は日本語か判別できないので中国語として読む
+            ("東京", "Dong Jing"),
+        ]);
+    }
+}
@@ -0,0 +1,391 @@
+use regex::{Captures, Regex};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::error::Error;
+
+// 翻訳APIに送る前に用語をプレースホルダーに置き換え、翻訳後に元に戻す
+//   プレースホルダーは [[0]] の形式で、翻訳APIが全角にしたり空白を入れたりしても戻せるようにする
+const PLACEHOLDER_PATTERN: &str = r"[\[［]\s*[\[［]\s*(\d+)\s*[\]］]\s*[\]］]";
+
+const INLINE_CODE_PATTERN: &str = r"`[^`]+`";
+const TICKER_PATTERN: &str = r"\$[A-Z]{1,6}\b";
+const HASHTAG_PATTERN: &str = r"#[\p{L}\p{N}_]+";
+const VERSION_PATTERN: &str = r"\bv?\d+(?:\.\d+)+(?:[-+][0-9A-Za-z.]+)?\b";
+
+// 用語の強制的な訳
+#[derive(Deserialize, Clone, Default)]
+pub struct GlossaryEntry {
+    pub term: String,
+    pub translation: String,
+    // 翻訳先の言語 (未指定の場合はすべての言語)
+    pub to: Option<String>,
+}
+
+#[derive(Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct TermProtectionRules {
+    pub glossary: Vec<GlossaryEntry>,
+    // 翻訳しない用語 (製品名など)
+    pub protected_terms: Vec<String>,
+    pub protect_inline_code: bool,
+    pub protect_tickers: bool,
+    pub protect_hashtags: bool,
+    pub protect_versions: bool,
+}
+
+// 設定ファイルの形式
+#[derive(Deserialize, Default)]
+struct TermProtectionFile {
+    // すべてのフィードに適用するルール
+    #[serde(flatten)]
+    global: TermProtectionRules,
+    // フィードのURL (前方一致) ごとのルール
+    #[serde(default)]
+    feeds: HashMap<String, TermProtectionRules>,
+}
+
+// 用語の正規表現 (読み込み時に1回だけ作る)
+#[derive(Clone)]
+struct CompiledTerm {
+    regex: Regex,
+    chars: usize,
+    // 強制する訳 (Noneの場合は元の文字列に戻す)
+    translation: Option<String>,
+    to: Option<String>,
+}
+
+#[derive(Clone, Default)]
+struct CompiledRules {
+    protect_inline_code: bool,
+    protect_tickers: bool,
+    protect_hashtags: bool,
+    protect_versions: bool,
+    terms: Vec<CompiledTerm>,
+}
+
+impl From<&TermProtectionRules> for CompiledRules {
+    fn from(rules: &TermProtectionRules) -> Self {
+        let glossary = rules.glossary.iter().map(|entry| {
+            (
+                entry.term.as_str(),
+                Some(entry.translation.clone()),
+                entry.to.clone(),
+            )
+        });
+        let protected_terms = rules
+            .protected_terms
+            .iter()
+            .map(|term| (term.as_str(), None, None));
+        let terms = glossary
+            .chain(protected_terms)
+            .filter_map(|(term, translation, to)| {
+                term_regex(term).map(|regex| CompiledTerm {
+                    regex,
+                    chars: term.chars().count(),
+                    translation,
+                    to,
+                })
+            })
+            .collect();
+
+        CompiledRules {
+            protect_inline_code: rules.protect_inline_code,
+            protect_tickers: rules.protect_tickers,
+            protect_hashtags: rules.protect_hashtags,
+            protect_versions: rules.protect_versions,
+            terms,
+        }
+    }
+}
+
+// 組み込みのパターン
+#[derive(Clone)]
+struct BuiltinPatterns {
+    inline_code: Regex,
+    ticker: Regex,
+    hashtag: Regex,
+    version: Regex,
+    placeholder: Regex,
+}
+
+impl BuiltinPatterns {
+    fn new() -> Self {
+        BuiltinPatterns {
+            inline_code: Regex::new(INLINE_CODE_PATTERN).unwrap(),
+            ticker: Regex::new(TICKER_PATTERN).unwrap(),
+            hashtag: Regex::new(HASHTAG_PATTERN).unwrap(),
+            version: Regex::new(VERSION_PATTERN).unwrap(),
+            placeholder: Regex::new(PLACEHOLDER_PATTERN).unwrap(),
+        }
+    }
+}
+
+// 正規表現は読み込み時に作っておき、リクエストごとにはフィードに合うものを選ぶだけにする
+#[derive(Deserialize, Clone)]
+#[serde(from = "TermProtectionFile")]
+pub struct TermProtectionConfig {
+    global: CompiledRules,
+    feeds: HashMap<String, CompiledRules>,
+    builtin: BuiltinPatterns,
+}
+
+impl From<TermProtectionFile> for TermProtectionConfig {
+    fn from(file: TermProtectionFile) -> Self {
+        TermProtectionConfig {
+            global: CompiledRules::from(&file.global),
+            feeds: file
+                .feeds
+                .iter()
+                .map(|(url_prefix, rules)| (url_prefix.clone(), CompiledRules::from(rules)))
+                .collect(),
+            builtin: BuiltinPatterns::new(),
+        }
+    }
+}
+
+impl Default for TermProtectionConfig {
+    fn default() -> Self {
+        TermProtectionConfig::from(TermProtectionFile::default())
+    }
+}
+
+impl TermProtectionConfig {
+    pub fn load(path: &str) -> Result<TermProtectionConfig, Box<dyn Error + Send + Sync>> {
+        let config_json = std::fs::read_to_string(path)?;
+        let config: TermProtectionConfig = serde_json::from_str(&config_json)?;
+        Ok(config)
+    }
+
+    // グローバルなルールとフィードのルールをまとめる
+    pub fn protector_for(&self, feed_url: &str, to: &str) -> TermProtector {
+        let mut rules = vec![&self.global];
+        rules.extend(
+            self.feeds
+                .iter()
+                .filter(|(url_prefix, _)| feed_url.starts_with(url_prefix.as_str()))
+                .map(|(_, rules)| rules),
+        );
+
+        let mut patterns: Vec<(Regex, Option<String>)> = Vec::new();
+        let builtin_patterns = [
+            (
+                &self.builtin.inline_code,
+                rules.iter().any(|r| r.protect_inline_code),
+            ),
+            (
+                &self.builtin.ticker,
+                rules.iter().any(|r| r.protect_tickers),
+            ),
+            (
+                &self.builtin.hashtag,
+                rules.iter().any(|r| r.protect_hashtags),
+            ),
+            (
+                &self.builtin.version,
+                rules.iter().any(|r| r.protect_versions),
+            ),
+        ];
+        for (pattern, enabled) in builtin_patterns {
+            if enabled {
+                patterns.push((pattern.clone(), None));
+            }
+        }
+
+        // 長い用語から先に置き換えて、短い用語が長い用語の一部にマッチしないようにする
+        let mut terms: Vec<&CompiledTerm> = rules
+            .iter()
+            .flat_map(|rule| rule.terms.iter())
+            .filter(|term| match term.to.as_deref() {
+                Some(term_to) => language_matches(term_to, to),
+                None => true,
+            })
+            .collect();
+        terms.sort_by_key(|term| std::cmp::Reverse(term.chars));
+        for term in terms {
+            patterns.push((term.regex.clone(), term.translation.clone()));
+        }
+
+        TermProtector {
+            patterns,
+            placeholder: self.builtin.placeholder.clone(),
+        }
+    }
+}
+
+// "ja" と "ja-JP" のどちらで指定されても同じ言語として扱う
+pub(crate) fn language_matches(entry_to: &str, to: &str) -> bool {
+    let primary = |tag: &str| tag.split(['-', '_']).next().unwrap_or(tag).to_lowercase();
+    entry_to.eq_ignore_ascii_case(to)
+        || (!entry_to.contains('-') && primary(entry_to) == primary(to))
+}
+
+// 英数字で始まる・終わる用語は単語の途中にマッチしないようにする
+fn term_regex(term: &str) -> Option<Regex> {
+    if term.trim().is_empty() {
+        return None;
+    }
+    let starts_with_word = term
+        .chars()
+        .next()
+        .is_some_and(|ch| ch.is_ascii_alphanumeric());
+    let ends_with_word = term
+        .chars()
+        .last()
+        .is_some_and(|ch| ch.is_ascii_alphanumeric());
+    let pattern = format!(
+        "(?i){}{}{}",
+        if starts_with_word { r"\b" } else { "" },
+        regex::escape(term),
+        if ends_with_word { r"\b" } else { "" }
+    );
+    Regex::new(&pattern).ok()
+}
+
+#[derive(Clone, Debug)]
+pub struct MaskedText {
+    pub text: String,
+    // プレースホルダーの番号ごとに、翻訳後に戻す文字列
+    replacements: Vec<String>,
+}
+
+pub struct TermProtector {
+    // (パターン, 強制する訳) 訳がNoneの場合は元の文字列に戻す
+    patterns: Vec<(Regex, Option<String>)>,
+    placeholder: Regex,
+}
+
+impl TermProtector {
+    // 選ばれた用語とその訳を並べたもの (翻訳結果が変わるのでキャッシュのキーに含める、用語がない場合は空)
+    pub fn fingerprint(&self) -> String {
+        self.patterns
+            .iter()
+            .map(|(pattern, translation)| format!("{}={:?}", pattern.as_str(), translation))
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    pub fn mask(&self, text: &str) -> MaskedText {
+        let mut replacements: Vec<String> = Vec::new();
+        // 元のテキストに [[0]] のような文字列が含まれている場合は、それもプレースホルダーにして元に戻す
+        //   そのままにすると、翻訳後に別の用語に置き換えてしまう
+        let mut masked = self
+            .placeholder
+            .replace_all(text, |caps: &Captures| {
+                replacements.push(caps[0].to_string());
+                format!("[[{}]]", replacements.len() - 1)
+            })
+            .to_string();
+
+        for (pattern, translation) in self.patterns.iter() {
+            masked = pattern
+                .replace_all(&masked, |caps: &Captures| {
+                    let matched = caps.get(0).unwrap().as_str();
+                    // 既に置き換えたプレースホルダーの中身にはマッチさせない
+                    if self.placeholder.is_match(matched) {
+                        return matched.to_string();
+                    }
+                    replacements.push(translation.clone().unwrap_or(matched.to_string()));
+                    format!("[[{}]]", replacements.len() - 1)
+                })
+                .to_string();
+        }
+
+        MaskedText {
+            text: masked,
+            replacements,
+        }
+    }
+
+    pub fn unmask(&self, masked: &MaskedText, translated: &str) -> String {
+        self.placeholder
+            .replace_all(translated, |caps: &Captures| {
+                let index: usize = caps[1].parse().unwrap_or(usize::MAX);
+                match masked.replacements.get(index) {
+                    Some(replacement) => replacement.clone(),
+                    None => caps[0].to_string(),
+                }
+            })
+            .to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(json: &str) -> TermProtectionConfig {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn protected_terms_round_trip() {
+        let protector = config(r#"{"protected_terms": ["Rust"], "protect_versions": true}"#)
+            .protector_for("https://example.com/feed", "ja-JP");
+        let masked = protector.mask("Rust 1.80 released");
+        assert_eq!(masked.text, "[[1]] [[0]] released");
+        assert_eq!(
+            protector.unmask(&masked, "［［1］］ [[0]] がリリース"),
+            "Rust 1.80 がリリース"
+        );
+    }
+
+    #[test]
+    fn existing_placeholders_in_source_are_kept() {
+        let protector = config(r#"{"protected_terms": ["Rust"]}"#)
+            .protector_for("https://example.com/feed", "ja-JP");
+        let masked = protector.mask("Rust [[0]] syntax");
+        assert_eq!(masked.text, "[[1]] [[0]] syntax");
+        assert_eq!(
+            protector.unmask(&masked, "[[1]] の [[0]] 構文"),
+            "Rust の [[0]] 構文"
+        );
+    }
+
+    #[test]
+    fn feed_rules_are_merged_with_global_rules() {
+        let config = config(
+            r#"{
+                "glossary": [{"term": "crate", "translation": "クレート", "to": "ja"}],
+                "feeds": {
+                    "https://example.com/": {"protected_terms": ["Cargo"]}
+                }
+            }"#,
+        );
+        let protector = config.protector_for("https://example.com/feed", "ja-JP");
+        let masked = protector.mask("Cargo builds a crate");
+        assert_eq!(
+            protector.unmask(&masked, &masked.text),
+            "Cargo builds a クレート"
+        );
+        assert_eq!(masked.text, "[[1]] builds a [[0]]");
+
+        // 他のフィードや他の言語には適用しない
+        let protector = config.protector_for("https://other.example.com/feed", "de-DE");
+        assert_eq!(
+            protector.mask("Cargo builds a crate").text,
+            "Cargo builds a crate"
+        );
+    }
+
+    #[test]
+    fn fingerprint_changes_with_the_selected_terms() {
+        let config = config(
+            r#"{
+                "feeds": {
+                    "https://example.com/": {"protected_terms": ["Cargo"]}
+                }
+            }"#,
+        );
+        let other_feed = config.protector_for("https://other.example.com/feed", "ja-JP");
+        assert_eq!(other_feed.fingerprint(), "");
+
+        let feed = config.protector_for("https://example.com/feed", "ja-JP");
+        assert_ne!(feed.fingerprint(), "");
+        assert_eq!(
+            feed.fingerprint(),
+            config
+                .protector_for("https://example.com/other", "ja")
+                .fingerprint()
+        );
+    }
+}
@@ -0,0 +1,158 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+// テストで翻訳APIや認証サーバーの代わりにするHTTPサーバー
+//   パスごとに決めたレスポンスを順番に返し (最後のものは繰り返す)、受け取ったリクエストを記録する
+
+#[derive(Clone, Debug)]
+pub struct ReceivedRequest {
+    pub method: String,
+    // クエリを含むパス
+    pub target: String,
+    // ヘッダーの名前は小文字にする
+    pub headers: HashMap<String, String>,
+    pub body: String,
+}
+
+impl ReceivedRequest {
+    pub fn path(&self) -> &str {
+        self.target.split('?').next().unwrap_or("")
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .get(&name.to_ascii_lowercase())
+            .map(|value| value.as_str())
+    }
+}
+
+type Routes = HashMap<String, (Vec<(u16, String)>, usize)>;
+
+pub struct TestServer {
+    // 例: http://127.0.0.1:12345/
+    pub url: String,
+    requests: Arc<Mutex<Vec<ReceivedRequest>>>,
+}
+
+impl TestServer {
+    // routes: パス -> 順番に返すステータスとボディ (登録していないパスには404を返す)
+    pub async fn start(routes: Vec<(&str, Vec<(u16, &str)>)>) -> TestServer {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let routes: Routes = routes
+            .into_iter()
+            .map(|(path, responses)| {
+                let responses = responses
+                    .into_iter()
+                    .map(|(status, body)| (status, body.to_string()))
+                    .collect();
+                (path.to_string(), (responses, 0))
+            })
+            .collect();
+        let routes = Arc::new(Mutex::new(routes));
+        let requests = Arc::new(Mutex::new(Vec::new()));
+
+        let received = requests.clone();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let routes = routes.clone();
+                let received = received.clone();
+                tokio::spawn(async move { handle(stream, routes, received).await });
+            }
+        });
+
+        TestServer { url, requests }
+    }
+
+    // パスが一致するリクエスト (クエリは含めずに比べる)
+    pub fn requests(&self, path: &str) -> Vec<ReceivedRequest> {
+        self.requests
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|request| request.path() == path)
+            .cloned()
+            .collect()
+    }
+}
+
+async fn handle(
+    mut stream: TcpStream,
+    routes: Arc<Mutex<Routes>>,
+    received: Arc<Mutex<Vec<ReceivedRequest>>>,
+) {
+    let request = match read_request(&mut stream).await {
+        Some(request) => request,
+        None => return,
+    };
+
+    let (status, body) = {
+        let mut routes = routes.lock().unwrap();
+        match routes.get_mut(request.path()) {
+            Some((responses, served)) => {
+                let index = (*served).min(responses.len() - 1);
+                *served += 1;
+                responses[index].clone()
+            }
+            None => (404, "not found".to_string()),
+        }
+    };
+    received.lock().unwrap().push(request);
+
+    let response = format!(
+        "HTTP/1.1 {} Test\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    let _ = stream.write_all(response.as_bytes()).await;
+    let _ = stream.shutdown().await;
+}
+
+async fn read_request(stream: &mut TcpStream) -> Option<ReceivedRequest> {
+    let mut data: Vec<u8> = Vec::new();
+    let mut buffer = [0u8; 8192];
+    let header_end = loop {
+        if let Some(index) = data.windows(4).position(|window| window == b"\r\n\r\n") {
+            break index;
+        }
+        let read = stream.read(&mut buffer).await.ok()?;
+        if read == 0 {
+            return None;
+        }
+        data.extend_from_slice(&buffer[..read]);
+    };
+
+    let head = String::from_utf8_lossy(&data[..header_end]).to_string();
+    let mut lines = head.split("\r\n");
+    let mut request_line = lines.next()?.split(' ');
+    let method = request_line.next()?.to_string();
+    let target = request_line.next()?.to_string();
+    let headers: HashMap<String, String> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_string()))
+        .collect();
+
+    let content_length: usize = headers
+        .get("content-length")
+        .and_then(|length| length.parse().ok())
+        .unwrap_or(0);
+    let mut body = data[header_end + 4..].to_vec();
+    while body.len() < content_length {
+        let read = stream.read(&mut buffer).await.ok()?;
+        if read == 0 {
+            break;
+        }
+        body.extend_from_slice(&buffer[..read]);
+    }
+
+    Some(ReceivedRequest {
+        method,
+        target,
+        headers,
+        body: String::from_utf8_lossy(&body).to_string(),
+    })
+}
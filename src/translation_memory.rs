@@ -0,0 +1,473 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+use crate::cache_provider::provider::CacheProvider;
+
+// 保存する翻訳の組
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct MemoryRecord {
+    source: String,
+    translated: String,
+}
+
+struct MemoryEntry {
+    record: MemoryRecord,
+    trigrams: HashSet<String>,
+    numbers: Vec<String>,
+}
+
+// 翻訳メモリから見つかった翻訳
+#[derive(Clone, Debug)]
+pub struct MemoryMatch {
+    // 翻訳に使った元のテキスト
+    pub source: String,
+    // 数字を置き換えた後の翻訳
+    pub translated: String,
+    // 正規化したテキストの類似度 (0.0 - 1.0)
+    pub score: f64,
+}
+
+pub struct TranslationMemoryOptions {
+    // 保存先 (Noneの場合はプロセス内でのみ覚えておく)
+    pub cache_provider: Option<Box<dyn CacheProvider>>,
+    // この値以上の類似度のものだけを使う
+    pub threshold: f64,
+    // バックエンドと翻訳先の言語ごとに覚えておく件数
+    pub max_entries: usize,
+}
+
+#[derive(Default)]
+struct MemoryState {
+    // 保存先のキー -> 覚えている翻訳
+    entries: HashMap<String, Vec<MemoryEntry>>,
+    // 保存先のキー -> まだ保存先に書いていない翻訳
+    pending: HashMap<String, Vec<MemoryRecord>>,
+}
+
+// 日付や番号だけが違うタイトルの翻訳を使い回す
+//   バックエンドと翻訳先の言語ごとに "memory:<バックエンド>:<言語>" のキーでキャッシュの保存先に保存する
+//   保存先との読み書きの間はロックを持たないので、他のリクエストの検索を待たせない
+#[derive(Clone)]
+pub struct TranslationMemory {
+    state: Arc<Mutex<MemoryState>>,
+    // 保存は1つずつ行い、保存している間に記録されたものは次の保存でまとめて書く
+    flushing: Arc<tokio::sync::Mutex<()>>,
+    cache_provider: Option<Box<dyn CacheProvider>>,
+    threshold: f64,
+    max_entries: usize,
+    number_pattern: Regex,
+}
+
+impl TranslationMemory {
+    pub fn new(options: TranslationMemoryOptions) -> Self {
+        TranslationMemory {
+            state: Arc::new(Mutex::new(MemoryState::default())),
+            flushing: Arc::new(tokio::sync::Mutex::new(())),
+            cache_provider: options.cache_provider,
+            threshold: options.threshold,
+            max_entries: options.max_entries,
+            number_pattern: Regex::new(r"\d+(?:[.,]\d+)*").unwrap(),
+        }
+    }
+
+    // 大文字小文字や空白の違いを無視し、数字は同じ記号にまとめる
+    fn normalize(&self, text: &str) -> String {
+        let text = text.to_lowercase();
+        let text = self.number_pattern.replace_all(&text, "0");
+        text.split_whitespace().collect::<Vec<&str>>().join(" ")
+    }
+
+    fn numbers(&self, text: &str) -> Vec<String> {
+        self.number_pattern
+            .find_iter(text)
+            .map(|number| number.as_str().to_string())
+            .collect()
+    }
+
+    fn trigrams(normalized: &str) -> HashSet<String> {
+        let chars: Vec<char> = format!("  {} ", normalized).chars().collect();
+        chars
+            .windows(3)
+            .map(|window| window.iter().collect::<String>())
+            .collect()
+    }
+
+    // 文字のtrigramのDice係数
+    fn similarity(a: &HashSet<String>, b: &HashSet<String>) -> f64 {
+        if a.is_empty() && b.is_empty() {
+            return 1.0;
+        }
+        let common = a.intersection(b).count();
+        (2 * common) as f64 / (a.len() + b.len()) as f64
+    }
+
+    fn to_entry(&self, record: MemoryRecord) -> MemoryEntry {
+        MemoryEntry {
+            trigrams: TranslationMemory::trigrams(&self.normalize(&record.source)),
+            numbers: self.numbers(&record.source),
+            record,
+        }
+    }
+
+    // 元のテキストの数字を新しいテキストの数字に置き換える
+    //   数字の個数が違う場合や、翻訳の中に元の数字が見つからない場合は使えないのでNoneを返す
+    fn adapt_numbers(translated: &str, from: &[String], to: &[String]) -> Option<String> {
+        if from == to {
+            return Some(translated.to_string());
+        }
+        if from.len() != to.len() {
+            return None;
+        }
+
+        let mut adapted = String::with_capacity(translated.len());
+        let mut rest = translated;
+        for (from, to) in from.iter().zip(to.iter()) {
+            let index = rest.find(from.as_str())?;
+            adapted.push_str(&rest[..index]);
+            adapted.push_str(to);
+            rest = &rest[index + from.len()..];
+        }
+        adapted.push_str(rest);
+
+        Some(adapted)
+    }
+
+    // 同じテキストは newer のものを使い、古いものから消して max_entries 件までにする
+    fn merge(&self, base: Vec<MemoryEntry>, newer: Vec<MemoryEntry>) -> Vec<MemoryEntry> {
+        let mut merged = base;
+        for entry in newer {
+            merged.retain(|merged| merged.record.source != entry.record.source);
+            merged.push(entry);
+        }
+        if merged.len() > self.max_entries {
+            let overflow = merged.len() - self.max_entries;
+            merged.drain(..overflow);
+        }
+        merged
+    }
+
+    fn storage_key(backend: &str, to: &str) -> String {
+        format!("memory:{}:{}", backend, to)
+    }
+
+    async fn load(&self, key: &str) -> Vec<MemoryEntry> {
+        let cache_provider = match self.cache_provider.clone() {
+            Some(cache_provider) => cache_provider,
+            None => return Vec::new(),
+        };
+
+        let stored = match cache_provider.get(key.to_string()).await {
+            Ok(stored) => stored,
+            Err(e) => {
+                println!("Error (failed to load translation memory): {}", e);
+                None
+            }
+        };
+        let records: Vec<MemoryRecord> = match stored {
+            Some(stored) => serde_json::from_str(&stored).unwrap_or_else(|e| {
+                println!("Error (failed to parse translation memory): {}", e);
+                Vec::new()
+            }),
+            None => Vec::new(),
+        };
+
+        records
+            .into_iter()
+            .map(|record| self.to_entry(record))
+            .collect()
+    }
+
+    async fn save(&self, key: &str, entries: &[MemoryEntry]) {
+        let cache_provider = match self.cache_provider.clone() {
+            Some(cache_provider) => cache_provider,
+            None => return,
+        };
+        let records: Vec<&MemoryRecord> = entries.iter().map(|entry| &entry.record).collect();
+        let value = serde_json::to_string(&records).unwrap();
+        let set_result = cache_provider.set(key.to_string(), value).await;
+        if let Err(e) = set_result {
+            println!("Error (failed to save translation memory): {}", e);
+        }
+    }
+
+    // 同じバックエンドで翻訳したものから、最も似ているテキストの翻訳を探す
+    pub async fn lookup(&self, backend: &str, to: &str, text: &str) -> Option<MemoryMatch> {
+        let key = TranslationMemory::storage_key(backend, to);
+        let is_loaded = self.state.lock().unwrap().entries.contains_key(&key);
+        if !is_loaded {
+            let loaded = self.load(&key).await;
+            // 読み込んでいる間に記録されたものがあれば、そちらを新しいものとして残す
+            let mut state = self.state.lock().unwrap();
+            let recorded = state.entries.remove(&key).unwrap_or_default();
+            let merged = self.merge(loaded, recorded);
+            state.entries.insert(key.clone(), merged);
+        }
+
+        let trigrams = TranslationMemory::trigrams(&self.normalize(text));
+        let numbers = self.numbers(text);
+
+        let state = self.state.lock().unwrap();
+        let mut best: Option<MemoryMatch> = None;
+        for entry in state.entries.get(&key).unwrap().iter() {
+            // trigramの数の差から上限を求めて、届かないものは比較しない
+            let upper_bound = (2 * trigrams.len().min(entry.trigrams.len())) as f64
+                / (trigrams.len() + entry.trigrams.len()).max(1) as f64;
+            if upper_bound < self.threshold {
+                continue;
+            }
+            let score = TranslationMemory::similarity(&trigrams, &entry.trigrams);
+            if score < self.threshold || best.as_ref().is_some_and(|best| best.score >= score) {
+                continue;
+            }
+            let translated = match TranslationMemory::adapt_numbers(
+                &entry.record.translated,
+                &entry.numbers,
+                &numbers,
+            ) {
+                Some(translated) => translated,
+                None => continue,
+            };
+            best = Some(MemoryMatch {
+                source: entry.record.source.clone(),
+                translated,
+                score,
+            });
+        }
+
+        best
+    }
+
+    // 翻訳APIで翻訳したものを覚えておく (古いものから消す)
+    //   保存先への書き込みはリクエストを待たせないように別のタスクで行う
+    pub fn record(&self, backend: &str, to: &str, translations: Vec<(String, String)>) {
+        if translations.is_empty() {
+            return;
+        }
+        let key = TranslationMemory::storage_key(backend, to);
+        let records: Vec<MemoryRecord> = translations
+            .into_iter()
+            .map(|(source, translated)| MemoryRecord { source, translated })
+            .collect();
+
+        {
+            let mut state = self.state.lock().unwrap();
+            let entries = records
+                .iter()
+                .map(|record| self.to_entry(record.clone()))
+                .collect();
+            let current = state.entries.remove(&key).unwrap_or_default();
+            let merged = self.merge(current, entries);
+            state.entries.insert(key.clone(), merged);
+            if self.cache_provider.is_none() {
+                return;
+            }
+            state.pending.entry(key).or_default().extend(records);
+        }
+
+        let memory = self.clone();
+        tokio::spawn(async move { memory.flush().await });
+    }
+
+    // まだ保存していない翻訳を、保存されている最新のものに足してから保存する
+    //   他のプロセスのものも残すように足しているが、別のプロセスと同時に保存した場合は
+    //   後から保存した方だけが残り、もう一方が記録したものは失われることがある
+    pub async fn flush(&self) {
+        if self.cache_provider.is_none() {
+            return;
+        }
+        let _flushing = self.flushing.lock().await;
+        let pending = std::mem::take(&mut self.state.lock().unwrap().pending);
+
+        for (key, records) in pending {
+            let entries = records
+                .into_iter()
+                .map(|record| self.to_entry(record))
+                .collect();
+            let merged = self.merge(self.load(&key).await, entries);
+            self.save(&key, &merged).await;
+
+            // 保存している間に記録されたものは次の保存で書くので、メモリにはそれも足しておく
+            let mut state = self.state.lock().unwrap();
+            let newer: Vec<MemoryEntry> = state
+                .pending
+                .get(&key)
+                .map(|records| {
+                    records
+                        .iter()
+                        .map(|record| self.to_entry(record.clone()))
+                        .collect()
+                })
+                .unwrap_or_default();
+            let merged = self.merge(merged, newer);
+            state.entries.insert(key, merged);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache_provider::memory::MemoryCacheProvider;
+
+    fn memory(provider: Option<&MemoryCacheProvider>, threshold: f64) -> TranslationMemory {
+        TranslationMemory::new(TranslationMemoryOptions {
+            cache_provider: provider.map(|provider| provider.clone_box()),
+            threshold,
+            max_entries: 100,
+        })
+    }
+
+    fn record(source: &str, translated: &str) -> Vec<(String, String)> {
+        vec![(source.to_string(), translated.to_string())]
+    }
+
+    #[test]
+    fn similarity_of_normalized_trigrams() {
+        let memory = memory(None, 0.8);
+        let trigrams = |text: &str| TranslationMemory::trigrams(&memory.normalize(text));
+
+        // 大文字小文字、空白、数字の違いは無視する
+        assert_eq!(
+            TranslationMemory::similarity(
+                &trigrams("Episode 12  Released"),
+                &trigrams("episode 345 released")
+            ),
+            1.0
+        );
+        assert_eq!(
+            TranslationMemory::similarity(&trigrams("abc"), &trigrams("xyz")),
+            0.0
+        );
+        // "  ab", " ab", "ab " と "  ac", " ac", "ac " で共通するのは "  a" だけ
+        assert_eq!(
+            TranslationMemory::similarity(&trigrams("ab"), &trigrams("ac")),
+            2.0 / 6.0
+        );
+    }
+
+    #[test]
+    fn numbers_are_substituted_in_order() {
+        let numbers =
+            |texts: &[&str]| -> Vec<String> { texts.iter().map(|text| text.to_string()).collect() };
+        assert_eq!(
+            TranslationMemory::adapt_numbers(
+                "第3巻 第12話",
+                &numbers(&["3", "12"]),
+                &numbers(&["4", "1"])
+            ),
+            Some("第4巻 第1話".to_string())
+        );
+        // 数字の個数が違うものや、翻訳に元の数字がないものは使わない
+        assert_eq!(
+            TranslationMemory::adapt_numbers("第12話", &numbers(&["12"]), &numbers(&["13", "2"])),
+            None
+        );
+        assert_eq!(
+            TranslationMemory::adapt_numbers("第十二話", &numbers(&["12"]), &numbers(&["13"])),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn lookup_uses_the_threshold_and_adapts_numbers() {
+        let memory = memory(None, 0.8);
+        memory.record(
+            "google:v2",
+            "ja",
+            record("Episode 12 released", "第12話が公開"),
+        );
+
+        let found = memory
+            .lookup("google:v2", "ja", "Episode 13 released")
+            .await
+            .unwrap();
+        assert_eq!(found.translated, "第13話が公開");
+        assert_eq!(found.source, "Episode 12 released");
+        assert_eq!(found.score, 1.0);
+
+        // 閾値に届かないもの、数字の個数が違うもの、別のバックエンドや言語では見つからない
+        assert!(memory
+            .lookup("google:v2", "ja", "Season 2 trailer released")
+            .await
+            .is_none());
+        assert!(memory
+            .lookup("google:v2", "ja", "Episode 13 released 2")
+            .await
+            .is_none());
+        assert!(memory
+            .lookup("deepl:v2", "ja", "Episode 13 released")
+            .await
+            .is_none());
+        assert!(memory
+            .lookup("google:v2", "ko", "Episode 13 released")
+            .await
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn records_from_every_process_are_kept() {
+        let provider = MemoryCacheProvider::default();
+        let first = memory(Some(&provider), 0.8);
+        let second = memory(Some(&provider), 0.8);
+
+        // 両方のプロセスが読み込んだ後に記録しても、上書きせずに足す
+        assert!(first.lookup("google:v2", "ja", "Hello").await.is_none());
+        assert!(second.lookup("google:v2", "ja", "Hello").await.is_none());
+        first.record(
+            "google:v2",
+            "ja",
+            record("Episode 1 released", "第1話が公開"),
+        );
+        first.flush().await;
+        second.record("google:v2", "ja", record("Chapter 1 is out", "第1章が出た"));
+        second.flush().await;
+
+        let third = memory(Some(&provider), 0.8);
+        assert!(third
+            .lookup("google:v2", "ja", "Episode 2 released")
+            .await
+            .is_some());
+        assert!(third
+            .lookup("google:v2", "ja", "Chapter 2 is out")
+            .await
+            .is_some());
+        assert!(provider.value("memory:google:v2:ja").is_some());
+    }
+
+    #[tokio::test]
+    async fn records_are_written_together_and_found_before_saving() {
+        let provider = MemoryCacheProvider::default();
+        let memory = memory(Some(&provider), 0.8);
+
+        // 保存を待たずにメモリから見つかる
+        for index in 1..=3 {
+            memory.record(
+                "google:v2",
+                "ja",
+                record(
+                    &format!("Part {} of the guide", index),
+                    &format!("ガイドの第{}部", index),
+                ),
+            );
+        }
+        assert!(memory
+            .lookup("google:v2", "ja", "Part 9 of the guide")
+            .await
+            .is_some());
+
+        memory.flush().await;
+        let stored: Vec<MemoryRecord> =
+            serde_json::from_str(&provider.value("memory:google:v2:ja").unwrap()).unwrap();
+        let sources: Vec<String> = stored.into_iter().map(|record| record.source).collect();
+        assert_eq!(
+            sources,
+            vec![
+                "Part 1 of the guide",
+                "Part 2 of the guide",
+                "Part 3 of the guide"
+            ]
+        );
+    }
+}